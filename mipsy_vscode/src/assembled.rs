@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+
+use mipsy_lib::{Binary, DATA_BOT, KDATA_BOT, KTEXT_BOT};
+use mipsy_parser::{MpConstValue, MpDirective, MpItem};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    decode::word_at,
+    expr::{evaluate_const, parsed_constant_values},
+    parsed::ParsedFile,
+};
+
+/// How many bytes of a data directive to send to the editor; `.space 4096`
+/// doesn't need to be shown in full.
+const MAX_BYTES: usize = 16;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SegmentKind {
+    Text,
+    Data,
+    KText,
+    KData,
+}
+
+impl SegmentKind {
+    fn of(address: u32) -> Self {
+        if address >= KDATA_BOT {
+            SegmentKind::KData
+        } else if address >= KTEXT_BOT {
+            SegmentKind::KText
+        } else if address >= DATA_BOT {
+            SegmentKind::Data
+        } else {
+            SegmentKind::Text
+        }
+    }
+}
+
+/// What a single source line assembled to.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AssembledLine {
+    /// 0-indexed
+    pub line: u32,
+    pub segment: SegmentKind,
+    pub address: u32,
+    /// in bytes
+    pub size: u32,
+    /// the instruction words, for a line in a text segment (several, for
+    /// a pseudo-instruction)
+    pub words: Vec<u32>,
+    /// the first few bytes, for a line in a data segment, with `None` for
+    /// uninitialised bytes (e.g. from `.space`)
+    pub bytes: Vec<Option<u8>>,
+}
+
+/// The number of values in a `.word` etc directive, counting each value
+/// written as `value:count` `count` times.
+fn value_count<T>(
+    values: &[(T, Option<MpConstValue>)],
+    constants: &HashMap<String, i64>,
+) -> Option<u32> {
+    let lookup = |name: &str| constants.get(name).copied();
+
+    values
+        .iter()
+        .map(|(_, repeat)| match repeat {
+            Some(count) => u32::try_from(evaluate_const(count, &lookup)?).ok(),
+            None => Some(1),
+        })
+        .sum()
+}
+
+/// The alignment and size of what a directive assembles to, if it can be
+/// worked out.
+fn data_layout(directive: &MpDirective, constants: &HashMap<String, i64>) -> Option<(u32, u32)> {
+    let lookup = |name: &str| constants.get(name).copied();
+
+    match directive {
+        MpDirective::Byte(values) => Some((1, value_count(values, constants)?)),
+        MpDirective::Half(values) => Some((2, 2 * value_count(values, constants)?)),
+        MpDirective::Word(values) => Some((4, 4 * value_count(values, constants)?)),
+        MpDirective::Float(values) => Some((4, 4 * value_count(values, constants)?)),
+        MpDirective::Double(values) => Some((8, 8 * value_count(values, constants)?)),
+        MpDirective::Ascii(string) => Some((1, string.len() as u32)),
+        MpDirective::Asciiz(string) => Some((1, string.len() as u32 + 1)),
+        MpDirective::Space(size) => Some((1, u32::try_from(evaluate_const(size, &lookup)?).ok()?)),
+        MpDirective::Align(power) => Some((
+            1u32.checked_shl(u32::try_from(evaluate_const(power, &lookup)?).ok()?)?,
+            0,
+        )),
+        MpDirective::Text
+        | MpDirective::Data
+        | MpDirective::KText
+        | MpDirective::KData
+        | MpDirective::Globl(_) => Some((1, 0)),
+    }
+}
+
+fn segment_of(directive: &MpDirective) -> Option<SegmentKind> {
+    match directive {
+        MpDirective::Text => Some(SegmentKind::Text),
+        MpDirective::Data => Some(SegmentKind::Data),
+        MpDirective::KText => Some(SegmentKind::KText),
+        MpDirective::KData => Some(SegmentKind::KData),
+        _ => None,
+    }
+}
+
+/// Works out which addresses each line of the file tagged `file_tag`
+/// assembled to, and what's there.
+///
+/// Instructions are found through `Binary::line_numbers`, but mipsy doesn't
+/// record where data came from, so data directives are laid out again here
+/// from mipsy's parse of each line, starting from the bottom of each data
+/// segment and picking up the exact address again at each label. Every file
+/// is walked through (in the order they were compiled) as the data segments
+/// carry on from one file to the next.
+pub fn assembled_lines(
+    binary: &Binary,
+    sources: &[(&str, &str)],
+    file_tag: &str,
+    tab_size: u32,
+) -> Vec<AssembledLine> {
+    let files: Vec<(&str, ParsedFile)> = sources
+        .iter()
+        .map(|&(tag, source)| (tag, ParsedFile::parse(tag, source, tab_size)))
+        .collect();
+    let constants = parsed_constant_values(files.iter().map(|(_, file)| file));
+
+    let mut text_addresses: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (&address, (tag, line_num)) in &binary.line_numbers {
+        if &**tag == file_tag {
+            text_addresses
+                .entry(line_num.saturating_sub(1))
+                .or_default()
+                .push(address);
+        }
+    }
+
+    let mut assembled: Vec<AssembledLine> = text_addresses
+        .into_iter()
+        .map(|(line, mut addresses)| {
+            addresses.sort_unstable();
+
+            AssembledLine {
+                line,
+                segment: SegmentKind::of(addresses[0]),
+                address: addresses[0],
+                size: 4 * addresses.len() as u32,
+                words: addresses
+                    .iter()
+                    .filter_map(|&address| word_at(binary, address))
+                    .collect(),
+                bytes: vec![],
+            }
+        })
+        .collect();
+
+    let (mut data, mut kdata) = (Some(DATA_BOT), Some(KDATA_BOT));
+
+    for (tag, file) in &files {
+        let mut segment = SegmentKind::Text;
+
+        for (line_num, line) in file.lines.iter().enumerate() {
+            for item in line.items.iter().flatten() {
+                let (cursor, bytes, base) = match segment {
+                    SegmentKind::Data => (&mut data, &binary.data, DATA_BOT),
+                    SegmentKind::KData => (&mut kdata, &binary.kdata, KDATA_BOT),
+                    SegmentKind::Text | SegmentKind::KText => (&mut None, &binary.data, DATA_BOT),
+                };
+
+                let directive = match item {
+                    MpItem::Label(label) => {
+                        if let Some(&address) = binary.labels.get(label) {
+                            if SegmentKind::of(address) == segment {
+                                *cursor = Some(address);
+                            }
+                        }
+                        continue;
+                    }
+                    MpItem::Directive(directive) => directive,
+                    MpItem::Constant(_) | MpItem::Instruction(_) => continue,
+                };
+
+                if let Some(new_segment) = segment_of(directive) {
+                    segment = new_segment;
+                    continue;
+                }
+                if !matches!(segment, SegmentKind::Data | SegmentKind::KData) {
+                    continue;
+                }
+
+                let (address, size) = match (*cursor, data_layout(directive, &constants)) {
+                    (Some(address), Some((align, size))) => (address.next_multiple_of(align), size),
+                    _ => {
+                        // lost track, until the next label
+                        *cursor = None;
+                        continue;
+                    }
+                };
+                *cursor = Some(address + size);
+
+                if size == 0 || tag != &file_tag {
+                    continue;
+                }
+
+                assembled.push(AssembledLine {
+                    line: line_num as u32,
+                    segment,
+                    address,
+                    size,
+                    words: vec![],
+                    bytes: bytes
+                        .iter()
+                        .skip((address - base) as usize)
+                        .take((size as usize).min(MAX_BYTES))
+                        .map(|byte| byte.as_option().copied())
+                        .collect(),
+                });
+            }
+        }
+    }
+
+    assembled.sort_by_key(|line| line.line);
+    assembled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::compile;
+
+    fn lines_of(source: &str) -> Vec<AssembledLine> {
+        assembled_lines(&compile(source), &[("test.s", source)], "test.s", 8)
+    }
+
+    #[test]
+    fn reports_every_word_of_a_pseudo_instruction() {
+        let lines = lines_of("main:\n\tli\t$t0, 0x12345678\n\tjr\t$ra\n");
+
+        let li = &lines[0];
+        assert_eq!((li.line, li.segment), (1, SegmentKind::Text));
+        assert_eq!((li.address, li.size), (0x00400000, 8));
+        assert_eq!(li.words.len(), 2);
+        assert_eq!(li.words[0] >> 26, 0x0F, "lui first");
+        assert_eq!(li.words[1] >> 26, 0x0D, "then ori");
+
+        assert_eq!((lines[1].line, lines[1].address), (2, 0x00400008));
+    }
+
+    #[test]
+    fn reports_the_bytes_of_data_lines() {
+        let source = "\t.data\nc:\t.byte\t7\nw:\t.word\t0x01020304, 5\n\
+                      s:\t.asciiz\t\"hi\"\n\t.space\t2\n\n\t.text\nmain:\tjr\t$ra\n";
+        let lines = lines_of(source);
+
+        let data: Vec<_> = lines
+            .iter()
+            .filter(|line| line.segment == SegmentKind::Data)
+            .map(|line| (line.line, line.address, line.size))
+            .collect();
+        assert_eq!(
+            data,
+            [
+                (1, DATA_BOT, 1),
+                (2, DATA_BOT + 4, 8),
+                (3, DATA_BOT + 12, 3),
+                (4, DATA_BOT + 15, 2),
+            ]
+        );
+
+        assert_eq!(lines[0].bytes, [Some(7)]);
+        assert_eq!(&lines[1].bytes[..4], [4, 3, 2, 1].map(Some));
+        assert_eq!(lines[2].bytes, [Some(b'h'), Some(b'i'), Some(0)]);
+        assert_eq!(lines[3].bytes, [None, None]);
+        assert!(lines
+            .iter()
+            .all(|line| line.segment != SegmentKind::Data || line.words.is_empty()));
+    }
+
+    #[test]
+    fn carries_data_on_from_earlier_files() {
+        let first = "\t.data\nx:\t.word\t1, 2\n";
+        let second = "\t.data\ny:\t.byte\t1\n\t.align\t2\nz:\t.half\t3\n\t.text\nmain:\tjr\t$ra\n";
+        let sources = [("first.s", first), ("second.s", second)];
+        let binary = crate::testing::compile_files(&sources);
+
+        let lines: Vec<_> = assembled_lines(&binary, &sources, "second.s", 8)
+            .into_iter()
+            .filter(|line| line.segment == SegmentKind::Data)
+            .map(|line| (line.line, line.address, line.size))
+            .collect();
+        assert_eq!(lines, [(1, DATA_BOT + 8, 1), (3, DATA_BOT + 12, 2)]);
+    }
+}
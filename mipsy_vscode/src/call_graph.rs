@@ -0,0 +1,291 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use mipsy_lib::Binary;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    calling_convention::{find_functions, function_body},
+    decode::{text_instructions, Decoded, Flow},
+    lexer::utf16_col,
+    source::SourceLine,
+    symbols::Symbol,
+};
+
+/// A function in the call graph.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CallGraphFunction {
+    pub name: String,
+    pub address: u32,
+    /// where its label is defined: 0-indexed, with UTF-16 columns
+    pub file_tag: Option<String>,
+    pub line: u32,
+    pub col: u32,
+    pub col_end: u32,
+    /// whether it can end up calling itself, directly or not
+    pub is_recursive: bool,
+    /// whether anything calls it (`main` is always considered called)
+    pub is_called: bool,
+}
+
+/// A `jal` (or `jalr`, etc) in one function calling another.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Call {
+    pub caller: String,
+    /// `None` for a `jalr`, which could call anything
+    pub callee: Option<String>,
+    pub address: u32,
+    /// the instruction's position in the source, like `CallGraphFunction`
+    pub file_tag: Option<String>,
+    pub line: u32,
+    pub col: u32,
+    pub col_end: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CallGraph {
+    pub functions: Vec<CallGraphFunction>,
+    pub calls: Vec<Call>,
+}
+
+/// Where the instruction at `address` came from, as (file tag, 0-indexed
+/// line, start column, end column), covering the whole instruction.
+fn source_span(
+    binary: &Binary,
+    sources: &[(&str, &str)],
+    address: u32,
+) -> Option<(String, u32, u32, u32)> {
+    let (file_tag, line) = binary.line_numbers.get(&address)?;
+    let line = line.checked_sub(1)?;
+
+    let text = sources
+        .iter()
+        .find(|&&(tag, _)| tag == &**file_tag)
+        .and_then(|&(_, source)| source.lines().nth(line as usize))?;
+    let (start, end) = SourceLine::parse(text).statement_span();
+
+    Some((
+        file_tag.to_string(),
+        line,
+        utf16_col(text, start),
+        utf16_col(text, end),
+    ))
+}
+
+/// Whether `entry` is closer before `pc` than `other`, where an entry before
+/// `pc` is always closer than one after it.
+fn closer_entry(pc: u32, entry: u32, other: u32) -> bool {
+    match (entry <= pc, other <= pc) {
+        (true, true) => entry > other,
+        (before, other_before) => before && !other_before,
+    }
+}
+
+/// Builds the static call graph of a program from the calls in its text
+/// segment. The functions are those found by the calling convention checks,
+/// along with `main`.
+pub fn call_graph(binary: &Binary, sources: &[(&str, &str)], symbols: &[Symbol]) -> CallGraph {
+    let instructions: HashMap<u32, Decoded> = text_instructions(binary).collect();
+
+    let mut functions: Vec<(String, u32, BTreeSet<u32>)> = find_functions(binary, &instructions)
+        .into_iter()
+        .map(|function| {
+            let addresses = function.addresses().collect();
+            (function.name, function.entry, addresses)
+        })
+        .collect();
+
+    if let Some(&main) = binary.labels.get("main") {
+        if instructions.contains_key(&main) && !functions.iter().any(|(_, entry, _)| *entry == main)
+        {
+            let addresses = function_body(&instructions, main).into_keys().collect();
+            functions.push(("main".to_string(), main, addresses));
+        }
+    }
+
+    let name_at: HashMap<u32, &str> = functions
+        .iter()
+        .map(|(name, entry, _)| (*entry, name.as_str()))
+        .collect();
+
+    // function bodies can overlap (e.g. where two functions share the code
+    // they return through), so each instruction belongs to the function
+    // whose entry is closest before it, and which reaches it
+    let mut owners: BTreeMap<u32, (u32, &str)> = BTreeMap::new();
+    for (name, entry, addresses) in &functions {
+        for &pc in addresses {
+            let owner = owners.entry(pc).or_insert((*entry, name));
+            if closer_entry(pc, *entry, owner.0) {
+                *owner = (*entry, name);
+            }
+        }
+    }
+
+    let mut calls = vec![];
+    for (pc, (_, caller)) in owners {
+        let callee = match instructions[&pc].flow(pc) {
+            Flow::Call(target) => match name_at.get(&target) {
+                Some(&name) => Some(name.to_string()),
+                None => continue,
+            },
+            Flow::CallIndirect => None,
+            _ => continue,
+        };
+
+        let (file_tag, line, col, col_end) = match source_span(binary, sources, pc) {
+            Some((file_tag, line, col, col_end)) => (Some(file_tag), line, col, col_end),
+            None => (None, 0, 0, 0),
+        };
+
+        calls.push(Call {
+            caller: caller.to_string(),
+            callee,
+            address: pc,
+            file_tag,
+            line,
+            col,
+            col_end,
+        });
+    }
+
+    let callees_of = |name: &str| -> Vec<&str> {
+        calls
+            .iter()
+            .filter(|call| call.caller == name)
+            .filter_map(|call| call.callee.as_deref())
+            .collect()
+    };
+
+    let reaches_itself = |name: &str| {
+        let mut seen: BTreeSet<&str> = BTreeSet::new();
+        let mut worklist = callees_of(name);
+
+        while let Some(callee) = worklist.pop() {
+            if callee == name {
+                return true;
+            }
+            if seen.insert(callee) {
+                worklist.extend(callees_of(callee));
+            }
+        }
+
+        false
+    };
+
+    let mut graph_functions: Vec<CallGraphFunction> = functions
+        .iter()
+        .map(|(name, entry, _)| {
+            let definition = symbols.iter().find(|symbol| &symbol.name == name);
+
+            CallGraphFunction {
+                name: name.clone(),
+                address: *entry,
+                file_tag: definition.map(|symbol| symbol.file_tag.clone()),
+                line: definition.map_or(0, |symbol| symbol.line),
+                col: definition.map_or(0, |symbol| symbol.col),
+                col_end: definition.map_or(0, |symbol| symbol.col_end),
+                is_recursive: reaches_itself(name),
+                is_called: name == "main"
+                    || calls
+                        .iter()
+                        .any(|call| call.callee.as_deref() == Some(name.as_str())),
+            }
+        })
+        .collect();
+    graph_functions.sort_by_key(|function| function.address);
+
+    CallGraph {
+        functions: graph_functions,
+        calls,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{symbols::symbol_table, testing::compile};
+
+    const PROGRAM: &str = "\
+main:
+\tjal\tfact
+\tjal\teven
+\tla\t$t0, fact
+\tjalr\t$t0
+\tjr\t$ra
+fact:
+\tjal\tfact
+\tjr\t$ra
+even:
+\tjal\todd
+\tjr\t$ra
+odd:
+\tjal\teven
+\tjr\t$ra
+unused:
+\tjr\t$ra
+";
+
+    fn graph() -> CallGraph {
+        let binary = compile(PROGRAM);
+        let sources = [("test.s", PROGRAM)];
+        let symbols = symbol_table(&sources, Some(&binary), 8);
+
+        call_graph(&binary, &sources, &symbols)
+    }
+
+    fn function<'g>(graph: &'g CallGraph, name: &str) -> &'g CallGraphFunction {
+        graph
+            .functions
+            .iter()
+            .find(|function| function.name == name)
+            .unwrap_or_else(|| panic!("no function `{}`", name))
+    }
+
+    #[test]
+    fn finds_recursion() {
+        let graph = graph();
+
+        assert!(function(&graph, "fact").is_recursive);
+        assert!(!function(&graph, "main").is_recursive);
+        assert!(!function(&graph, "unused").is_recursive);
+    }
+
+    #[test]
+    fn finds_mutual_recursion() {
+        let graph = graph();
+
+        assert!(function(&graph, "even").is_recursive);
+        assert!(function(&graph, "odd").is_recursive);
+    }
+
+    #[test]
+    fn marks_functions_which_are_never_called() {
+        let graph = graph();
+
+        assert!(!function(&graph, "unused").is_called);
+        assert!(function(&graph, "main").is_called);
+        assert!(function(&graph, "odd").is_called);
+
+        let unused = function(&graph, "unused");
+        assert_eq!((unused.line, unused.col, unused.col_end), (15, 0, 6));
+    }
+
+    #[test]
+    fn reports_jalr_as_calling_something_unknown() {
+        let graph = graph();
+
+        let calls: Vec<_> = graph
+            .calls
+            .iter()
+            .filter(|call| call.caller == "main")
+            .map(|call| (call.callee.as_deref(), call.line, call.col, call.col_end))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                (Some("fact"), 1, 1, 9),
+                (Some("even"), 2, 1, 9),
+                (None, 4, 1, 9),
+            ]
+        );
+    }
+}
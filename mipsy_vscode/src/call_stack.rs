@@ -0,0 +1,399 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
+
+use mipsy_lib::{
+    util::{get_segment, Segment},
+    Binary,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::decode::{ktext_instructions, text_instructions, Flow};
+
+/// A function which has been called and hasn't returned yet.
+#[derive(Clone, Debug)]
+struct Frame {
+    function: String,
+    /// the address of the `jal` (or `jalr`) which called it
+    call_site: u32,
+    /// `$sp` and `$fp` when it was called
+    sp: Option<i32>,
+    fp: Option<i32>,
+}
+
+/// A frame of the call stack, as `get_call_stack` shows it.
+#[derive(Serialize, Deserialize)]
+pub struct StackFrame {
+    pub function: String,
+    /// where the function is up to: the current instruction for the
+    /// innermost frame, otherwise the call it's waiting on
+    pub pc: u32,
+    pub file_tag: Option<String>,
+    pub line: Option<u32>,
+    /// the address of the call which started the function, and `$sp` and
+    /// `$fp` at the time (all unknown for the outermost frame)
+    pub call_site: Option<u32>,
+    pub sp: Option<i32>,
+    pub fp: Option<i32>,
+}
+
+/// What a call or return did to the stack, so it can be undone.
+struct Change {
+    pc: u32,
+    pushed: bool,
+    popped: Vec<Frame>,
+}
+
+/// A shadow call stack, kept by watching each `jal`/`jalr` and `jr $ra` the
+/// program runs (and undoes, when stepping backwards).
+pub struct CallStack {
+    frames: Vec<Frame>,
+    history: Vec<Change>,
+    /// where each function (`main`, the targets of `jal`s and the labels in
+    /// the kernel) starts
+    functions: BTreeMap<u32, String>,
+}
+
+impl CallStack {
+    pub fn new(binary: &Binary) -> Self {
+        let mut labels: HashMap<u32, &str> = HashMap::new();
+        for (name, &address) in &binary.labels {
+            let existing = labels.entry(address).or_insert(name);
+            if name.as_str() < *existing {
+                *existing = name;
+            }
+        }
+        let name_of = |address: u32| match labels.get(&address) {
+            Some(name) => name.to_string(),
+            None => std::format!("0x{:08X}", address),
+        };
+
+        let mut functions = BTreeMap::new();
+        for (pc, inst) in text_instructions(binary).chain(ktext_instructions(binary)) {
+            if let Flow::Call(target) = inst.flow(pc) {
+                functions.insert(target, name_of(target));
+            }
+        }
+        for (&address, name) in &labels {
+            if name == &"main" || get_segment(address) == Segment::KText {
+                functions.insert(address, name.to_string());
+            }
+        }
+
+        CallStack {
+            frames: vec![],
+            history: vec![],
+            functions,
+        }
+    }
+
+    /// The function which `pc` is (probably) part of.
+    fn function_containing(&self, pc: u32) -> String {
+        match self.functions.range(..=pc).next_back() {
+            Some((_, name)) => name.clone(),
+            None => std::format!("0x{:08X}", pc),
+        }
+    }
+
+    fn function_at(&self, address: u32) -> String {
+        match self.functions.get(&address) {
+            Some(name) => name.clone(),
+            None => self.function_containing(address),
+        }
+    }
+
+    /// Keeps track of the instruction at `pc` having run, which went to
+    /// `next_pc`. `$sp`, `$fp` and the register jumped to (for `jalr` and
+    /// `jr`) are from before it ran. Returns a message if it returned
+    /// somewhere other than where the function was called from.
+    pub fn after_step(
+        &mut self,
+        pc: u32,
+        flow: Flow,
+        next_pc: Option<u32>,
+        sp: Option<i32>,
+        fp: Option<i32>,
+        jumped_to: Option<u32>,
+    ) -> Option<String> {
+        let target = match flow {
+            Flow::Call(target) => Some(target),
+            Flow::CallIndirect | Flow::Return => jumped_to,
+            _ => return None,
+        };
+
+        if flow == Flow::Return {
+            return self.returned(pc, target);
+        }
+
+        // a `bltzal`/`bgezal` which didn't branch isn't a call
+        if next_pc.is_some() && next_pc != target {
+            self.history.push(Change {
+                pc,
+                pushed: false,
+                popped: vec![],
+            });
+            return None;
+        }
+
+        self.frames.push(Frame {
+            function: target.map_or_else(|| "[unknown]".into(), |target| self.function_at(target)),
+            call_site: pc,
+            sp,
+            fp,
+        });
+        self.history.push(Change {
+            pc,
+            pushed: true,
+            popped: vec![],
+        });
+
+        None
+    }
+
+    fn returned(&mut self, pc: u32, target: Option<u32>) -> Option<String> {
+        let matching = self
+            .frames
+            .iter()
+            .rposition(|frame| Some(frame.call_site.wrapping_add(4)) == target);
+
+        let (popped, mismatch) = match (matching, self.frames.last()) {
+            (Some(index), _) if index + 1 == self.frames.len() => {
+                (self.frames.split_off(index), None)
+            }
+            // returning past some of the functions which were called, e.g.
+            // if one jumped to another with `j` and not `jal`
+            (Some(index), Some(innermost)) => {
+                let skipped: Vec<String> = self.frames[index..self.frames.len() - 1]
+                    .iter()
+                    .map(|frame| std::format!("`{}`", frame.function))
+                    .collect();
+                let mismatch = std::format!(
+                    "`{}` returned to where `{}` was called from, so {} never returned",
+                    innermost.function,
+                    self.frames[index].function,
+                    skipped.join(", ")
+                );
+                (self.frames.split_off(index), Some(mismatch))
+            }
+            (None, Some(innermost)) => {
+                let mismatch = std::format!(
+                    "`{}` returned to {}, but was called from 0x{:08X} (so should have returned to 0x{:08X}) - has `$ra` been changed?",
+                    innermost.function,
+                    target.map_or_else(|| "an unknown address".into(), |target| std::format!("0x{:08X}", target)),
+                    innermost.call_site,
+                    innermost.call_site.wrapping_add(4),
+                );
+                (self.frames.split_off(self.frames.len() - 1), Some(mismatch))
+            }
+            // returning from the outermost function, e.g. `main`
+            (_, None) => (vec![], None),
+        };
+
+        self.history.push(Change {
+            pc,
+            pushed: false,
+            popped,
+        });
+
+        mismatch
+    }
+
+    /// Undoes the instruction at `pc`, when stepping backwards.
+    pub fn undo(&mut self, pc: u32, flow: Flow) {
+        if !matches!(flow, Flow::Call(_) | Flow::CallIndirect | Flow::Return) {
+            return;
+        }
+        if self.history.last().map(|change| change.pc) != Some(pc) {
+            return;
+        }
+
+        if let Some(change) = self.history.pop() {
+            if change.pushed {
+                self.frames.pop();
+            }
+            self.frames.extend(change.popped);
+        }
+    }
+
+    /// The frames of the stack, innermost (at `pc`) first.
+    pub fn frames(&self, pc: u32, line_numbers: &HashMap<u32, (Rc<str>, u32)>) -> Vec<StackFrame> {
+        let frame = |function: String, pc: u32, callee: Option<&Frame>| {
+            let position = line_numbers.get(&pc);
+            StackFrame {
+                function,
+                pc,
+                file_tag: position.map(|(tag, _)| tag.to_string()),
+                line: position.map(|&(_, line)| line),
+                call_site: callee.map(|callee| callee.call_site),
+                sp: callee.and_then(|callee| callee.sp),
+                fp: callee.and_then(|callee| callee.fp),
+            }
+        };
+
+        let mut stack = vec![];
+        let mut pc = pc;
+        for callee in self.frames.iter().rev() {
+            stack.push(frame(callee.function.clone(), pc, Some(callee)));
+            pc = callee.call_site;
+        }
+        stack.push(frame(self.function_containing(pc), pc, None));
+
+        stack
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::compile;
+    use mipsy_lib::TEXT_BOT;
+
+    const PROGRAM: &str = "\
+main:
+\tjal\tf
+\tjr\t$ra
+f:
+\tjal\tg
+\tjr\t$ra
+g:
+\tjr\t$ra
+";
+
+    const MAIN: u32 = TEXT_BOT;
+    const F: u32 = TEXT_BOT + 8;
+    const G: u32 = TEXT_BOT + 16;
+    const SP: i32 = 0x7FFFFF00;
+
+    struct Program {
+        binary: Binary,
+        stack: CallStack,
+    }
+
+    impl Program {
+        fn new() -> Self {
+            let binary = compile(PROGRAM);
+            let stack = CallStack::new(&binary);
+            Program { binary, stack }
+        }
+
+        /// Runs the `jal` at `pc`.
+        fn call(&mut self, pc: u32, target: u32) -> Option<String> {
+            let flow = Flow::Call(target);
+            self.stack
+                .after_step(pc, flow, Some(target), Some(SP), Some(SP), None)
+        }
+
+        /// Runs the `jr $ra` at `pc`, with `$ra` holding `ra`.
+        fn ret(&mut self, pc: u32, ra: u32) -> Option<String> {
+            self.stack
+                .after_step(pc, Flow::Return, Some(ra), Some(SP), Some(SP), Some(ra))
+        }
+
+        /// The function and line of each frame, innermost first.
+        fn frames(&self, pc: u32) -> Vec<(String, Option<u32>)> {
+            self.stack
+                .frames(pc, &self.binary.line_numbers)
+                .into_iter()
+                .map(|frame| (frame.function, frame.line))
+                .collect()
+        }
+    }
+
+    fn frame(function: &str, line: u32) -> (String, Option<u32>) {
+        (function.to_string(), Some(line))
+    }
+
+    #[test]
+    fn pushes_calls_and_pops_returns() {
+        let mut program = Program::new();
+        assert_eq!(program.frames(MAIN), [frame("main", 2)]);
+
+        assert_eq!(program.call(MAIN, F), None);
+        assert_eq!(program.call(F, G), None);
+        assert_eq!(
+            program.frames(G),
+            [frame("g", 8), frame("f", 5), frame("main", 2)]
+        );
+
+        let stack = program.stack.frames(G, &program.binary.line_numbers);
+        assert_eq!(stack[0].call_site, Some(F));
+        assert_eq!(stack[0].sp, Some(SP));
+        assert_eq!(stack[2].call_site, None);
+
+        assert_eq!(program.ret(G, F + 4), None);
+        assert_eq!(program.frames(F + 4), [frame("f", 6), frame("main", 2)]);
+        assert_eq!(program.ret(F + 4, MAIN + 4), None);
+        assert_eq!(program.frames(MAIN + 4), [frame("main", 3)]);
+    }
+
+    #[test]
+    fn explains_returning_past_a_frame() {
+        let mut program = Program::new();
+        program.call(MAIN, F);
+        program.call(F, G);
+
+        assert_eq!(
+            program.ret(G, MAIN + 4).as_deref(),
+            Some("`g` returned to where `f` was called from, so `f` never returned")
+        );
+        assert_eq!(program.frames(MAIN + 4), [frame("main", 3)]);
+    }
+
+    #[test]
+    fn explains_returning_somewhere_else() {
+        let mut program = Program::new();
+        program.call(MAIN, F);
+
+        assert_eq!(
+            program.ret(F + 4, G).as_deref(),
+            Some(
+                "`f` returned to 0x00400010, but was called from 0x00400000 \
+                 (so should have returned to 0x00400004) - has `$ra` been changed?"
+            )
+        );
+        assert_eq!(program.frames(G), [frame("g", 8)]);
+    }
+
+    #[test]
+    fn ignores_a_branch_and_link_which_isnt_taken() {
+        let mut program = Program::new();
+
+        // e.g. `bltzal $t0, g` with `$t0` not negative
+        let flow = Flow::Call(G);
+        let next = Some(MAIN + 4);
+        assert_eq!(
+            program
+                .stack
+                .after_step(MAIN, flow, next, Some(SP), Some(SP), None),
+            None
+        );
+        assert_eq!(program.frames(MAIN + 4), [frame("main", 3)]);
+
+        program.stack.undo(MAIN, flow);
+        assert_eq!(program.frames(MAIN), [frame("main", 2)]);
+    }
+
+    #[test]
+    fn undoes_back_to_an_earlier_stack() {
+        let mut program = Program::new();
+        program.call(MAIN, F);
+        program.call(F, G);
+        program.ret(G, MAIN + 4);
+        assert_eq!(program.frames(MAIN + 4), [frame("main", 3)]);
+
+        // only undoes the most recent call or return
+        program.stack.undo(F, Flow::Call(G));
+        assert_eq!(program.frames(MAIN + 4), [frame("main", 3)]);
+
+        program.stack.undo(G, Flow::Return);
+        assert_eq!(
+            program.frames(G),
+            [frame("g", 8), frame("f", 5), frame("main", 2)]
+        );
+
+        program.stack.undo(F, Flow::Call(G));
+        program.stack.undo(MAIN, Flow::Call(F));
+        assert_eq!(program.frames(MAIN), [frame("main", 2)]);
+    }
+}
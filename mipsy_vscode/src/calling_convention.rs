@@ -0,0 +1,575 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use mipsy_lib::{
+    util::{get_segment, Segment},
+    Binary,
+};
+
+use crate::{
+    decode::{register_bit, text_instructions, Decoded, Flow},
+    diagnostics::Warnings,
+    registers::{is_callee_saved, register_name, RA, SP, V0, ZERO},
+    syscalls::find_syscall,
+    ErrorReport,
+};
+
+const SPECIAL: u32 = 0x00;
+const ADDI: u32 = 0x08;
+const ADDIU: u32 = 0x09;
+const ORI: u32 = 0x0D;
+const LUI: u32 = 0x0F;
+const LW: u32 = 0x23;
+const SW: u32 = 0x2B;
+
+const FUNCT_SYSCALL: u32 = 0x0C;
+const FUNCT_ADD: u32 = 0x20;
+const FUNCT_ADDU: u32 = 0x21;
+const FUNCT_SUB: u32 = 0x22;
+const FUNCT_SUBU: u32 = 0x23;
+const FUNCT_OR: u32 = 0x25;
+
+/// Whether a syscall is `exit` or `exit2`, after which control never comes back.
+fn is_exit_syscall(number: i32) -> bool {
+    u32::try_from(number)
+        .ok()
+        .and_then(find_syscall)
+        .is_some_and(|syscall| syscall.kind == "exit")
+}
+
+/// What's known about the value of a register, relative to when the function
+/// being checked was called.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Value {
+    Const(i32),
+    /// the value `$sp` had on entry, plus an offset
+    Stack(i32),
+    /// a stack address, but with a different offset depending on the path taken
+    Mismatched,
+    Unknown,
+}
+
+impl Value {
+    fn join(self, other: Value) -> Value {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Value::Unknown, _) | (_, Value::Unknown) => Value::Unknown,
+            (Value::Stack(_) | Value::Mismatched, Value::Stack(_) | Value::Mismatched) => {
+                Value::Mismatched
+            }
+            _ => Value::Unknown,
+        }
+    }
+
+    fn add(self, other: Value) -> Value {
+        match (self, other) {
+            (Value::Const(a), Value::Const(b)) => Value::Const(a.wrapping_add(b)),
+            (Value::Stack(a), Value::Const(b)) | (Value::Const(b), Value::Stack(a)) => {
+                Value::Stack(a.wrapping_add(b))
+            }
+            (Value::Mismatched, Value::Const(_)) | (Value::Const(_), Value::Mismatched) => {
+                Value::Mismatched
+            }
+            _ => Value::Unknown,
+        }
+    }
+
+    fn sub(self, other: Value) -> Value {
+        match other {
+            Value::Const(b) => self.add(Value::Const(b.wrapping_neg())),
+            _ => Value::Unknown,
+        }
+    }
+
+    fn is_stack(self) -> bool {
+        matches!(self, Value::Stack(_) | Value::Mismatched)
+    }
+}
+
+/// Whether the caller's value of a register the function must preserve is
+/// still intact.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+struct Preserved {
+    /// the first instruction to overwrite the caller's value, unless it has
+    /// been restored since
+    changed_at: Option<u32>,
+    /// whether the caller's value has been stored on the stack
+    saved: bool,
+}
+
+impl Preserved {
+    fn join(self, other: Preserved) -> Preserved {
+        Preserved {
+            changed_at: match (self.changed_at, other.changed_at) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            saved: self.saved && other.saved,
+        }
+    }
+}
+
+fn is_checked(reg: u32) -> bool {
+    is_callee_saved(reg) || reg == RA
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct State {
+    values: [Value; 32],
+    preserved: [Preserved; 32],
+}
+
+impl State {
+    fn entry() -> Self {
+        let mut values = [Value::Unknown; 32];
+        values[ZERO as usize] = Value::Const(0);
+        values[SP as usize] = Value::Stack(0);
+
+        State {
+            values,
+            preserved: [Preserved::default(); 32],
+        }
+    }
+
+    fn join(&self, other: &State) -> State {
+        State {
+            values: std::array::from_fn(|i| self.values[i].join(other.values[i])),
+            preserved: std::array::from_fn(|i| self.preserved[i].join(other.preserved[i])),
+        }
+    }
+
+    fn value(&self, reg: u32) -> Value {
+        self.values[reg as usize]
+    }
+
+    /// The state after executing `inst` at `pc`.
+    fn step(&self, pc: u32, inst: &Decoded) -> State {
+        let mut next = self.clone();
+        let imm = Value::Const(i32::from(inst.imm));
+
+        for reg in (0..32).filter(|&reg| inst.writes() & register_bit(reg) != 0) {
+            next.values[reg as usize] = Value::Unknown;
+        }
+
+        match (inst.opcode, inst.funct) {
+            (ADDI | ADDIU, _) => next.values[inst.rt as usize] = self.value(inst.rs).add(imm),
+            (ORI, _) => {
+                next.values[inst.rt as usize] = match self.value(inst.rs) {
+                    Value::Const(value) => Value::Const(value | i32::from(inst.imm as u16)),
+                    _ => Value::Unknown,
+                }
+            }
+            (LUI, _) => next.values[inst.rt as usize] = Value::Const(i32::from(inst.imm) << 16),
+            (SPECIAL, FUNCT_ADD | FUNCT_ADDU) => {
+                next.values[inst.rd as usize] = self.value(inst.rs).add(self.value(inst.rt))
+            }
+            (SPECIAL, FUNCT_SUB | FUNCT_SUBU) => {
+                next.values[inst.rd as usize] = self.value(inst.rs).sub(self.value(inst.rt))
+            }
+            // `move` is often `or $rd, $rs, $zero`
+            (SPECIAL, FUNCT_OR) if inst.rt == ZERO => {
+                next.values[inst.rd as usize] = self.value(inst.rs)
+            }
+            _ => {}
+        }
+
+        if matches!(inst.flow(pc), Flow::Call(_) | Flow::CallIndirect) {
+            // the callee can change anything it doesn't have to preserve
+            for reg in (0..32).filter(|&reg| !is_callee_saved(reg) && reg != SP && reg != ZERO) {
+                next.values[reg as usize] = Value::Unknown;
+            }
+        }
+        next.values[ZERO as usize] = Value::Const(0);
+
+        for reg in (0..32).filter(|&reg| is_checked(reg)) {
+            let preserved = &mut next.preserved[reg as usize];
+            let on_stack = self.value(inst.rs).is_stack();
+
+            if inst.opcode == SW && inst.rt == reg && on_stack {
+                if preserved.changed_at.is_none() {
+                    preserved.saved = true;
+                }
+            } else if inst.opcode == LW && inst.rt == reg && on_stack && preserved.saved {
+                preserved.changed_at = None;
+            } else if inst.writes() & register_bit(reg) != 0 && preserved.changed_at.is_none() {
+                preserved.changed_at = Some(pc);
+            }
+        }
+
+        next
+    }
+
+    fn is_exit_syscall(&self, inst: &Decoded) -> bool {
+        inst.opcode == SPECIAL
+            && inst.funct == FUNCT_SYSCALL
+            && matches!(self.value(V0), Value::Const(number) if is_exit_syscall(number))
+    }
+}
+
+/// The state before every instruction that can be reached from the start of a
+/// function without returning from it.
+fn analyse(instructions: &HashMap<u32, Decoded>, entry: u32) -> BTreeMap<u32, State> {
+    let mut states = BTreeMap::new();
+    let mut worklist = vec![entry];
+    states.insert(entry, State::entry());
+
+    while let Some(pc) = worklist.pop() {
+        let inst = match instructions.get(&pc) {
+            Some(inst) => inst,
+            None => continue,
+        };
+
+        let state = &states[&pc];
+        if state.is_exit_syscall(inst) {
+            continue;
+        }
+
+        let next = pc.wrapping_add(4);
+        let successors = match inst.flow(pc) {
+            Flow::Next | Flow::Call(_) | Flow::CallIndirect => vec![next],
+            Flow::Branch(target) => vec![next, target],
+            Flow::Jump(target) => vec![target],
+            Flow::JumpIndirect | Flow::Return => vec![],
+        };
+
+        let after = state.step(pc, inst);
+        for successor in successors {
+            if !instructions.contains_key(&successor) {
+                continue;
+            }
+
+            match states.get(&successor) {
+                Some(existing) => {
+                    let joined = existing.join(&after);
+                    if joined != *existing {
+                        states.insert(successor, joined);
+                        worklist.push(successor);
+                    }
+                }
+                None => {
+                    states.insert(successor, after.clone());
+                    worklist.push(successor);
+                }
+            }
+        }
+    }
+
+    states
+}
+
+/// The instructions that can be reached from the start of a function without
+/// returning from it, each along with whether it's a syscall which exits the
+/// program (and so goes nowhere after it).
+pub fn function_body(instructions: &HashMap<u32, Decoded>, entry: u32) -> BTreeMap<u32, bool> {
+    analyse(instructions, entry)
+        .into_iter()
+        .filter_map(|(pc, state)| Some((pc, state.is_exit_syscall(instructions.get(&pc)?))))
+        .collect()
+}
+
+pub struct Function {
+    pub name: String,
+    pub entry: u32,
+    states: BTreeMap<u32, State>,
+}
+
+impl Function {
+    /// Every instruction in the function.
+    pub fn addresses(&self) -> impl Iterator<Item = u32> + '_ {
+        self.states.keys().copied()
+    }
+}
+
+/// Finds the functions in a program: the targets of `jal`, and any other
+/// label in the text segment (that isn't part of a function already) from
+/// which a `jr $ra` can be reached.
+pub fn find_functions(binary: &Binary, instructions: &HashMap<u32, Decoded>) -> Vec<Function> {
+    let mut labels: BTreeMap<u32, &str> = BTreeMap::new();
+    for (name, &address) in &binary.labels {
+        if matches!(get_segment(address), Segment::Text) {
+            let existing = labels.entry(address).or_insert(name);
+            if name.as_str() < *existing {
+                *existing = name;
+            }
+        }
+    }
+
+    let call_targets: BTreeSet<u32> = instructions
+        .iter()
+        .filter_map(|(&pc, inst)| match inst.flow(pc) {
+            Flow::Call(target) if instructions.contains_key(&target) => Some(target),
+            _ => None,
+        })
+        .collect();
+
+    let name_of = |address: u32| {
+        labels.get(&address).map_or_else(
+            || std::format!("0x{:08X}", address),
+            |name| name.to_string(),
+        )
+    };
+
+    let mut functions: Vec<Function> = call_targets
+        .iter()
+        .map(|&entry| Function {
+            name: name_of(entry),
+            entry,
+            states: analyse(instructions, entry),
+        })
+        .collect();
+
+    let mut covered: BTreeSet<u32> = functions
+        .iter()
+        .flat_map(|function| function.states.keys().copied())
+        .collect();
+
+    for &address in labels.keys() {
+        if covered.contains(&address) || !instructions.contains_key(&address) {
+            continue;
+        }
+
+        let states = analyse(instructions, address);
+        let returns = states
+            .keys()
+            .any(|pc| instructions[pc].flow(*pc) == Flow::Return);
+
+        if returns {
+            covered.extend(states.keys().copied());
+            functions.push(Function {
+                name: name_of(address),
+                entry: address,
+                states,
+            });
+        }
+    }
+
+    functions
+}
+
+/// Checks that every function in the program follows the calling convention:
+/// `$s0`-`$s7`, `$fp` and `$ra` are restored, and `$sp` is back where it
+/// started, whenever it returns. Only warnings for the file tagged `file_tag`
+/// are returned.
+pub fn check_program(
+    binary: &Binary,
+    source: &str,
+    file_tag: &str,
+    tab_size: u32,
+) -> Vec<ErrorReport> {
+    let instructions: HashMap<u32, Decoded> = text_instructions(binary).collect();
+    let mut warnings = Warnings::new(file_tag, source, tab_size);
+
+    let line_of = |address: u32| {
+        binary
+            .line_numbers
+            .get(&address)
+            .filter(|(tag, _)| **tag == *file_tag)
+            .map(|&(_, line)| line)
+    };
+
+    for function in find_functions(binary, &instructions) {
+        let name = &function.name;
+        let returns = function
+            .states
+            .iter()
+            .filter(|(pc, _)| instructions[pc].flow(**pc) == Flow::Return);
+
+        // the first return each problem is found at
+        let mut changed: BTreeMap<(u32, u32), u32> = BTreeMap::new();
+
+        for (&pc, state) in returns {
+            for reg in (0..32).filter(|&reg| is_checked(reg)) {
+                if let Some(changed_at) = state.preserved[reg as usize].changed_at {
+                    changed.entry((changed_at, reg)).or_insert(pc);
+                }
+            }
+
+            let line = match line_of(pc) {
+                Some(line) => line,
+                None => continue,
+            };
+
+            match state.value(SP) {
+                Value::Stack(offset) if offset != 0 => warnings.warn_line(
+                    line,
+                    std::format!(
+                        "`$sp` is {} bytes {} than when `{}` was called",
+                        offset.unsigned_abs(),
+                        if offset < 0 { "lower" } else { "higher" },
+                        name
+                    ),
+                    vec![std::format!(
+                        "everything `{}` pushes onto the stack needs to be popped off before it returns",
+                        name
+                    )],
+                ),
+                Value::Mismatched => warnings.warn_line(
+                    line,
+                    std::format!("`$sp` isn't always restored before `{}` returns", name),
+                    vec![std::format!(
+                        "depending on the path taken through `{}`, a different amount is pushed onto or popped off the stack",
+                        name
+                    )],
+                ),
+                _ => {}
+            }
+        }
+
+        for ((changed_at, reg), return_pc) in changed {
+            let line = match line_of(changed_at) {
+                Some(line) => line,
+                None => continue,
+            };
+
+            let mut tips = vec![];
+            if let Some(return_line) = line_of(return_pc) {
+                tips.push(std::format!("`{}` returns on line {}", name, return_line));
+            }
+
+            let is_call = matches!(
+                instructions[&changed_at].flow(changed_at),
+                Flow::Call(_) | Flow::CallIndirect
+            );
+
+            if reg == RA && is_call {
+                tips.insert(0, "save `$ra` on the stack before calling another function, and restore it before `jr $ra`".into());
+                warnings.warn_line(
+                    line,
+                    std::format!(
+                        "this call overwrites `$ra`, so `{}` won't be able to return to its caller",
+                        name
+                    ),
+                    tips,
+                );
+            } else {
+                let reg_name = register_name(reg);
+                tips.insert(
+                    0,
+                    std::format!(
+                        "`{}` must be preserved by functions, so save it on the stack at the start of `{}` and restore it before returning",
+                        reg_name, name
+                    ),
+                );
+                warnings.warn_register(
+                    line,
+                    reg,
+                    std::format!(
+                        "`{}` changes `{}` without restoring it before returning",
+                        name,
+                        reg_name
+                    ),
+                    tips,
+                );
+            }
+        }
+    }
+
+    warnings.into_reports()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::compile;
+
+    fn warnings(source: &str) -> Vec<(u32, String)> {
+        check_program(&compile(source), source, "test.s", 8)
+            .into_iter()
+            .map(|warning| (warning.line, warning.message))
+            .collect()
+    }
+
+    #[test]
+    fn accepts_a_saved_and_restored_frame() {
+        let source = "\
+main:
+\taddiu $sp, $sp, -8
+\tsw $ra, 4($sp)
+\tsw $s0, 0($sp)
+\tli $s0, 3
+\tmove $a0, $s0
+\tjal f
+\tlw $s0, 0($sp)
+\tlw $ra, 4($sp)
+\taddiu $sp, $sp, 8
+\tjr $ra
+f:
+\taddi $v0, $a0, 1
+\tjr $ra
+";
+
+        assert_eq!(warnings(source), []);
+    }
+
+    #[test]
+    fn warns_about_unrestored_s_registers() {
+        let source = "main:\n\tli $s0, 1\n\tli $v0, 0\n\tjr $ra\n";
+
+        assert_eq!(
+            warnings(source),
+            [(
+                2,
+                "`main` changes `$s0` without restoring it before returning".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn warns_about_unbalanced_stack_pointers() {
+        let source = "\
+main:
+\taddiu $sp, $sp, -4
+\tbeqz $a0, skip
+\taddiu $sp, $sp, 4
+skip:
+\tjr $ra
+f:
+\taddiu $sp, $sp, -8
+\tjr $ra
+";
+
+        assert_eq!(
+            warnings(source),
+            [
+                (
+                    6,
+                    "`$sp` isn't always restored before `main` returns".into()
+                ),
+                (9, "`$sp` is 8 bytes lower than when `f` was called".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_about_calls_which_overwrite_ra() {
+        let source = "main:\n\tjal f\n\tjr $ra\nf:\n\tjr $ra\n";
+
+        assert_eq!(
+            warnings(source),
+            [(
+                2,
+                "this call overwrites `$ra`, so `main` won't be able to return to its caller"
+                    .into()
+            )]
+        );
+    }
+
+    #[test]
+    fn finds_functions_and_their_bodies() {
+        let binary = compile("main:\n\tjal f\n\tli $v0, 10\n\tsyscall\n\tnop\nf:\n\tjr $ra\n");
+        let instructions: HashMap<u32, Decoded> = text_instructions(&binary).collect();
+
+        let functions = find_functions(&binary, &instructions);
+        let names: Vec<&str> = functions
+            .iter()
+            .map(|function| function.name.as_str())
+            .collect();
+        assert_eq!(names, ["f"]);
+
+        // the `nop` after exiting is never reached, so `main` never returns
+        let main = binary.labels["main"];
+        let body = function_body(&instructions, main);
+        assert_eq!(body.len(), 3);
+        assert_eq!(body.values().filter(|&&exits| exits).count(), 1);
+        assert!(!body.contains_key(&(main + 12)));
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use mipsy_lib::{decompile::decompile_inst_into_parts, Binary, InstSet};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    calling_convention::function_body,
+    decode::{text_instructions, Decoded, Flow},
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    /// a conditional branch which is taken
+    Taken,
+    /// a conditional branch which isn't taken
+    NotTaken,
+    /// an unconditional jump or branch
+    Jump,
+    /// carrying on to the next instruction, into the start of another block
+    Fallthrough,
+}
+
+/// How control leaves a block other than along an edge.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockExit {
+    Return,
+    /// `jr` through a register other than `$ra`, which could go anywhere
+    Indirect,
+    /// the `exit` syscall
+    Exit,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BlockInstruction {
+    pub address: u32,
+    pub word: u32,
+    /// the disassembled instruction, e.g. `addi $t0, $t0, 1`
+    pub assembly: String,
+    /// 1-indexed, like mipsy's line numbers
+    pub line: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BasicBlock {
+    pub id: usize,
+    pub start: u32,
+    /// the address of the last instruction in the block
+    pub end: u32,
+    /// the labels at the start of the block
+    pub labels: Vec<String>,
+    pub file_tag: Option<String>,
+    /// the (1-indexed, inclusive) range of source lines the block came from
+    pub lines: Option<[u32; 2]>,
+    pub instructions: Vec<BlockInstruction>,
+    pub exit: Option<BlockExit>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ControlFlowGraph {
+    pub function: String,
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+/// Where control can go after the instruction at `pc`, ignoring calls, which
+/// come back to the next instruction.
+pub fn successors(pc: u32, inst: &Decoded, exits: bool) -> Vec<(u32, EdgeKind)> {
+    let next = pc.wrapping_add(4);
+
+    if exits {
+        return vec![];
+    }
+
+    match inst.flow(pc) {
+        Flow::Next | Flow::Call(_) | Flow::CallIndirect => vec![(next, EdgeKind::Fallthrough)],
+        Flow::Branch(target) => vec![(target, EdgeKind::Taken), (next, EdgeKind::NotTaken)],
+        Flow::Jump(target) => vec![(target, EdgeKind::Jump)],
+        Flow::JumpIndirect | Flow::Return => vec![],
+    }
+}
+
+/// Splits the function starting at the label `function` into basic blocks,
+/// following every path from its start that doesn't return from it.
+pub fn control_flow_graph(
+    binary: &Binary,
+    iset: &InstSet,
+    function: &str,
+) -> Result<ControlFlowGraph, String> {
+    let entry = *binary
+        .labels
+        .get(function)
+        .ok_or_else(|| std::format!("there's no label called `{}`", function))?;
+
+    let instructions: HashMap<u32, Decoded> = text_instructions(binary).collect();
+    if !instructions.contains_key(&entry) {
+        return Err(std::format!(
+            "`{}` isn't the label of an instruction in the text segment",
+            function
+        ));
+    }
+
+    let body = function_body(&instructions, entry);
+
+    // a new block starts at the function's entry, wherever control can jump
+    // to, and after anything which doesn't just carry on to the next instruction
+    let mut leaders: BTreeSet<u32> = [entry].into_iter().collect();
+    for (&pc, &exits) in &body {
+        let successors = successors(pc, &instructions[&pc], exits);
+        let falls_through = matches!(successors.as_slice(), [(_, EdgeKind::Fallthrough)]);
+
+        for (target, kind) in successors {
+            if kind != EdgeKind::Fallthrough {
+                leaders.insert(target);
+            }
+        }
+        if !falls_through {
+            leaders.insert(pc.wrapping_add(4));
+        }
+    }
+
+    let mut label_names: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for (name, &address) in &binary.labels {
+        label_names.entry(address).or_default().push(name.clone());
+    }
+    for names in label_names.values_mut() {
+        names.sort();
+    }
+
+    let mut blocks: Vec<BasicBlock> = vec![];
+    let mut block_of: HashMap<u32, usize> = HashMap::new();
+
+    for (&pc, &exits) in &body {
+        let continues_block = blocks
+            .last()
+            .is_some_and(|block| block.end.wrapping_add(4) == pc && !leaders.contains(&pc));
+
+        if !continues_block {
+            blocks.push(BasicBlock {
+                id: blocks.len(),
+                start: pc,
+                end: pc,
+                labels: label_names.get(&pc).cloned().unwrap_or_default(),
+                file_tag: None,
+                lines: None,
+                instructions: vec![],
+                exit: None,
+            });
+        }
+
+        let block = blocks.last_mut().expect("just pushed one");
+        let inst = &instructions[&pc];
+        let location = binary.line_numbers.get(&pc);
+        let decompiled = decompile_inst_into_parts(binary, iset, inst.word, pc);
+
+        block.end = pc;
+        block.instructions.push(BlockInstruction {
+            address: pc,
+            word: inst.word,
+            assembly: std::format!(
+                "{} {}",
+                decompiled
+                    .inst_name
+                    .unwrap_or_else(|| "[unknown instruction]".into()),
+                decompiled.arguments.join(", ")
+            )
+            .trim_end()
+            .to_string(),
+            line: location.map(|&(_, line)| line),
+        });
+
+        if let Some((file_tag, line)) = location {
+            block.file_tag.get_or_insert_with(|| file_tag.to_string());
+            block.lines = Some(match block.lines {
+                Some([first, last]) => [first.min(*line), last.max(*line)],
+                None => [*line, *line],
+            });
+        }
+
+        block.exit = match inst.flow(pc) {
+            _ if exits => Some(BlockExit::Exit),
+            Flow::Return => Some(BlockExit::Return),
+            Flow::JumpIndirect => Some(BlockExit::Indirect),
+            _ => None,
+        };
+
+        block_of.insert(pc, block.id);
+    }
+
+    let mut edges = vec![];
+    for block in &blocks {
+        let last = block.end;
+
+        for (target, kind) in successors(last, &instructions[&last], body[&last]) {
+            if let Some(&to) = block_of.get(&target) {
+                edges.push(Edge {
+                    from: block.id,
+                    to,
+                    kind,
+                });
+            }
+        }
+    }
+
+    Ok(ControlFlowGraph {
+        function: function.to_string(),
+        blocks,
+        edges,
+    })
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('{', "\\{")
+        .replace('}', "\\}")
+        .replace('<', "\\<")
+        .replace('>', "\\>")
+        .replace('|', "\\|")
+}
+
+/// Renders a control flow graph in Graphviz's DOT language, with one record
+/// per block listing its instructions.
+pub fn to_dot(cfg: &ControlFlowGraph) -> String {
+    let mut dot = std::format!(
+        "digraph \"{}\" {{\n    node [shape=record, fontname=\"monospace\"];\n",
+        escape_dot(&cfg.function)
+    );
+
+    for block in &cfg.blocks {
+        let mut heading = if block.labels.is_empty() {
+            std::format!("0x{:08X}", block.start)
+        } else {
+            std::format!("{}:", block.labels.join(": "))
+        };
+        if let Some([first, last]) = block.lines {
+            heading += &if first == last {
+                std::format!(" (line {})", first)
+            } else {
+                std::format!(" (lines {}-{})", first, last)
+            };
+        }
+
+        let mut body: String = block
+            .instructions
+            .iter()
+            .map(|inst| escape_dot(&inst.assembly) + "\\l")
+            .collect();
+        match block.exit {
+            Some(BlockExit::Return) => body += "(returns)\\l",
+            Some(BlockExit::Indirect) => body += "(jumps somewhere unknown)\\l",
+            Some(BlockExit::Exit) => body += "(exits)\\l",
+            None => {}
+        }
+
+        dot += &std::format!(
+            "    b{} [label=\"{{{}|{}}}\"];\n",
+            block.id,
+            escape_dot(&heading),
+            body
+        );
+    }
+
+    for edge in &cfg.edges {
+        let attributes = match edge.kind {
+            EdgeKind::Taken => " [label=\"taken\", color=\"darkgreen\"]",
+            EdgeKind::NotTaken => " [label=\"not taken\", color=\"red\"]",
+            EdgeKind::Jump | EdgeKind::Fallthrough => "",
+        };
+        dot += &std::format!("    b{} -> b{}{};\n", edge.from, edge.to, attributes);
+    }
+
+    dot += "}\n";
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::compile;
+    use mipsy_lib::TEXT_BOT;
+
+    fn graph_of(source: &str, function: &str) -> Result<ControlFlowGraph, String> {
+        control_flow_graph(&compile(source), &mipsy_instructions::inst_set(), function)
+    }
+
+    fn edges(cfg: &ControlFlowGraph) -> Vec<(usize, usize, EdgeKind)> {
+        cfg.edges
+            .iter()
+            .map(|edge| (edge.from, edge.to, edge.kind))
+            .collect()
+    }
+
+    #[test]
+    fn splits_blocks_at_branch_targets_and_after_branches() {
+        let source = "\
+main:
+\tli\t$t0, 0
+\tli\t$t1, 3
+loop:
+\taddi\t$t0, $t0, 1
+\tbne\t$t0, $t1, loop
+\tjr\t$ra
+";
+        let cfg = graph_of(source, "main").unwrap();
+
+        let blocks: Vec<_> = cfg
+            .blocks
+            .iter()
+            .map(|block| (block.start, block.end, block.labels.clone(), block.lines))
+            .collect();
+        assert_eq!(
+            blocks,
+            [
+                (
+                    TEXT_BOT,
+                    TEXT_BOT + 4,
+                    vec!["main".to_string()],
+                    Some([2, 3])
+                ),
+                (
+                    TEXT_BOT + 8,
+                    TEXT_BOT + 12,
+                    vec!["loop".to_string()],
+                    Some([5, 6])
+                ),
+                (TEXT_BOT + 16, TEXT_BOT + 16, vec![], Some([7, 7])),
+            ]
+        );
+        assert_eq!(cfg.blocks[2].exit, Some(BlockExit::Return));
+
+        assert_eq!(
+            edges(&cfg),
+            [
+                (0, 1, EdgeKind::Fallthrough),
+                (1, 1, EdgeKind::Taken),
+                (1, 2, EdgeKind::NotTaken),
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_both_sides_of_a_branch() {
+        let source = "\
+main:
+\tbeq\t$a0, $zero, else
+\tli\t$v0, 1
+\tj\tend
+else:
+\tli\t$v0, 10
+\tsyscall
+end:
+\tjr\t$ra
+";
+        let cfg = graph_of(source, "main").unwrap();
+
+        assert_eq!(cfg.blocks.len(), 4);
+        assert_eq!(cfg.blocks[2].labels, ["else"]);
+        assert_eq!(cfg.blocks[2].exit, Some(BlockExit::Exit));
+        assert_eq!(cfg.blocks[3].labels, ["end"]);
+
+        // the exit syscall doesn't fall through into `end`
+        assert_eq!(
+            edges(&cfg),
+            [
+                (0, 2, EdgeKind::Taken),
+                (0, 1, EdgeKind::NotTaken),
+                (1, 3, EdgeKind::Jump),
+            ]
+        );
+
+        let dot = to_dot(&cfg);
+        assert!(dot.contains("b0 -> b2 [label=\"taken\""));
+        assert!(dot.contains("b0 -> b1 [label=\"not taken\""));
+        assert!(dot.contains("(exits)"));
+    }
+
+    #[test]
+    fn needs_a_text_label() {
+        let source = "\t.data\nx:\t.word\t1\n\t.text\nmain:\n\tjr\t$ra\n";
+
+        assert!(graph_of(source, "nope").unwrap_err().contains("no label"));
+        assert!(graph_of(source, "x").unwrap_err().contains("text segment"));
+    }
+}
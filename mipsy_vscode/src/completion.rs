@@ -0,0 +1,447 @@
+use std::collections::BTreeSet;
+
+use mipsy_lib::{inst::instruction::ArgumentType, InstSet};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    lexer::{byte_offset_of_utf16_col, is_identifier_char, tokenise_line, utf16_col, TokenKind},
+    registers::{register_index, GP_REGISTER_NAMES, V0, ZERO},
+    source::{Operand, SourceLine, Statement},
+    symbols::{Symbol, SymbolKind},
+    syntax::{operand_formats, DirectiveOperands, DIRECTIVES},
+    syscalls::SYSCALLS,
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionKind {
+    Instruction,
+    PseudoInstruction,
+    Directive,
+    Register,
+    FpuRegister,
+    Label,
+    Constant,
+    Syscall,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Completion {
+    label: String,
+    kind: CompletionKind,
+    detail: Option<String>,
+    docs: String,
+    sort_text: String,
+    insert_text: String,
+    /// the (0-indexed, UTF-16) columns of the text the completion replaces
+    replace: [u32; 2],
+}
+
+/// The kinds of thing that can go where the cursor is.
+#[derive(Default, Debug)]
+struct Wanted {
+    registers: bool,
+    fpu_registers: bool,
+    labels: bool,
+    constants: bool,
+    syscalls: bool,
+}
+
+/// Whether an instruction works on floating point registers (e.g. `add.s`).
+fn is_fpu_instruction(mnemonic: &str) -> bool {
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    mnemonic.ends_with(".s") || mnemonic.ends_with(".d")
+}
+
+fn operand_register(operand: Option<&Operand>) -> Option<u32> {
+    match operand?.tokens.as_slice() {
+        [register] if register.kind == TokenKind::Register => register_index(register.text),
+        _ => None,
+    }
+}
+
+/// Whether the operand being written is a syscall number going into `$v0`,
+/// e.g. `li $v0, ` or `addi $v0, $zero, `.
+fn is_setting_syscall(mnemonic: &str, operands: &[Operand], index: usize) -> bool {
+    if operand_register(operands.first()) != Some(V0) {
+        return false;
+    }
+
+    match mnemonic.to_ascii_lowercase().as_str() {
+        "li" => index == 1,
+        "addi" | "addiu" | "ori" => index == 2 && operand_register(operands.get(1)) == Some(ZERO),
+        _ => false,
+    }
+}
+
+fn wanted_for_instruction(
+    iset: &InstSet,
+    mnemonic: &str,
+    operands: &[Operand],
+    in_parens: bool,
+) -> Wanted {
+    let index = operands.len().saturating_sub(1);
+    let mut wanted = Wanted::default();
+
+    if in_parens {
+        wanted.registers = true;
+        return wanted;
+    }
+
+    let formats = operand_formats(iset, mnemonic);
+
+    if formats.is_empty() {
+        // we don't know what the instruction is, so anything goes
+        wanted.registers = true;
+        wanted.labels = true;
+        wanted.constants = true;
+        return wanted;
+    }
+
+    for arg in formats.iter().filter_map(|format| format.get(index)) {
+        match arg {
+            ArgumentType::Rd | ArgumentType::Rs | ArgumentType::Rt => {
+                if is_fpu_instruction(mnemonic) {
+                    wanted.fpu_registers = true;
+                } else {
+                    wanted.registers = true;
+                }
+            }
+            ArgumentType::Fd | ArgumentType::Fs | ArgumentType::Ft => wanted.fpu_registers = true,
+            ArgumentType::OffRs
+            | ArgumentType::OffRt
+            | ArgumentType::Off32Rs
+            | ArgumentType::Off32Rt => {
+                wanted.labels = true;
+                wanted.constants = true;
+            }
+            ArgumentType::J => wanted.labels = true,
+            _ => wanted.constants = true,
+        }
+    }
+
+    wanted.syscalls = is_setting_syscall(mnemonic, operands, index);
+
+    wanted
+}
+
+fn wanted_for_directive(directive: &str) -> Wanted {
+    let operands = DIRECTIVES
+        .iter()
+        .find(|known| known.name == directive)
+        .map(|known| known.operands);
+
+    match operands {
+        Some(DirectiveOperands::Values | DirectiveOperands::Value) => Wanted {
+            labels: directive == ".word",
+            constants: true,
+            ..Wanted::default()
+        },
+        Some(DirectiveOperands::Label) => Wanted {
+            labels: true,
+            ..Wanted::default()
+        },
+        Some(DirectiveOperands::None | DirectiveOperands::String | DirectiveOperands::Floats)
+        | None => Wanted::default(),
+    }
+}
+
+/// Suggests what could be written at a (0-indexed, UTF-16) column of a line,
+/// based on what's valid there.
+pub fn completions(iset: &InstSet, symbols: &[Symbol], line: &str, col: u32) -> Vec<Completion> {
+    let cursor = byte_offset_of_utf16_col(line, col);
+    let before = &line[..cursor];
+
+    let in_literal_or_comment = tokenise_line(before).last().is_some_and(|token| {
+        matches!(
+            token.kind,
+            TokenKind::Comment | TokenKind::UnterminatedString | TokenKind::UnterminatedChar
+        )
+    });
+    if in_literal_or_comment {
+        return vec![];
+    }
+
+    let word_start = before
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_identifier_char(c) || c == '$')
+        .last()
+        .map_or(cursor, |(i, _)| i);
+    let replace = [utf16_col(line, word_start), col];
+
+    let mut completions = vec![];
+    let mut add = |label: String, kind, detail, docs: String, sort_text, insert_text| {
+        completions.push(Completion {
+            label,
+            kind,
+            detail,
+            docs,
+            sort_text,
+            insert_text,
+            replace,
+        })
+    };
+
+    let context = SourceLine::parse(&before[..word_start]);
+
+    let wanted = match &context.statement {
+        Statement::Empty => {
+            let mut instructions: BTreeSet<(String, bool, bool)> = BTreeSet::new();
+            for sig in iset.native_set() {
+                let has_operands = !sig.compile_ops().format().is_empty();
+                instructions.insert((sig.name().to_ascii_lowercase(), false, has_operands));
+            }
+            for sig in iset.pseudo_set() {
+                let name = sig.name().to_ascii_lowercase();
+                let has_operands = !sig.compile_ops().format().is_empty();
+                if !instructions
+                    .iter()
+                    .any(|(existing, _, _)| *existing == name)
+                {
+                    instructions.insert((name, true, has_operands));
+                }
+            }
+
+            for (name, is_pseudo, has_operands) in instructions {
+                let insert_text = if has_operands {
+                    std::format!("{}\t", name)
+                } else {
+                    name.clone()
+                };
+                let kind = if is_pseudo {
+                    CompletionKind::PseudoInstruction
+                } else {
+                    CompletionKind::Instruction
+                };
+                add(
+                    name.clone(),
+                    kind,
+                    None,
+                    String::new(),
+                    std::format!("c{}", name),
+                    insert_text,
+                );
+            }
+
+            for directive in DIRECTIVES {
+                let sort_level = if before[word_start..].starts_with('.') {
+                    "a"
+                } else {
+                    "e"
+                };
+                add(
+                    directive.name.to_string(),
+                    CompletionKind::Directive,
+                    None,
+                    String::new(),
+                    std::format!("{}{}", sort_level, directive.name),
+                    std::format!("{}\t", directive.name),
+                );
+            }
+
+            Wanted::default()
+        }
+        Statement::Instruction { mnemonic, operands } => {
+            let in_parens = operands.last().is_some_and(|operand| {
+                let depth: i32 = operand
+                    .tokens
+                    .iter()
+                    .map(|token| match token.kind {
+                        TokenKind::LParen => 1,
+                        TokenKind::RParen => -1,
+                        _ => 0,
+                    })
+                    .sum();
+                depth > 0
+            });
+
+            wanted_for_instruction(iset, mnemonic.text, operands, in_parens)
+        }
+        Statement::Directive { name, .. } => wanted_for_directive(name.text),
+        Statement::Constant { .. } => Wanted {
+            constants: true,
+            ..Wanted::default()
+        },
+        Statement::Invalid { .. } => Wanted::default(),
+    };
+
+    if wanted.syscalls {
+        for syscall in SYSCALLS {
+            add(
+                syscall.number.to_string(),
+                CompletionKind::Syscall,
+                Some(syscall.name.to_string()),
+                std::format!("syscall: {}", syscall.name),
+                std::format!(
+                    "a{}{:02}",
+                    if syscall.is_common { "a" } else { "b" },
+                    syscall.number
+                ),
+                syscall.number.to_string(),
+            );
+        }
+    }
+
+    if wanted.registers {
+        for (index, name) in GP_REGISTER_NAMES.iter().enumerate() {
+            let label = std::format!("${}", name);
+            add(
+                label.clone(),
+                CompletionKind::Register,
+                Some(std::format!("${}", index)),
+                String::new(),
+                std::format!("b{:02}", index),
+                label,
+            );
+        }
+    }
+
+    if wanted.fpu_registers {
+        for index in 0..32 {
+            let label = std::format!("$f{}", index);
+            add(
+                label.clone(),
+                CompletionKind::FpuRegister,
+                None,
+                String::new(),
+                std::format!("b{:02}", index),
+                label,
+            );
+        }
+    }
+
+    let mut seen = BTreeSet::new();
+    for symbol in symbols {
+        let kind = match symbol.kind {
+            SymbolKind::TextLabel | SymbolKind::DataLabel if wanted.labels => CompletionKind::Label,
+            SymbolKind::Constant if wanted.constants => CompletionKind::Constant,
+            _ => continue,
+        };
+
+        if seen.insert(symbol.name.as_str()) {
+            add(
+                symbol.name.clone(),
+                kind,
+                symbol.value.map(|value| value.to_string()),
+                symbol.docs.clone(),
+                std::format!("c{}", symbol.name),
+                symbol.name.clone(),
+            );
+        }
+    }
+
+    completions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbols::symbol_table;
+
+    const PROGRAM: &str = "N = 4\nmain:\tjr $ra\n\t.data\nmsg:\t.asciiz \"hi\"\n";
+
+    fn complete(line: &str) -> Vec<Completion> {
+        let symbols = symbol_table(&[("test.s", PROGRAM)], None, 8);
+        completions(
+            &mipsy_instructions::inst_set(),
+            &symbols,
+            line,
+            line.encode_utf16().count() as u32,
+        )
+    }
+
+    fn kinds(completions: &[Completion]) -> BTreeSet<String> {
+        completions
+            .iter()
+            .map(|completion| std::format!("{:?}", completion.kind))
+            .collect()
+    }
+
+    fn find<'c>(completions: &'c [Completion], label: &str) -> Option<&'c Completion> {
+        completions
+            .iter()
+            .find(|completion| completion.label == label)
+    }
+
+    #[test]
+    fn suggests_fpu_registers_for_float_instructions() {
+        for line in ["\tadd.s $f0, ", "\tmul.d $f2, $f4, $f"] {
+            let found = complete(line);
+
+            assert_eq!(
+                kinds(&found),
+                BTreeSet::from(["FpuRegister".into()]),
+                "{}",
+                line
+            );
+            assert!(find(&found, "$f12").is_some());
+        }
+
+        assert_eq!(
+            kinds(&complete("\tadd $t0, ")),
+            BTreeSet::from(["Register".into()])
+        );
+    }
+
+    #[test]
+    fn suggests_syscall_numbers_going_into_v0() {
+        for line in ["\tli $v0, ", "\tli $2, 1", "\taddi $v0, $zero, "] {
+            let found = complete(line);
+            let print_int = find(&found, "1").unwrap_or_else(|| panic!("{}", line));
+
+            assert_eq!(print_int.kind, CompletionKind::Syscall);
+            assert_eq!(print_int.detail.as_deref(), Some("print int"));
+        }
+
+        assert!(!kinds(&complete("\tli $t0, ")).contains("Syscall"));
+        assert!(!kinds(&complete("\taddi $v0, $t0, ")).contains("Syscall"));
+    }
+
+    #[test]
+    fn replaces_the_word_being_written() {
+        let found = complete("\tli $v0, 1");
+        assert_eq!(find(&found, "10").unwrap().replace, [9, 10]);
+
+        let found = complete("\tjal ma");
+        assert_eq!(find(&found, "main").unwrap().replace, [5, 7]);
+    }
+
+    #[test]
+    fn suggests_labels_and_constants_where_they_fit() {
+        let found = complete("\tj ");
+        assert_eq!(kinds(&found), BTreeSet::from(["Label".into()]));
+
+        let found = complete("\tlw $t0, ");
+        assert!(find(&found, "msg").is_some());
+        assert_eq!(find(&found, "N").unwrap().detail.as_deref(), Some("4"));
+
+        let found = complete("\tlw $t0, 4($");
+        assert_eq!(kinds(&found), BTreeSet::from(["Register".into()]));
+    }
+
+    #[test]
+    fn suggests_instructions_and_directives_at_the_start() {
+        let found = complete("\t");
+
+        assert_eq!(
+            find(&found, "add").unwrap().kind,
+            CompletionKind::Instruction
+        );
+        assert_eq!(
+            find(&found, "li").unwrap().kind,
+            CompletionKind::PseudoInstruction
+        );
+        assert_eq!(find(&found, "syscall").unwrap().insert_text, "syscall");
+        assert_eq!(
+            find(&found, ".word").unwrap().kind,
+            CompletionKind::Directive
+        );
+    }
+
+    #[test]
+    fn suggests_nothing_in_comments_or_strings() {
+        assert!(complete("\tli $v0, 1 # ").is_empty());
+        assert!(complete("msg:\t.asciiz \"hel").is_empty());
+    }
+}
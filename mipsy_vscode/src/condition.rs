@@ -0,0 +1,729 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    expr::parse_char,
+    lexer::{is_identifier_char, is_identifier_start, parse_number},
+    registers::register_index,
+};
+
+/// Something a condition can read from the paused program.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Location {
+    Register(u32),
+    Hi,
+    Lo,
+    Pc,
+    Byte(u32),
+}
+
+/// The functions a condition can use to read memory: (name, size in bytes,
+/// whether it's sign extended), like the load instructions they're named after.
+const LOADS: &[(&str, u32, bool)] = &[
+    ("lw", 4, true),
+    ("lh", 2, true),
+    ("lhu", 2, false),
+    ("lb", 1, true),
+    ("lbu", 1, false),
+];
+
+/// The binary operators, loosest binding first, as in C.
+const LEVELS: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(i64),
+    Read(Location),
+    Load {
+        size: u32,
+        signed: bool,
+        address: Box<Expr>,
+    },
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Token<'a> {
+    Number(i64),
+    Register(&'a str),
+    Identifier(&'a str),
+    Operator(&'a str),
+    LParen,
+    RParen,
+}
+
+/// How long the character literal at the start of `text` is, allowing for an
+/// escaped quote (`'\''`).
+fn char_literal_len(text: &str) -> Option<usize> {
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '\'' if !escaped => return Some(i + 1),
+            _ => escaped = false,
+        }
+    }
+
+    None
+}
+
+fn tokenise(text: &str) -> Result<Vec<Token<'_>>, String> {
+    const OPERATORS: &[&str] = &[
+        "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "|", "^", "&", "<", ">", "+", "-", "*",
+        "/", "%", "!", "~",
+    ];
+
+    let mut tokens = vec![];
+    let mut rest = text.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let word_len = rest.find(|c| !is_identifier_char(c)).unwrap_or(rest.len());
+
+        let (token, len) = if c == '$' {
+            let len = 1 + rest[1..]
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len() - 1);
+            (Token::Register(&rest[..len]), len)
+        } else if c.is_ascii_digit() {
+            let number = parse_number(&rest[..word_len])
+                .ok_or_else(|| std::format!("`{}` isn't a number", &rest[..word_len]))?;
+            (Token::Number(number), word_len)
+        } else if c == '\'' {
+            let len = char_literal_len(rest)
+                .filter(|&len| parse_char(&rest[..len]).is_some())
+                .ok_or("a character literal isn't finished")?;
+            (Token::Number(parse_char(&rest[..len]).unwrap_or(0)), len)
+        } else if is_identifier_start(c) {
+            (Token::Identifier(&rest[..word_len]), word_len)
+        } else if c == '(' {
+            (Token::LParen, 1)
+        } else if c == ')' {
+            (Token::RParen, 1)
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or_else(|| std::format!("unexpected `{}`", c))?;
+            (Token::Operator(op), op.len())
+        };
+
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'t, 'a> {
+    tokens: &'t [Token<'a>],
+    pos: usize,
+    labels: &'t HashMap<String, u32>,
+}
+
+impl Parser<'_, '_> {
+    fn peek_operator(&self, level: usize) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Operator(op)) => LEVELS[level].iter().copied().find(|known| known == op),
+            _ => None,
+        }
+    }
+
+    fn expect_rparen(&mut self) -> Result<(), String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::RParen) => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err("missing a `)`".into()),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut expr = self.binary(level + 1)?;
+        while let Some(op) = self.peek_operator(level) {
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(rhs));
+        }
+
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let token = *self
+            .tokens
+            .get(self.pos)
+            .ok_or("the condition ends too soon")?;
+        self.pos += 1;
+
+        match token {
+            Token::Number(value) => Ok(Expr::Number(value)),
+            Token::Operator(op) => {
+                match ["-", "+", "!", "~"].into_iter().find(|&known| known == op) {
+                    Some(op) => Ok(Expr::Unary(op, Box::new(self.unary()?))),
+                    None => Err(std::format!("unexpected `{}`", op)),
+                }
+            }
+            Token::Register(name) => match &*name.to_ascii_lowercase() {
+                "$hi" => Ok(Expr::Read(Location::Hi)),
+                "$lo" => Ok(Expr::Read(Location::Lo)),
+                "$pc" => Ok(Expr::Read(Location::Pc)),
+                _ => register_index(name)
+                    .map(|reg| Expr::Read(Location::Register(reg)))
+                    .ok_or_else(|| std::format!("`{}` isn't a register", name)),
+            },
+            Token::Identifier(name) => {
+                let load = LOADS.iter().find(|&&(load, _, _)| load == name);
+
+                match (load, self.tokens.get(self.pos)) {
+                    (Some(&(_, size, signed)), Some(Token::LParen)) => {
+                        self.pos += 1;
+                        let address = self.binary(0)?;
+                        self.expect_rparen()?;
+
+                        Ok(Expr::Load {
+                            size,
+                            signed,
+                            address: Box::new(address),
+                        })
+                    }
+                    _ => self
+                        .labels
+                        .get(name)
+                        .map(|&address| Expr::Number(address.into()))
+                        .ok_or_else(|| std::format!("there's no label called `{}`", name)),
+                }
+            }
+            Token::LParen => {
+                let expr = self.binary(0)?;
+                self.expect_rparen()?;
+                Ok(expr)
+            }
+            Token::RParen => Err("unexpected `)`".into()),
+        }
+    }
+}
+
+/// An expression over the registers and memory of a paused program, such as
+/// `$t0 == 10 && lw($sp + 4) < 0`. Labels stand for their addresses.
+#[derive(Clone, Debug)]
+pub struct Condition {
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(text: &str, labels: &HashMap<String, u32>) -> Result<Self, String> {
+        let tokens = tokenise(text)?;
+        if tokens.is_empty() {
+            return Err("the condition is empty".into());
+        }
+
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+            labels,
+        };
+        let expr = parser.binary(0)?;
+
+        match tokens.get(parser.pos) {
+            None => Ok(Condition { expr }),
+            Some(Token::RParen) => Err("unexpected `)`".into()),
+            Some(_) => Err("expected an operator".into()),
+        }
+    }
+
+    /// Works out the value of the expression, where true is 1 and false is 0.
+    pub fn evaluate(&self, read: &impl Fn(Location) -> Option<i32>) -> Result<i64, String> {
+        evaluate(&self.expr, read)
+    }
+
+    pub fn is_true(&self, read: &impl Fn(Location) -> Option<i32>) -> Result<bool, String> {
+        Ok(self.evaluate(read)? != 0)
+    }
+}
+
+fn read_value(location: Location, read: &impl Fn(Location) -> Option<i32>) -> Result<i64, String> {
+    read(location).map(i64::from).ok_or_else(|| match location {
+        Location::Register(reg) => std::format!(
+            "`{}` is uninitialised",
+            crate::registers::register_name(reg)
+        ),
+        Location::Hi => "`$hi` is uninitialised".into(),
+        Location::Lo => "`$lo` is uninitialised".into(),
+        Location::Pc => "there's no program counter".into(),
+        Location::Byte(address) => std::format!("the byte at 0x{:08X} is uninitialised", address),
+    })
+}
+
+fn evaluate(expr: &Expr, read: &impl Fn(Location) -> Option<i32>) -> Result<i64, String> {
+    let truth = |value: bool| i64::from(value);
+
+    Ok(match expr {
+        Expr::Number(value) => *value,
+        Expr::Read(location) => read_value(*location, read)?,
+        Expr::Load {
+            size,
+            signed,
+            address,
+        } => {
+            let address = evaluate(address, read)? as u32;
+            if !address.is_multiple_of(*size) {
+                return Err(std::format!(
+                    "0x{:08X} isn't a multiple of {}",
+                    address,
+                    size
+                ));
+            }
+
+            // little endian, like mipsy
+            let mut value: i64 = 0;
+            for i in (0..*size).rev() {
+                let byte = read_value(Location::Byte(address.wrapping_add(i)), read)?;
+                value = (value << 8) | (byte & 0xFF);
+            }
+
+            let bits = 8 * size;
+            if *signed && value & (1 << (bits - 1)) != 0 {
+                value -= 1 << bits;
+            }
+            value
+        }
+        Expr::Unary(op, operand) => {
+            let value = evaluate(operand, read)?;
+            match *op {
+                "-" => value.wrapping_neg(),
+                "!" => truth(value == 0),
+                "~" => !value,
+                _ => value,
+            }
+        }
+        Expr::Binary("&&", lhs, rhs) => {
+            truth(evaluate(lhs, read)? != 0 && evaluate(rhs, read)? != 0)
+        }
+        Expr::Binary("||", lhs, rhs) => {
+            truth(evaluate(lhs, read)? != 0 || evaluate(rhs, read)? != 0)
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (evaluate(lhs, read)?, evaluate(rhs, read)?);
+            let shift = || u32::try_from(rhs).map_err(|_| "can't shift by a negative amount");
+
+            match *op {
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "==" => truth(lhs == rhs),
+                "!=" => truth(lhs != rhs),
+                "<" => truth(lhs < rhs),
+                "<=" => truth(lhs <= rhs),
+                ">" => truth(lhs > rhs),
+                ">=" => truth(lhs >= rhs),
+                "<<" => lhs.checked_shl(shift()?).unwrap_or(0),
+                ">>" => lhs.checked_shr(shift()?).unwrap_or(0),
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" => lhs.checked_div(rhs).ok_or("division by zero")?,
+                _ => lhs.checked_rem(rhs).ok_or("division by zero")?,
+            }
+        }
+    })
+}
+
+/// When a breakpoint should stop, based on how many times it's been hit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HitCondition {
+    /// e.g. `>= 5`; a plain number means exactly that many hits
+    Compare(&'static str, u32),
+    /// `% 3`: every third hit
+    Every(u32),
+}
+
+impl HitCondition {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        const OPERATORS: &[&str] = &["==", "!=", "<=", ">=", "<", ">", "%", "="];
+
+        let text = text.trim();
+        let (op, count) = match OPERATORS.iter().find(|op| text.starts_with(*op)) {
+            Some(op) => (*op, text[op.len()..].trim()),
+            None => ("==", text),
+        };
+        let count: u32 = count
+            .parse()
+            .map_err(|_| std::format!("`{}` isn't a hit count", count))?;
+
+        match op {
+            "%" if count == 0 => Err("can't stop on every 0th hit".into()),
+            "%" => Ok(HitCondition::Every(count)),
+            "=" => Ok(HitCondition::Compare("==", count)),
+            op => Ok(HitCondition::Compare(op, count)),
+        }
+    }
+
+    pub fn is_met(&self, hits: u32) -> bool {
+        match *self {
+            HitCondition::Every(count) => hits.is_multiple_of(count),
+            HitCondition::Compare(op, count) => match op {
+                "==" => hits == count,
+                "!=" => hits != count,
+                "<" => hits < count,
+                "<=" => hits <= count,
+                ">" => hits > count,
+                _ => hits >= count,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum LogPart {
+    Text(String),
+    Value(Condition),
+}
+
+/// The message of a logpoint, where each `{expression}` is replaced with its
+/// value when the logpoint is hit.
+#[derive(Clone, Debug)]
+pub struct LogMessage {
+    parts: Vec<LogPart>,
+}
+
+impl LogMessage {
+    pub fn parse(text: &str, labels: &HashMap<String, u32>) -> Result<Self, String> {
+        let mut parts = vec![];
+        let mut rest = text;
+
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..]
+                .find('}')
+                .map(|close| open + close)
+                .ok_or("a `{` in the message isn't closed")?;
+
+            parts.push(LogPart::Text(rest[..open].to_string()));
+            parts.push(LogPart::Value(Condition::parse(
+                &rest[open + 1..close],
+                labels,
+            )?));
+            rest = &rest[close + 1..];
+        }
+        parts.push(LogPart::Text(rest.to_string()));
+
+        Ok(LogMessage { parts })
+    }
+
+    pub fn render(&self, read: &impl Fn(Location) -> Option<i32>) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                LogPart::Text(text) => text.clone(),
+                LogPart::Value(value) => match value.evaluate(read) {
+                    Ok(value) => value.to_string(),
+                    Err(err) => std::format!("<{}>", err),
+                },
+            })
+            .collect()
+    }
+}
+
+/// A breakpoint as the editor sets it (DAP's `SourceBreakpoint`).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceBreakpoint {
+    /// 1-indexed
+    pub line: u32,
+    #[serde(default)]
+    pub condition: Option<String>,
+    #[serde(default)]
+    pub hit_condition: Option<String>,
+    #[serde(default)]
+    pub log_message: Option<String>,
+}
+
+/// Whether a breakpoint could be set, and why not.
+#[derive(Serialize, Deserialize)]
+pub struct BreakpointStatus {
+    pub line: u32,
+    pub verified: bool,
+    pub message: Option<String>,
+}
+
+/// What to do when the program reaches a breakpoint.
+pub enum BreakpointHit {
+    Continue,
+    Stop,
+    /// a logpoint: print the message and keep going
+    Log(String),
+    /// the condition couldn't be worked out, so stop and say why
+    Error(String),
+}
+
+pub struct Breakpoint {
+    condition: Option<Condition>,
+    hit_condition: Option<HitCondition>,
+    log_message: Option<LogMessage>,
+    /// how many times the program has reached the breakpoint with its
+    /// condition true
+    hits: u32,
+}
+
+impl Breakpoint {
+    pub fn new(source: &SourceBreakpoint, labels: &HashMap<String, u32>) -> Result<Self, String> {
+        let non_empty = |text: &Option<String>| {
+            text.as_deref()
+                .filter(|text| !text.trim().is_empty())
+                .map(str::to_string)
+        };
+
+        Ok(Breakpoint {
+            condition: non_empty(&source.condition)
+                .map(|condition| Condition::parse(&condition, labels))
+                .transpose()
+                .map_err(|err| std::format!("invalid condition: {}", err))?,
+            hit_condition: non_empty(&source.hit_condition)
+                .map(|hit_condition| HitCondition::parse(&hit_condition))
+                .transpose()
+                .map_err(|err| std::format!("invalid hit count: {}", err))?,
+            log_message: non_empty(&source.log_message)
+                .map(|message| LogMessage::parse(&message, labels))
+                .transpose()
+                .map_err(|err| std::format!("invalid log message: {}", err))?,
+            hits: 0,
+        })
+    }
+
+    /// Keeps the hits counted by `previous`, the breakpoint this replaces on
+    /// the same line, unless the hit condition has changed.
+    pub fn carry_over_hits(&mut self, previous: &Breakpoint) {
+        if self.hit_condition == previous.hit_condition {
+            self.hits = previous.hits;
+        }
+    }
+
+    fn condition_holds(&self, read: &impl Fn(Location) -> Option<i32>) -> Result<bool, String> {
+        self.condition
+            .as_ref()
+            .map_or(Ok(true), |condition| condition.is_true(read))
+    }
+
+    /// Works out what to do now that the program has reached the breakpoint,
+    /// counting the hit if its condition is true.
+    pub fn hit(&mut self, read: &impl Fn(Location) -> Option<i32>) -> BreakpointHit {
+        match self.condition_holds(read) {
+            Ok(true) => {}
+            Ok(false) => return BreakpointHit::Continue,
+            Err(err) => {
+                return BreakpointHit::Error(std::format!(
+                    "couldn't check the breakpoint's condition: {}",
+                    err
+                ))
+            }
+        }
+
+        self.hits += 1;
+        if !self
+            .hit_condition
+            .is_none_or(|hit_condition| hit_condition.is_met(self.hits))
+        {
+            return BreakpointHit::Continue;
+        }
+
+        match &self.log_message {
+            Some(message) => BreakpointHit::Log(message.render(read)),
+            None => BreakpointHit::Stop,
+        }
+    }
+
+    /// Whether `hit` counts reaching the breakpoint now as a hit.
+    pub fn counts_hit(&self, read: &impl Fn(Location) -> Option<i32>) -> bool {
+        self.condition_holds(read) == Ok(true)
+    }
+
+    /// Takes back a hit `hit` counted, when the program goes back to before
+    /// it reached the breakpoint.
+    pub fn unhit(&mut self) {
+        self.hits = self.hits.saturating_sub(1);
+    }
+
+    /// Whether stepping backwards should stop here: whether `hit` stopped the
+    /// program when it got here going forwards, given the hits counted up to
+    /// now. Logpoints never stop.
+    pub fn stops_going_back(&self, read: &impl Fn(Location) -> Option<i32>) -> bool {
+        self.log_message.is_none()
+            && self.condition_holds(read).unwrap_or(true)
+            && self
+                .hit_condition
+                .is_none_or(|hit_condition| hit_condition.is_met(self.hits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARR: u32 = 0x10010000;
+
+    /// Reads from a program where `$t0` is 10, and the word at `arr` is -1
+    /// (with nothing after it).
+    fn read(location: Location) -> Option<i32> {
+        match location {
+            Location::Register(8) => Some(10),
+            Location::Byte(address) if (ARR..ARR + 4).contains(&address) => Some(0xFF),
+            _ => None,
+        }
+    }
+
+    fn evaluate(text: &str) -> Result<i64, String> {
+        let labels = HashMap::from([("arr".to_string(), ARR)]);
+        Condition::parse(text, &labels)?.evaluate(&read)
+    }
+
+    #[test]
+    fn binds_operators_as_c_does() {
+        assert_eq!(evaluate("1 + 2 * 3"), Ok(7));
+        assert_eq!(evaluate("1 << 2 + 1"), Ok(8));
+        assert_eq!(evaluate("6 & 3 == 3"), Ok(0));
+        assert_eq!(evaluate("1 | 2 ^ 3 & 1"), Ok(3));
+        assert_eq!(evaluate("-$t0 * 2 < 0 && !0 || 0"), Ok(1));
+        assert_eq!(evaluate("(1 + 2) * 3"), Ok(9));
+    }
+
+    #[test]
+    fn loads_like_the_instructions() {
+        assert_eq!(evaluate("lw(arr)"), Ok(-1));
+        assert_eq!(evaluate("lh(arr + 2)"), Ok(-1));
+        assert_eq!(evaluate("lhu(arr + 2)"), Ok(0xFFFF));
+        assert_eq!(evaluate("lb(arr + 3)"), Ok(-1));
+        assert_eq!(evaluate("lbu(arr + 3)"), Ok(0xFF));
+    }
+
+    #[test]
+    fn rejects_misaligned_and_uninitialised_loads() {
+        assert_eq!(
+            evaluate("lw(arr + 2)"),
+            Err("0x10010002 isn't a multiple of 4".into())
+        );
+        assert_eq!(
+            evaluate("lb(arr + 4)"),
+            Err("the byte at 0x10010004 is uninitialised".into())
+        );
+        assert_eq!(evaluate("$t1"), Err("`$t1` is uninitialised".into()));
+    }
+
+    #[test]
+    fn rejects_division_by_zero() {
+        assert_eq!(evaluate("$t0 / 0"), Err("division by zero".into()));
+        assert_eq!(evaluate("$t0 % 0"), Err("division by zero".into()));
+        assert_eq!(evaluate("$t0 / 3 + $t0 % 3"), Ok(4));
+    }
+
+    #[test]
+    fn reads_character_literals() {
+        assert_eq!(evaluate("'a'"), Ok(97));
+        assert_eq!(evaluate("'\\n'"), Ok(10));
+        assert_eq!(evaluate("'\\''"), Ok(39));
+        assert_eq!(evaluate("'\\\\' + 1"), Ok(93));
+        assert!(evaluate("'a").is_err());
+    }
+
+    #[test]
+    fn explains_bad_conditions() {
+        assert_eq!(evaluate(""), Err("the condition is empty".into()));
+        assert_eq!(evaluate("(1 + 2"), Err("missing a `)`".into()));
+        assert_eq!(evaluate("1 2"), Err("expected an operator".into()));
+        assert_eq!(
+            evaluate("nope"),
+            Err("there's no label called `nope`".into())
+        );
+        assert_eq!(evaluate("$t99"), Err("`$t99` isn't a register".into()));
+    }
+
+    #[test]
+    fn parses_hit_conditions() {
+        let parse = |text| HitCondition::parse(text);
+
+        assert_eq!(parse("5"), Ok(HitCondition::Compare("==", 5)));
+        assert_eq!(parse("= 5"), Ok(HitCondition::Compare("==", 5)));
+        assert_eq!(parse(">= 3"), Ok(HitCondition::Compare(">=", 3)));
+        assert_eq!(parse("<2"), Ok(HitCondition::Compare("<", 2)));
+        assert_eq!(parse("% 2"), Ok(HitCondition::Every(2)));
+        assert!(parse("% 0").is_err());
+        assert!(parse("often").is_err());
+
+        let met = |text, hits| parse(text).unwrap().is_met(hits);
+        assert!(met("3", 3) && !met("3", 4));
+        assert!(met("> 1", 2) && !met("> 1", 1));
+        assert!(met("% 2", 4) && !met("% 2", 3));
+    }
+
+    #[test]
+    fn fills_in_log_messages() {
+        let labels = HashMap::new();
+        let render = |text| LogMessage::parse(text, &labels).map(|message| message.render(&read));
+
+        assert_eq!(render("t0 is {$t0}"), Ok("t0 is 10".into()));
+        assert_eq!(render("{$t0 * 2}{$t0}"), Ok("2010".into()));
+        assert_eq!(render("no values"), Ok("no values".into()));
+        assert_eq!(render("{$t1}!"), Ok("<`$t1` is uninitialised>!".into()));
+        assert!(render("{$t0").is_err());
+        assert!(render("{$t0 +}").is_err());
+    }
+
+    fn breakpoint(condition: Option<&str>, hit_condition: Option<&str>) -> Breakpoint {
+        let source = SourceBreakpoint {
+            line: 1,
+            condition: condition.map(String::from),
+            hit_condition: hit_condition.map(String::from),
+            log_message: None,
+        };
+        Breakpoint::new(&source, &HashMap::new()).unwrap()
+    }
+
+    #[test]
+    fn counts_hits_both_ways() {
+        let mut every_other = breakpoint(Some("$t0 > 0"), Some("% 2"));
+
+        assert!(matches!(every_other.hit(&read), BreakpointHit::Continue));
+        assert!(matches!(every_other.hit(&read), BreakpointHit::Stop));
+        assert!(every_other.stops_going_back(&read));
+
+        // going back past the second hit, then forwards again
+        every_other.unhit();
+        assert!(!every_other.stops_going_back(&read));
+        assert!(matches!(every_other.hit(&read), BreakpointHit::Stop));
+
+        let mut never = breakpoint(Some("$t0 < 0"), None);
+        assert!(!never.counts_hit(&read));
+        assert!(matches!(never.hit(&read), BreakpointHit::Continue));
+    }
+
+    #[test]
+    fn keeps_hits_unless_the_hit_condition_changes() {
+        let mut old = breakpoint(None, Some(">= 2"));
+        old.hit(&read);
+
+        let mut same = breakpoint(Some("$t0 == 10"), Some(">=2"));
+        same.carry_over_hits(&old);
+        assert!(matches!(same.hit(&read), BreakpointHit::Stop));
+
+        let mut changed = breakpoint(None, Some(">= 3"));
+        changed.carry_over_hits(&old);
+        assert!(matches!(changed.hit(&read), BreakpointHit::Continue));
+    }
+}
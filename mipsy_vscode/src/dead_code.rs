@@ -0,0 +1,326 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use mipsy_lib::{
+    util::{get_segment, Segment},
+    Binary, KTEXT_BOT, TEXT_BOT,
+};
+
+use crate::{
+    calling_convention::function_body,
+    decode::{ktext_instructions, text_instructions, Decoded, Flow},
+    diagnostics::Warnings,
+    lint::is_branch_or_jump,
+    parsed::{NameUse, ParsedFile},
+};
+
+fn is_store(mnemonic: &str) -> bool {
+    matches!(
+        mnemonic,
+        "sb" | "sh" | "sw" | "swl" | "swr" | "sc" | "swc1" | "sdc1" | "s.s" | "s.d"
+    )
+}
+
+/// How the labels (and constants) in a program are used, apart from where
+/// they're defined.
+#[derive(Default)]
+struct Uses<'a> {
+    used: HashSet<&'a str>,
+    /// used other than as the target of a branch or jump, e.g. by `la`, so
+    /// the program could end up jumping there through a register
+    address_taken: HashSet<&'a str>,
+    /// used other than by a store, so what's there could be read
+    read: HashSet<&'a str>,
+}
+
+fn uses<'a>(files: &[ParsedFile<'a>]) -> Uses<'a> {
+    let mut uses = Uses::default();
+
+    for file in files {
+        for line in &file.lines {
+            let mnemonic = line
+                .mnemonic()
+                .map(|mnemonic| mnemonic.text.to_ascii_lowercase());
+
+            for (token, name_use) in line.symbol_names() {
+                if name_use.is_definition() {
+                    continue;
+                }
+
+                uses.used.insert(token.text);
+                if !mnemonic.as_deref().is_some_and(is_branch_or_jump) {
+                    uses.address_taken.insert(token.text);
+                }
+                if !mnemonic.as_deref().is_some_and(is_store) {
+                    uses.read.insert(token.text);
+                }
+            }
+        }
+    }
+
+    uses
+}
+
+/// Every instruction which can be run, following control flow (and calls)
+/// from each of `roots`.
+fn reachable(instructions: &HashMap<u32, Decoded>, roots: Vec<u32>) -> HashSet<u32> {
+    let mut reached = HashSet::new();
+    let mut entries = HashSet::new();
+    let mut worklist = roots;
+
+    while let Some(entry) = worklist.pop() {
+        if !instructions.contains_key(&entry) || !entries.insert(entry) {
+            continue;
+        }
+
+        for pc in function_body(instructions, entry).into_keys() {
+            reached.insert(pc);
+
+            if let Flow::Call(target) = instructions[&pc].flow(pc) {
+                worklist.push(target);
+            }
+        }
+    }
+
+    reached
+}
+
+/// Warns about code which can never be run, and labels which are never used.
+///
+/// The program starts at `main`, or at the start of the kernel if it was
+/// compiled with one. Labels whose address is taken (e.g. with `la`) are
+/// assumed to be run too, as they could be jumped to with `jr` or `jalr`.
+pub fn check_dead_code(
+    binary: &Binary,
+    sources: &[(&str, &str)],
+    check_main: bool,
+    tab_size: u32,
+    warnings: &mut Warnings,
+) {
+    let file_tag = warnings.file_tag().to_owned();
+    let files: Vec<ParsedFile> = sources
+        .iter()
+        .map(|&(tag, source)| ParsedFile::parse(tag, source, tab_size))
+        .collect();
+    let file = sources
+        .iter()
+        .position(|&(tag, _)| tag == file_tag)
+        .map(|index| &files[index]);
+
+    let uses = uses(&files);
+    let instructions: HashMap<u32, Decoded> = text_instructions(binary)
+        .chain(ktext_instructions(binary))
+        .collect();
+
+    let mut text_labels: BTreeMap<u32, &str> = BTreeMap::new();
+    for (name, &address) in &binary.labels {
+        if matches!(get_segment(address), Segment::Text | Segment::KText) {
+            let existing = text_labels.entry(address).or_insert(name);
+            if name.as_str() < *existing {
+                *existing = name;
+            }
+        }
+    }
+
+    let mut roots = vec![];
+    match binary.labels.get("main") {
+        Some(&main) => roots.push(main),
+        None => {
+            // there's no telling where it starts, so anything with a label could be run
+            roots.push(TEXT_BOT);
+            roots.extend(text_labels.keys().copied());
+        }
+    }
+    if check_main {
+        roots.push(KTEXT_BOT);
+    }
+    roots.extend(
+        binary
+            .labels
+            .iter()
+            .filter(|&(name, _)| uses.address_taken.contains(name.as_str()))
+            .map(|(_, &address)| address),
+    );
+
+    let reached = reachable(&instructions, roots);
+
+    let mut unreachable: Vec<u32> = instructions
+        .keys()
+        .copied()
+        .filter(|pc| !reached.contains(pc))
+        .collect();
+    unreachable.sort_unstable();
+
+    for (i, &pc) in unreachable.iter().enumerate() {
+        // only report the start of each stretch of unreachable code
+        if i > 0 && unreachable[i - 1] == pc.wrapping_sub(4) {
+            continue;
+        }
+
+        let line = match binary.line_numbers.get(&pc) {
+            Some((tag, line)) if **tag == *file_tag => *line,
+            _ => continue,
+        };
+
+        let tip = match text_labels.get(&pc) {
+            Some(label) => std::format!(
+                "nothing that runs jumps to, branches to or calls `{}`",
+                label
+            ),
+            None => {
+                "the code before it always jumps somewhere else or exits, and nothing jumps here"
+                    .into()
+            }
+        };
+
+        warnings.warn_line(line, "this code can never be run".into(), vec![tip]);
+    }
+
+    for (index, line) in file.iter().flat_map(|file| &file.lines).enumerate() {
+        let line_num = index as u32 + 1;
+
+        for (label, _) in line
+            .names()
+            .into_iter()
+            .filter(|(_, name_use)| *name_use == NameUse::Label)
+        {
+            let address = match binary.labels.get(label.text) {
+                Some(&address) => address,
+                None => continue,
+            };
+            if label.text == "main" {
+                continue;
+            }
+
+            match get_segment(address) {
+                Segment::Text | Segment::KText => {
+                    if !uses.used.contains(label.text) {
+                        warnings.warn_span(
+                            line_num,
+                            label.start,
+                            label.end,
+                            std::format!("the label `{}` is never used", label.text),
+                            vec!["nothing jumps to, branches to or calls it".into()],
+                        );
+                    }
+                }
+                _ if !uses.used.contains(label.text) => {
+                    warnings.hint_span(
+                        line_num,
+                        label.start,
+                        label.end,
+                        std::format!("the label `{}` is never used", label.text),
+                        vec!["nothing loads from or stores to it".into()],
+                    );
+                }
+                _ if !uses.read.contains(label.text) => {
+                    warnings.hint_span(
+                        line_num,
+                        label.start,
+                        label.end,
+                        std::format!("`{}` is stored to, but never loaded from", label.text),
+                        vec!["whatever is stored there is never read back".into()],
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::compile, ErrorReport};
+
+    fn reports(source: &str, check_main: bool) -> Vec<ErrorReport> {
+        let binary = compile(source);
+        let mut warnings = Warnings::new("test.s", source, 8);
+        check_dead_code(&binary, &[("test.s", source)], check_main, 8, &mut warnings);
+
+        warnings.into_reports()
+    }
+
+    /// The lines with a warning (or hint) whose message contains `about`.
+    fn warned(source: &str, check_main: bool, about: &str) -> Vec<u32> {
+        reports(source, check_main)
+            .into_iter()
+            .filter(|warning| warning.message.contains(about))
+            .map(|warning| warning.line)
+            .collect()
+    }
+
+    #[test]
+    fn warns_about_code_after_a_jump_or_return() {
+        let source = "\
+main:
+\tj\tend
+\tli\t$t0, 1
+\tli\t$t1, 2
+end:
+\tjr\t$ra
+\tli\t$t2, 3
+";
+
+        // only the start of each stretch is reported
+        assert_eq!(warned(source, true, "can never be run"), [3, 7]);
+    }
+
+    #[test]
+    fn assumes_labels_whose_address_is_taken_are_run() {
+        let source = "\
+main:
+\tla\t$t0, handler
+\tjr\t$ra
+handler:
+\tjr\t$ra
+never:
+\tjr\t$ra
+";
+
+        assert_eq!(warned(source, true, "can never be run"), [7]);
+        assert_eq!(warned(source, true, "is never used"), [6]);
+    }
+
+    #[test]
+    fn runs_the_kernel_when_compiled_with_one() {
+        let source = "\
+main:
+\tjr\t$ra
+\t.ktext
+handler:
+\tli\t$v0, 10
+\tsyscall
+";
+
+        assert_eq!(warned(source, true, "can never be run"), Vec::<u32>::new());
+        assert_eq!(warned(source, false, "can never be run"), [5]);
+    }
+
+    #[test]
+    fn hints_at_data_which_is_never_loaded() {
+        let source = "\
+main:
+\tli\t$t0, 1
+\tsw\t$t0, saved
+\tlw\t$t1, loaded
+\tjr\t$ra
+\t.data
+saved:\t.word\t0
+loaded:\t.word\t0
+unused:\t.word\t0
+";
+        let reports = reports(source, true);
+
+        let hints: Vec<_> = reports
+            .iter()
+            .map(|report| (report.line, report.message.as_str(), report.is_hint))
+            .collect();
+        assert_eq!(
+            hints,
+            [
+                (7, "`saved` is stored to, but never loaded from", true),
+                (9, "the label `unused` is never used", true),
+            ]
+        );
+    }
+}
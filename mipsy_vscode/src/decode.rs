@@ -0,0 +1,435 @@
+use mipsy_lib::{Binary, Safe, KTEXT_BOT, TEXT_BOT};
+
+use crate::registers::{RA, V0, ZERO};
+
+/// The fields of a native MIPS instruction word. Unlike `decompile`, this
+/// doesn't need the instruction set, as it only cares about which registers
+/// an instruction touches and where control flow goes next.
+#[derive(Clone, Copy, Debug)]
+pub struct Decoded {
+    pub word: u32,
+    pub opcode: u32,
+    pub rs: u32,
+    pub rt: u32,
+    pub rd: u32,
+    pub shamt: u32,
+    pub funct: u32,
+    pub imm: i16,
+    pub target: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Flow {
+    /// continues to the next instruction
+    Next,
+    /// either continues to the next instruction or branches
+    Branch(u32),
+    /// always goes somewhere else
+    Jump(u32),
+    JumpIndirect,
+    /// `jal` and friends: continues to the next instruction once the callee returns
+    Call(u32),
+    CallIndirect,
+    /// `jr $ra`
+    Return,
+}
+
+/// How the fields of an instruction word are laid out.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    R,
+    I,
+    J,
+}
+
+/// A set of general purpose registers, one bit per register.
+pub type RegisterSet = u32;
+
+pub fn register_bit(reg: u32) -> RegisterSet {
+    1 << (reg & 31)
+}
+
+pub fn registers_in(set: RegisterSet) -> impl Iterator<Item = u32> {
+    (0..32).filter(move |&reg| set & register_bit(reg) != 0)
+}
+
+const SPECIAL: u32 = 0x00;
+const REGIMM: u32 = 0x01;
+const J: u32 = 0x02;
+const JAL: u32 = 0x03;
+const BEQ: u32 = 0x04;
+const BNE: u32 = 0x05;
+const BLEZ: u32 = 0x06;
+const BGTZ: u32 = 0x07;
+const LUI: u32 = 0x0F;
+const COP1: u32 = 0x11;
+const SPECIAL2: u32 = 0x1C;
+
+const FUNCT_JR: u32 = 0x08;
+const FUNCT_JALR: u32 = 0x09;
+const FUNCT_SYSCALL: u32 = 0x0C;
+
+const COP1_MFC1: u32 = 0x00;
+const COP1_CFC1: u32 = 0x02;
+const COP1_MFHC1: u32 = 0x03;
+const COP1_MTC1: u32 = 0x04;
+const COP1_CTC1: u32 = 0x06;
+const COP1_MTHC1: u32 = 0x07;
+const COP1_BC: u32 = 0x08;
+
+impl Decoded {
+    pub fn new(word: u32) -> Self {
+        Decoded {
+            word,
+            opcode: word >> 26,
+            rs: (word >> 21) & 31,
+            rt: (word >> 16) & 31,
+            rd: (word >> 11) & 31,
+            shamt: (word >> 6) & 31,
+            funct: word & 63,
+            imm: (word & 0xFFFF) as u16 as i16,
+            target: word & 0x03FF_FFFF,
+        }
+    }
+
+    fn branch_target(&self, pc: u32) -> u32 {
+        pc.wrapping_add(4)
+            .wrapping_add((i32::from(self.imm) << 2) as u32)
+    }
+
+    pub fn format(&self) -> Format {
+        match self.opcode {
+            SPECIAL | SPECIAL2 => Format::R,
+            COP1 if self.rs != COP1_BC => Format::R,
+            J | JAL => Format::J,
+            _ => Format::I,
+        }
+    }
+
+    pub fn is_nop(&self) -> bool {
+        self.word == 0
+    }
+
+    /// The general purpose registers this instruction reads.
+    pub fn reads(&self) -> RegisterSet {
+        let rs = register_bit(self.rs);
+        let rt = register_bit(self.rt);
+
+        let set = match self.opcode {
+            SPECIAL => match self.funct {
+                // sll, srl, sra
+                0x00 | 0x02 | 0x03 => rt,
+                FUNCT_JR | FUNCT_JALR => rs,
+                FUNCT_SYSCALL => register_bit(V0),
+                // break, mfhi, mflo
+                0x0D | 0x10 | 0x12 => 0,
+                // mthi, mtlo
+                0x11 | 0x13 => rs,
+                _ => rs | rt,
+            },
+            REGIMM | BLEZ | BGTZ => rs,
+            J | JAL | LUI => 0,
+            BEQ | BNE => rs | rt,
+            COP1 => match self.rs {
+                COP1_MTC1 | COP1_CTC1 | COP1_MTHC1 => rt,
+                _ => 0,
+            },
+            SPECIAL2 => match self.funct {
+                // clz, clo
+                0x20 | 0x21 => rs,
+                _ => rs | rt,
+            },
+            // stores (sb, sh, swl, sw, swr, sc) and lwl/lwr read both
+            0x22 | 0x26 | 0x28..=0x2E | 0x38 => rs | rt,
+            _ => rs,
+        };
+
+        set & !register_bit(ZERO)
+    }
+
+    /// The general purpose registers this instruction writes.
+    pub fn writes(&self) -> RegisterSet {
+        let rt = register_bit(self.rt);
+        let rd = register_bit(self.rd);
+
+        match self.opcode {
+            SPECIAL => match self.funct {
+                FUNCT_JR | 0x0D | 0x11 | 0x13 | 0x18..=0x1B | 0x30..=0x36 => 0,
+                // assume a syscall might return something
+                FUNCT_SYSCALL => register_bit(V0),
+                _ => rd,
+            },
+            // bltzal, bgezal
+            REGIMM if self.rt & 0x10 != 0 => register_bit(RA),
+            JAL => register_bit(RA),
+            // addi, addiu, slti, sltiu, andi, ori, xori, lui, loads, ll, and
+            // sc (which sets rt to whether it succeeded)
+            0x08..=0x0F | 0x20..=0x26 | 0x30 | 0x38 => rt,
+            COP1 if matches!(self.rs, COP1_MFC1 | COP1_CFC1 | COP1_MFHC1) => rt,
+            // mul, clz, clo
+            SPECIAL2 if matches!(self.funct, 0x02 | 0x20 | 0x21) => rd,
+            _ => 0,
+        }
+    }
+
+    /// How many bytes this instruction loads or stores, and whether it
+    /// stores, if it accesses memory. The address is `imm` past `rs`.
+    pub fn memory_access(&self) -> Option<(u32, bool)> {
+        match self.opcode {
+            // lb, lbu / sb
+            0x20 | 0x24 => Some((1, false)),
+            0x28 => Some((1, true)),
+            // lh, lhu / sh
+            0x21 | 0x25 => Some((2, false)),
+            0x29 => Some((2, true)),
+            // lwl, lw, lwr, ll, lwc1 / swl, sw, swr, sc, swc1
+            0x22 | 0x23 | 0x26 | 0x30 | 0x31 => Some((4, false)),
+            0x2A | 0x2B | 0x2E | 0x38 | 0x39 => Some((4, true)),
+            // ldc1 / sdc1
+            0x35 => Some((8, false)),
+            0x3D => Some((8, true)),
+            _ => None,
+        }
+    }
+
+    /// Where control goes after executing this instruction at `pc`.
+    pub fn flow(&self, pc: u32) -> Flow {
+        match self.opcode {
+            SPECIAL => match self.funct {
+                FUNCT_JR if self.rs == RA => Flow::Return,
+                FUNCT_JR => Flow::JumpIndirect,
+                FUNCT_JALR => Flow::CallIndirect,
+                _ => Flow::Next,
+            },
+            J => Flow::Jump((pc.wrapping_add(4) & 0xF000_0000) | (self.target << 2)),
+            JAL => Flow::Call((pc.wrapping_add(4) & 0xF000_0000) | (self.target << 2)),
+            REGIMM => match self.rt {
+                // bltzal, bgezal (and their likely forms)
+                0x10..=0x13 => Flow::Call(self.branch_target(pc)),
+                // bgez $zero (`b` in some assemblers)
+                0x01 if self.rs == ZERO => Flow::Jump(self.branch_target(pc)),
+                // bltz, bgez (and their likely forms)
+                0x00..=0x03 => Flow::Branch(self.branch_target(pc)),
+                // tgei, tgeiu, tlti, tltiu, teqi, tnei
+                _ => Flow::Next,
+            },
+            // beq $x, $x (`b`)
+            BEQ if self.rs == self.rt => Flow::Jump(self.branch_target(pc)),
+            BEQ | BNE | BLEZ | BGTZ => Flow::Branch(self.branch_target(pc)),
+            COP1 if self.rs == COP1_BC => Flow::Branch(self.branch_target(pc)),
+            _ => Flow::Next,
+        }
+    }
+}
+
+fn read_word(segment: &[Safe<u8>], index: usize) -> Option<Safe<u32>> {
+    let bytes = segment.get(index..index + 4)?;
+
+    Some(
+        match (|| {
+            Some(u32::from_le_bytes([
+                *bytes[0].as_option()?,
+                *bytes[1].as_option()?,
+                *bytes[2].as_option()?,
+                *bytes[3].as_option()?,
+            ]))
+        })() {
+            Some(word) => Safe::Valid(word),
+            None => Safe::Uninitialised,
+        },
+    )
+}
+
+fn segment_instructions(
+    segment: &[Safe<u8>],
+    base: u32,
+) -> impl Iterator<Item = (u32, Decoded)> + '_ {
+    (0..segment.len() / 4).filter_map(move |i| {
+        let address = base + 4 * i as u32;
+        match read_word(segment, 4 * i)? {
+            Safe::Valid(word) => Some((address, Decoded::new(word))),
+            Safe::Uninitialised => None,
+        }
+    })
+}
+
+/// Every initialised instruction in the (user) text segment, with its address.
+pub fn text_instructions(binary: &Binary) -> impl Iterator<Item = (u32, Decoded)> + '_ {
+    segment_instructions(&binary.text, TEXT_BOT)
+}
+
+/// Every initialised instruction in the kernel text segment, with its address.
+pub fn ktext_instructions(binary: &Binary) -> impl Iterator<Item = (u32, Decoded)> + '_ {
+    segment_instructions(&binary.ktext, KTEXT_BOT)
+}
+
+/// The instruction word at an address in the text or kernel text segment.
+pub fn word_at(binary: &Binary, address: u32) -> Option<u32> {
+    let (segment, base) = if address >= KTEXT_BOT {
+        (&binary.ktext, KTEXT_BOT)
+    } else {
+        (&binary.text, TEXT_BOT)
+    };
+
+    let index = address.checked_sub(base)? as usize;
+    match read_word(segment, index)? {
+        Safe::Valid(word) => Some(word),
+        Safe::Uninitialised => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registers::SP;
+
+    const T0: u32 = 8;
+    const T1: u32 = 9;
+    const T2: u32 = 10;
+
+    fn registers(set: RegisterSet) -> Vec<u32> {
+        registers_in(set).collect()
+    }
+
+    #[test]
+    fn decodes_arithmetic() {
+        // add $t0, $t1, $t2
+        let add = Decoded::new(0x012A_4020);
+        assert_eq!(add.format(), Format::R);
+        assert_eq!(registers(add.reads()), [T1, T2]);
+        assert_eq!(registers(add.writes()), [T0]);
+        assert_eq!(add.flow(TEXT_BOT), Flow::Next);
+
+        // addi $t0, $zero, 5
+        let addi = Decoded::new(0x2008_0005);
+        assert_eq!(registers(addi.reads()), []);
+        assert_eq!(registers(addi.writes()), [T0]);
+    }
+
+    #[test]
+    fn decodes_loads_and_stores() {
+        // lw $t0, 4($sp)
+        let lw = Decoded::new(0x8FA8_0004);
+        assert_eq!(registers(lw.reads()), [SP]);
+        assert_eq!(registers(lw.writes()), [T0]);
+        assert_eq!(lw.memory_access(), Some((4, false)));
+
+        // sw $t0, 4($sp)
+        let sw = Decoded::new(0xAFA8_0004);
+        assert_eq!(registers(sw.reads()), [T0, SP]);
+        assert_eq!(registers(sw.writes()), []);
+        assert_eq!(sw.memory_access(), Some((4, true)));
+
+        // ll $t0, 0($t1)
+        let ll = Decoded::new(0xC128_0000);
+        assert_eq!(registers(ll.reads()), [T1]);
+        assert_eq!(registers(ll.writes()), [T0]);
+
+        // sc $t0, 0($t1)
+        let sc = Decoded::new(0xE128_0000);
+        assert_eq!(registers(sc.reads()), [T0, T1]);
+        assert_eq!(registers(sc.writes()), [T0]);
+        assert_eq!(sc.memory_access(), Some((4, true)));
+    }
+
+    #[test]
+    fn decodes_coprocessor_moves() {
+        // mfc1 $t0, $f2
+        let mfc1 = Decoded::new(0x4408_1000);
+        assert_eq!(registers(mfc1.reads()), []);
+        assert_eq!(registers(mfc1.writes()), [T0]);
+
+        // mtc1 $t0, $f2
+        let mtc1 = Decoded::new(0x4488_1000);
+        assert_eq!(registers(mtc1.reads()), [T0]);
+        assert_eq!(registers(mtc1.writes()), []);
+
+        // cfc1 $t0, $31
+        let cfc1 = Decoded::new(0x4448_F800);
+        assert_eq!(registers(cfc1.reads()), []);
+        assert_eq!(registers(cfc1.writes()), [T0]);
+
+        // ctc1 $t0, $31
+        let ctc1 = Decoded::new(0x44C8_F800);
+        assert_eq!(registers(ctc1.reads()), [T0]);
+        assert_eq!(registers(ctc1.writes()), []);
+    }
+
+    #[test]
+    fn decodes_jumps() {
+        let pc = TEXT_BOT + 0x10;
+
+        // j 0x00400000
+        assert_eq!(Decoded::new(0x0810_0000).flow(pc), Flow::Jump(TEXT_BOT));
+
+        // jal 0x00400000
+        let jal = Decoded::new(0x0C10_0000);
+        assert_eq!(jal.format(), Format::J);
+        assert_eq!(jal.flow(pc), Flow::Call(TEXT_BOT));
+        assert_eq!(registers(jal.writes()), [RA]);
+
+        // jr $ra
+        let jr = Decoded::new(0x03E0_0008);
+        assert_eq!(jr.flow(pc), Flow::Return);
+        assert_eq!(registers(jr.reads()), [RA]);
+        assert_eq!(registers(jr.writes()), []);
+
+        // jr $t0
+        assert_eq!(Decoded::new(0x0100_0008).flow(pc), Flow::JumpIndirect);
+
+        // jalr $t0
+        let jalr = Decoded::new(0x0100_F809);
+        assert_eq!(jalr.flow(pc), Flow::CallIndirect);
+        assert_eq!(registers(jalr.writes()), [RA]);
+    }
+
+    #[test]
+    fn decodes_branches() {
+        let pc = TEXT_BOT;
+        let target = pc + 4 + 16;
+
+        // beq $t0, $t1, +4
+        assert_eq!(Decoded::new(0x1109_0004).flow(pc), Flow::Branch(target));
+        // beq $zero, $zero, +4 (b)
+        assert_eq!(Decoded::new(0x1000_0004).flow(pc), Flow::Jump(target));
+        // bgez $t0, +4
+        assert_eq!(Decoded::new(0x0501_0004).flow(pc), Flow::Branch(target));
+        // bgez $zero, +4
+        assert_eq!(Decoded::new(0x0401_0004).flow(pc), Flow::Jump(target));
+        // bc1t +4
+        assert_eq!(Decoded::new(0x4501_0004).flow(pc), Flow::Branch(target));
+        // beq $t0, $t1, -1
+        assert_eq!(Decoded::new(0x1109_FFFF).flow(pc), Flow::Branch(pc));
+
+        // bgezal $t0, +4
+        let bgezal = Decoded::new(0x0511_0004);
+        assert_eq!(bgezal.flow(pc), Flow::Call(target));
+        assert_eq!(registers(bgezal.reads()), [T0]);
+        assert_eq!(registers(bgezal.writes()), [RA]);
+    }
+
+    #[test]
+    fn decodes_traps() {
+        // tgei $t0, 5 and teqi $t0, 5
+        for word in [0x0508_0005, 0x050C_0005] {
+            let trap = Decoded::new(word);
+            assert_eq!(trap.flow(TEXT_BOT), Flow::Next);
+            assert_eq!(registers(trap.reads()), [T0]);
+            assert_eq!(registers(trap.writes()), []);
+        }
+
+        // teq $t0, $t1
+        let teq = Decoded::new(0x0109_0034);
+        assert_eq!(teq.flow(TEXT_BOT), Flow::Next);
+        assert_eq!(registers(teq.reads()), [T0, T1]);
+        assert_eq!(registers(teq.writes()), []);
+    }
+
+    #[test]
+    fn decodes_syscalls() {
+        let syscall = Decoded::new(0x0000_000C);
+        assert_eq!(registers(syscall.reads()), [V0]);
+        assert_eq!(registers(syscall.writes()), [V0]);
+        assert!(Decoded::new(0).is_nop());
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{
+    lexer::{display_col, tab_size_of, tokenise_line, TokenKind},
+    parsed::{ParsedFile, ParsedLine},
+    registers::register_index,
+    ErrorReport,
+};
+
+/// Collects warnings about a single file, converting byte ranges of lines into
+/// the columns mipsy's own error reports use.
+pub struct Warnings<'a> {
+    file_tag: &'a str,
+    file: ParsedFile<'a>,
+    tab_size: u32,
+    reports: Vec<ErrorReport>,
+}
+
+impl<'a> Warnings<'a> {
+    /// `tab_size` is the one mipsy was configured with, which the file can
+    /// override.
+    pub fn new(file_tag: &'a str, source: &'a str, tab_size: u32) -> Self {
+        Warnings {
+            file_tag,
+            file: ParsedFile::parse(file_tag, source, tab_size),
+            tab_size: tab_size_of(source, tab_size),
+            reports: vec![],
+        }
+    }
+
+    pub fn file_tag(&self) -> &str {
+        self.file_tag
+    }
+
+    /// The (1-indexed) line of the file.
+    pub fn line(&self, line: u32) -> Option<&ParsedLine<'a>> {
+        self.file.lines.get(line.checked_sub(1)? as usize)
+    }
+
+    pub fn warn_span(
+        &mut self,
+        line: u32,
+        start: usize,
+        end: usize,
+        message: String,
+        tips: Vec<String>,
+    ) {
+        self.report(line, start, end, message, tips, false);
+    }
+
+    /// Like `warn_span`, but for something that's less likely to be a mistake.
+    pub fn hint_span(
+        &mut self,
+        line: u32,
+        start: usize,
+        end: usize,
+        message: String,
+        tips: Vec<String>,
+    ) {
+        self.report(line, start, end, message, tips, true);
+    }
+
+    fn report(
+        &mut self,
+        line: u32,
+        start: usize,
+        end: usize,
+        message: String,
+        tips: Vec<String>,
+        is_hint: bool,
+    ) {
+        let text = self.line(line).map_or("", |line| line.text);
+
+        if self
+            .reports
+            .iter()
+            .any(|report| report.line == line && report.message == message)
+        {
+            // e.g. several instructions from one pseudo-instruction
+            return;
+        }
+
+        self.reports.push(ErrorReport {
+            message,
+            localised: true,
+            tips,
+            file_tag: self.file_tag.to_owned(),
+            line,
+            col: display_col(text, start, self.tab_size),
+            col_end: display_col(text, end.max(start + 1), self.tab_size),
+            is_warning: true,
+            is_hint,
+            is_multfile_related: false,
+        });
+    }
+
+    /// Warns about the whole statement on a line.
+    pub fn warn_line(&mut self, line: u32, message: String, tips: Vec<String>) {
+        let (start, end) = self.line(line).map_or((0, 0), |line| line.statement_span());
+        self.warn_span(line, start, end, message, tips);
+    }
+
+    /// Warns about a register operand on a line, or the whole statement if
+    /// the register isn't written out explicitly (e.g. `$at` in a pseudo-instruction).
+    pub fn warn_register(&mut self, line: u32, register: u32, message: String, tips: Vec<String>) {
+        let span = self.line(line).and_then(|line| {
+            tokenise_line(line.text)
+                .into_iter()
+                .find(|token| {
+                    token.kind == TokenKind::Register
+                        && register_index(token.text) == Some(register)
+                })
+                .map(|token| (token.start, token.end))
+        });
+
+        match span {
+            Some((start, end)) => self.warn_span(line, start, end, message, tips),
+            None => self.warn_line(line, message, tips),
+        }
+    }
+
+    pub fn into_reports(self) -> Vec<ErrorReport> {
+        self.reports
+    }
+}
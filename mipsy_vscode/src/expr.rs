@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use mipsy_parser::{MpConstValue, MpItem};
+
+use crate::parsed::ParsedFile;
+
+/// The value of a character literal such as `'a'` or `'\n'`.
+pub fn parse_char(text: &str) -> Option<i64> {
+    let inner = text.strip_prefix('\'')?.strip_suffix('\'')?;
+    let mut chars = inner.chars();
+
+    let value = match chars.next()? {
+        '\\' => match chars.next()? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            c => c,
+        },
+        c => c,
+    };
+
+    chars.next().is_none().then_some(value as i64)
+}
+
+/// Works out the value of every constant that can be, given how to evaluate
+/// a definition from the values of the constants worked out so far.
+fn resolve_constants<V>(
+    definitions: &[(&str, V)],
+    evaluate: impl Fn(&V, &HashMap<String, i64>) -> Option<i64>,
+) -> HashMap<String, i64> {
+    let mut values = HashMap::new();
+
+    // constants can refer to constants defined later on, so keep going
+    // until nothing new can be resolved
+    loop {
+        let before = values.len();
+
+        for (name, value) in definitions {
+            if values.contains_key(*name) {
+                continue;
+            }
+            if let Some(result) = evaluate(value, &values) {
+                values.insert(name.to_string(), result);
+            }
+        }
+
+        if values.len() == before {
+            return values;
+        }
+    }
+}
+
+/// Evaluates a constant expression as mipsy parsed it, looking up constants
+/// with `lookup`.
+pub fn evaluate_const(value: &MpConstValue, lookup: &impl Fn(&str) -> Option<i64>) -> Option<i64> {
+    let binary = |lhs: &MpConstValue, rhs: &MpConstValue| {
+        Some((evaluate_const(lhs, lookup)?, evaluate_const(rhs, lookup)?))
+    };
+
+    Some(match value {
+        MpConstValue::Value(value) => *value as i64,
+        MpConstValue::Const(name) => lookup(name)?,
+        MpConstValue::Minus(value) => evaluate_const(value, lookup)?.wrapping_neg(),
+        MpConstValue::Neg(value) => !evaluate_const(value, lookup)?,
+        MpConstValue::Sum(lhs, rhs) => {
+            let (lhs, rhs) = binary(lhs, rhs)?;
+            lhs.wrapping_add(rhs)
+        }
+        MpConstValue::Sub(lhs, rhs) => {
+            let (lhs, rhs) = binary(lhs, rhs)?;
+            lhs.wrapping_sub(rhs)
+        }
+        MpConstValue::Mult(lhs, rhs) => {
+            let (lhs, rhs) = binary(lhs, rhs)?;
+            lhs.wrapping_mul(rhs)
+        }
+        MpConstValue::Div(lhs, rhs) => {
+            let (lhs, rhs) = binary(lhs, rhs)?;
+            lhs.checked_div(rhs)?
+        }
+        MpConstValue::Mod(lhs, rhs) => {
+            let (lhs, rhs) = binary(lhs, rhs)?;
+            lhs.checked_rem(rhs)?
+        }
+        MpConstValue::And(lhs, rhs) => {
+            let (lhs, rhs) = binary(lhs, rhs)?;
+            lhs & rhs
+        }
+        MpConstValue::Or(lhs, rhs) => {
+            let (lhs, rhs) = binary(lhs, rhs)?;
+            lhs | rhs
+        }
+        MpConstValue::Xor(lhs, rhs) => {
+            let (lhs, rhs) = binary(lhs, rhs)?;
+            lhs ^ rhs
+        }
+        MpConstValue::Shl(lhs, rhs) => {
+            let (lhs, rhs) = binary(lhs, rhs)?;
+            lhs.checked_shl(u32::try_from(rhs).ok()?)?
+        }
+        MpConstValue::Shr(lhs, rhs) => {
+            let (lhs, rhs) = binary(lhs, rhs)?;
+            lhs.checked_shr(u32::try_from(rhs).ok()?)?
+        }
+    })
+}
+
+/// The value of every constant mipsy parsed in the given files, where it can
+/// be worked out without compiling the program.
+pub fn parsed_constant_values<'p>(
+    files: impl IntoIterator<Item = &'p ParsedFile<'p>>,
+) -> HashMap<String, i64> {
+    let definitions: Vec<(&str, &MpConstValue)> = files
+        .into_iter()
+        .flat_map(|file| &file.lines)
+        .filter_map(|line| line.items.as_ref().ok())
+        .flatten()
+        .filter_map(|item| match item {
+            MpItem::Constant(constant) => Some((constant.label(), constant.value())),
+            _ => None,
+        })
+        .collect();
+
+    resolve_constants(&definitions, |value, values| {
+        evaluate_const(value, &|name| values.get(name).copied())
+    })
+}
@@ -0,0 +1,404 @@
+use mipsy_parser::{MpDirective, MpItem};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    lexer::{display_col, tab_size_of, tokenise_line, Token, TokenKind},
+    parsed::{NameUse, ParsedFile, ParsedLine},
+    registers::{register_index, register_name},
+};
+
+/// How registers are written in formatted code.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RegisterStyle {
+    /// `$t0`
+    #[default]
+    Names,
+    /// `$8`
+    Numbers,
+    /// however they were written
+    Preserve,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct FormatOptions {
+    pub registers: RegisterStyle,
+    /// whether to line up columns with tabs rather than spaces
+    pub use_tabs: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            registers: RegisterStyle::Names,
+            use_tabs: true,
+        }
+    }
+}
+
+/// A line of code split into the parts which are lined up in columns.
+struct CodeLine {
+    /// labels, or a whole constant definition, which start at the left margin
+    prefix: String,
+    /// the mnemonic or directive
+    head: Option<String>,
+    operands: String,
+    comment: Option<String>,
+}
+
+enum Line {
+    /// blank lines, and lines we can't make sense of, which are left alone
+    /// (apart from trailing whitespace)
+    Verbatim(String),
+    Comment {
+        indented: bool,
+        text: String,
+    },
+    Code(CodeLine),
+}
+
+fn register_spelling(register: &Token, style: RegisterStyle) -> String {
+    match (style, register_index(register.text)) {
+        (RegisterStyle::Names, Some(index)) => register_name(index),
+        (RegisterStyle::Numbers, Some(index)) => std::format!("${}", index),
+        _ => register.text.to_string(),
+    }
+}
+
+/// Writes out a run of tokens as they were written, apart from registers.
+fn respell(text: &str, tokens: &[Token], style: RegisterStyle) -> String {
+    let mut result = String::new();
+
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 {
+            result.push_str(&text[tokens[i - 1].end..token.start]);
+        }
+
+        if token.kind == TokenKind::Register {
+            result.push_str(&register_spelling(token, style));
+        } else {
+            result.push_str(token.text);
+        }
+    }
+
+    result
+}
+
+/// How many comma-separated operands mipsy parsed for an instruction or
+/// directive.
+fn operand_count(item: &MpItem) -> Option<usize> {
+    Some(match item {
+        MpItem::Instruction(instruction) => instruction.arguments().len(),
+        MpItem::Directive(directive) => match directive {
+            MpDirective::Text | MpDirective::Data | MpDirective::KText | MpDirective::KData => 0,
+            MpDirective::Ascii(_)
+            | MpDirective::Asciiz(_)
+            | MpDirective::Align(_)
+            | MpDirective::Space(_)
+            | MpDirective::Globl(_) => 1,
+            MpDirective::Byte(values) | MpDirective::Half(values) | MpDirective::Word(values) => {
+                values.len()
+            }
+            MpDirective::Float(values) => values.len(),
+            MpDirective::Double(values) => values.len(),
+        },
+        MpItem::Label(_) | MpItem::Constant(_) => return None,
+    })
+}
+
+/// Splits the tokens after a mnemonic or directive at the commas between
+/// operands.
+fn split_operands<'t, 'a>(tokens: &'t [Token<'a>]) -> Vec<&'t [Token<'a>]> {
+    if tokens.is_empty() {
+        return vec![];
+    }
+
+    let mut depth = 0;
+    let mut operands = vec![];
+    let mut start = 0;
+
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::LParen => depth += 1,
+            TokenKind::RParen => depth -= 1,
+            TokenKind::Comma if depth <= 0 => {
+                operands.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    operands.push(&tokens[start..]);
+
+    operands
+}
+
+/// Splits a line mipsy parsed into columns. Lines mipsy can't parse, or
+/// whose parse can't be matched up with where things are on the line, are
+/// left alone.
+fn classify(line: &ParsedLine, style: RegisterStyle) -> Line {
+    let verbatim = || Line::Verbatim(line.text.trim_end().to_string());
+    let trimmed = line.text.trim();
+
+    let (items, names) = match (&line.items, line.lined_up_names()) {
+        (Ok(items), Some(names)) if !trimmed.is_empty() && !trimmed.starts_with("#!") => {
+            (items, names)
+        }
+        _ => return verbatim(),
+    };
+
+    let mut tokens = tokenise_line(line.text);
+    let comment = match tokens.last() {
+        Some(token) if token.kind == TokenKind::Comment => tokens.pop(),
+        _ => None,
+    }
+    .map(|comment| comment.text.trim_end().to_string());
+
+    if items.is_empty() {
+        return Line::Comment {
+            indented: line.text.starts_with(char::is_whitespace),
+            text: comment.unwrap_or_default(),
+        };
+    }
+
+    let labels: Vec<Token> = names
+        .iter()
+        .filter(|(_, name_use)| *name_use == NameUse::Label)
+        .map(|&(token, _)| token)
+        .collect();
+
+    let mut prefix = labels
+        .iter()
+        .map(|label| std::format!("{}:", label.text))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // everything after the labels (and their colons)
+    let rest = match labels.last() {
+        Some(last) => match tokens.iter().position(|token| token.start >= last.end) {
+            Some(colon) => &tokens[colon + 1..],
+            None => return verbatim(),
+        },
+        None => &tokens[..],
+    };
+
+    let statement = items.iter().find(|item| !matches!(item, MpItem::Label(_)));
+
+    let (head, operands) = match (statement, rest) {
+        (None, []) => (None, String::new()),
+        (Some(MpItem::Constant(_)), [name, equals, value @ ..])
+            if equals.kind == TokenKind::Equals =>
+        {
+            if !prefix.is_empty() {
+                prefix.push(' ');
+            }
+            prefix.push_str(&std::format!(
+                "{} = {}",
+                name.text,
+                respell(line.text, value, style)
+            ));
+            (None, String::new())
+        }
+        (Some(item), [head, operands @ ..]) => {
+            let operands = split_operands(operands);
+            if Some(operands.len()) != operand_count(item) {
+                return verbatim();
+            }
+
+            (
+                Some(head.text.to_string()),
+                operands
+                    .iter()
+                    .map(|operand| respell(line.text, operand, style))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )
+        }
+        _ => return verbatim(),
+    };
+
+    Line::Code(CodeLine {
+        prefix,
+        head,
+        operands,
+        comment,
+    })
+}
+
+struct Writer {
+    text: String,
+    col: u32,
+    tab_size: u32,
+    use_tabs: bool,
+}
+
+impl Writer {
+    fn push(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.col = display_col(&self.text, self.text.len(), self.tab_size) - 1;
+    }
+
+    /// Pads to (at least) `target`, always leaving some whitespace unless
+    /// nothing has been written yet.
+    fn pad_to(&mut self, target: u32) {
+        if self.text.is_empty() && target == 0 {
+            return;
+        }
+
+        let target = if self.col >= target {
+            self.next_stop(self.col)
+        } else {
+            target
+        };
+
+        if self.use_tabs {
+            while self.col < target {
+                self.push("\t");
+            }
+        } else {
+            let spaces = " ".repeat((target - self.col) as usize);
+            self.push(&spaces);
+        }
+    }
+
+    /// The first tab stop after `col`.
+    fn next_stop(&self, col: u32) -> u32 {
+        (col / self.tab_size + 1) * self.tab_size
+    }
+}
+
+fn width(text: &str, tab_size: u32) -> u32 {
+    display_col(text, text.len(), tab_size) - 1
+}
+
+/// Lays out one run of consecutive lines of code, lining up their columns.
+fn format_block(block: &[CodeLine], tab_size: u32, use_tabs: bool) -> Vec<String> {
+    let writer = || Writer {
+        text: String::new(),
+        col: 0,
+        tab_size,
+        use_tabs,
+    };
+    let round_up = |col: u32| col.div_ceil(tab_size) * tab_size;
+
+    // mnemonics start at the first tab stop clear of every label in the block
+    let indent = block
+        .iter()
+        .filter(|line| line.head.is_some())
+        .map(|line| round_up(width(&line.prefix, tab_size) + 1))
+        .max()
+        .unwrap_or(0)
+        .max(tab_size);
+    let head_width = block
+        .iter()
+        .filter(|line| !line.operands.is_empty())
+        .filter_map(|line| line.head.as_ref())
+        .map(|head| width(head, tab_size))
+        .max()
+        .unwrap_or(0);
+    let operands_col = indent + round_up(head_width + 1);
+
+    let code: Vec<Writer> = block
+        .iter()
+        .map(|line| {
+            let mut code = writer();
+            code.push(&line.prefix);
+
+            if let Some(head) = &line.head {
+                code.pad_to(indent);
+                code.push(head);
+
+                if !line.operands.is_empty() {
+                    code.pad_to(operands_col);
+                    code.push(&line.operands);
+                }
+            }
+
+            code
+        })
+        .collect();
+
+    let comment_col = block
+        .iter()
+        .zip(&code)
+        .filter(|(line, code)| line.comment.is_some() && !code.text.is_empty())
+        .map(|(_, code)| round_up(code.col + 1))
+        .max()
+        .unwrap_or(0);
+
+    block
+        .iter()
+        .zip(code)
+        .map(|(line, mut code)| {
+            if let Some(comment) = &line.comment {
+                code.pad_to(comment_col);
+                code.push(comment);
+            }
+            code.text
+        })
+        .collect()
+}
+
+/// Reformats a MIPS program, lining up labels, mnemonics, operands and
+/// comments into columns. Only whitespace and the spelling of registers
+/// change, and every line stays on the same line number, so the program
+/// compiles to exactly the same binary. `tab_size` is the one mipsy is
+/// configured with, which the file can override.
+pub fn format_program(
+    source: &str,
+    file_tag: &str,
+    options: &FormatOptions,
+    tab_size: u32,
+) -> String {
+    let tab_size = tab_size_of(source, tab_size);
+    let newline = if source.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    let lines: Vec<Line> = ParsedFile::parse(file_tag, source, tab_size)
+        .lines
+        .iter()
+        .map(|line| classify(line, options.registers))
+        .collect();
+
+    let mut formatted: Vec<String> = vec![];
+    let mut block: Vec<CodeLine> = vec![];
+
+    for line in lines {
+        let line = match line {
+            Line::Code(code) => {
+                block.push(code);
+                continue;
+            }
+            line => line,
+        };
+
+        formatted.extend(format_block(&block, tab_size, options.use_tabs));
+        block.clear();
+
+        formatted.push(match line {
+            Line::Verbatim(text) => text,
+            Line::Comment { indented, text } if indented => {
+                let indent = if options.use_tabs {
+                    "\t".to_string()
+                } else {
+                    " ".repeat(tab_size as usize)
+                };
+                indent + &text
+            }
+            Line::Comment { text, .. } => text,
+            Line::Code(_) => unreachable!(),
+        });
+    }
+    formatted.extend(format_block(&block, tab_size, options.use_tabs));
+
+    let mut result = formatted.join(newline);
+    if source.ends_with('\n') {
+        result.push_str(newline);
+    }
+
+    result
+}
@@ -0,0 +1,277 @@
+use mipsy_lib::{decompile::decompile_inst_into_parts, Binary, InstSet};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    decode::{word_at, Decoded, Format},
+    lexer::utf16_col,
+    parsed::ParsedFile,
+    registers::register_name,
+    signature_help::{operand_forms, OperandForm},
+};
+
+/// One field of an instruction word, e.g. `rs` or `funct`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InstructionField {
+    name: String,
+    value: u32,
+    bits: u32,
+    /// what the value means, e.g. the register name
+    meaning: Option<String>,
+}
+
+/// One instruction word produced by a line of source.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AssembledInstruction {
+    address: u32,
+    word: u32,
+    /// the instruction as mipsy would disassemble it, e.g. `addi $t0, $t1, 5`
+    assembly: String,
+    format: String,
+    fields: Vec<InstructionField>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InstructionHover {
+    mnemonic: String,
+    is_pseudo: bool,
+    /// what the instruction does, from the instruction set
+    description: Option<String>,
+    /// every way of writing its operands, which is all there is to show
+    /// about the line if the program doesn't compile
+    forms: Vec<OperandForm>,
+    /// empty if the program doesn't compile
+    assembled: Vec<AssembledInstruction>,
+}
+
+fn field(name: &str, value: u32, bits: u32, meaning: Option<String>) -> InstructionField {
+    InstructionField {
+        name: name.into(),
+        value,
+        bits,
+        meaning,
+    }
+}
+
+fn fields_of(inst: &Decoded) -> (Format, Vec<InstructionField>) {
+    let opcode = field("opcode", inst.opcode, 6, None);
+    let register = |name: &str, reg: u32| field(name, reg, 5, Some(register_name(reg)));
+
+    let fields = match inst.format() {
+        Format::R => vec![
+            opcode,
+            register("rs", inst.rs),
+            register("rt", inst.rt),
+            register("rd", inst.rd),
+            field("shamt", inst.shamt, 5, None),
+            field("funct", inst.funct, 6, None),
+        ],
+        Format::I => vec![
+            opcode,
+            register("rs", inst.rs),
+            register("rt", inst.rt),
+            field(
+                "imm",
+                u32::from(inst.imm as u16),
+                16,
+                Some(inst.imm.to_string()),
+            ),
+        ],
+        Format::J => vec![
+            opcode,
+            field(
+                "target",
+                inst.target,
+                26,
+                Some(std::format!("0x{:08X}", inst.target << 2)),
+            ),
+        ],
+    };
+
+    (inst.format(), fields)
+}
+
+/// Describes the instruction whose mnemonic is at a (0-indexed, UTF-16)
+/// position in `source`, as mipsy parses the line. `binary` is the compiled
+/// program, if it compiles, and is used to show what the line assembles to.
+pub fn instruction_hover(
+    iset: &InstSet,
+    binary: Option<&Binary>,
+    source: &str,
+    file_tag: &str,
+    line: u32,
+    col: u32,
+    tab_size: u32,
+) -> Option<InstructionHover> {
+    let file = ParsedFile::parse(file_tag, source, tab_size);
+    let parsed_line = file.lines.get(line as usize)?;
+    let mnemonic = parsed_line.mnemonic().filter(|mnemonic| {
+        utf16_col(parsed_line.text, mnemonic.start) <= col
+            && col <= utf16_col(parsed_line.text, mnemonic.end)
+    })?;
+
+    let native = iset
+        .native_set()
+        .iter()
+        .filter(|sig| sig.name().eq_ignore_ascii_case(mnemonic.text))
+        .map(|sig| sig.meta());
+    let pseudo = iset
+        .pseudo_set()
+        .iter()
+        .filter(|sig| sig.name().eq_ignore_ascii_case(mnemonic.text))
+        .map(|sig| sig.meta());
+    let is_native = native.clone().next().is_some();
+
+    let metas: Vec<_> = native.chain(pseudo).collect();
+    if metas.is_empty() {
+        return None;
+    }
+
+    let description = metas
+        .iter()
+        .find_map(|meta| meta.desc_long().or(meta.desc_short()))
+        .map(String::from);
+
+    let mut addresses: Vec<u32> = binary
+        .map(|binary| {
+            binary
+                .line_numbers
+                .iter()
+                .filter(|(_, (tag, line_num))| **tag == *file_tag && *line_num == line + 1)
+                .map(|(&address, _)| address)
+                .collect()
+        })
+        .unwrap_or_default();
+    addresses.sort_unstable();
+
+    let assembled: Vec<AssembledInstruction> = binary
+        .into_iter()
+        .flat_map(|binary| {
+            addresses.iter().filter_map(move |&address| {
+                let word = word_at(binary, address)?;
+                let decompiled = decompile_inst_into_parts(binary, iset, word, address);
+                let (format, fields) = fields_of(&Decoded::new(word));
+
+                Some(AssembledInstruction {
+                    address,
+                    word,
+                    assembly: std::format!(
+                        "{} {}",
+                        decompiled
+                            .inst_name
+                            .unwrap_or("[unknown instruction]".into()),
+                        decompiled.arguments.join(", ")
+                    )
+                    .trim_end()
+                    .to_string(),
+                    format: std::format!("{:?}", format),
+                    fields,
+                })
+            })
+        })
+        .collect();
+
+    // a real instruction can still be used as a pseudo-instruction, e.g.
+    // `add $t0, $t1, 5` becomes an `addi`
+    let is_pseudo = match assembled.as_slice() {
+        [] => !is_native,
+        [only] => !only
+            .assembly
+            .split_whitespace()
+            .next()
+            .is_some_and(|name| name.eq_ignore_ascii_case(mnemonic.text)),
+        _ => true,
+    };
+
+    Some(InstructionHover {
+        mnemonic: mnemonic.text.to_ascii_lowercase(),
+        is_pseudo,
+        description,
+        forms: operand_forms(iset, mnemonic.text),
+        assembled,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::compile;
+
+    fn hover(source: &str, compiles: bool, line: u32, col: u32) -> Option<InstructionHover> {
+        let iset = &mipsy_instructions::inst_set();
+        let binary = compiles.then(|| compile(source));
+
+        instruction_hover(iset, binary.as_ref(), source, "test.s", line, col, 8)
+    }
+
+    fn field_values(inst: &AssembledInstruction) -> Vec<(&str, u32)> {
+        inst.fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.value))
+            .collect()
+    }
+
+    #[test]
+    fn expands_pseudo_instructions_with_the_operands_given() {
+        let hover = hover("main:\n\tli $t0, 0x12345678\n\tjr $ra\n", true, 1, 2).unwrap();
+
+        assert_eq!(hover.mnemonic, "li");
+        assert!(hover.is_pseudo);
+        assert_eq!(hover.assembled.len(), 2);
+
+        let (upper, lower) = (&hover.assembled[0], &hover.assembled[1]);
+        assert_eq!(upper.fields.last().unwrap().value, 0x1234);
+        assert_eq!(lower.fields.last().unwrap().value, 0x5678);
+        let rt = lower
+            .fields
+            .iter()
+            .find(|field| field.name == "rt")
+            .unwrap();
+        assert_eq!(rt.meaning.as_deref(), Some("$t0"));
+        assert_eq!(lower.address, upper.address + 4);
+    }
+
+    #[test]
+    fn splits_words_into_fields() {
+        let source = "main:\n\tadd $t0, $t1, $t2\n\taddi $t0, $t1, -5\n\tj main\n";
+
+        let add = &hover(source, true, 1, 1).unwrap().assembled[0];
+        assert_eq!((add.word, add.format.as_str()), (0x012A_4020, "R"));
+        assert_eq!(
+            field_values(add),
+            [
+                ("opcode", 0),
+                ("rs", 9),
+                ("rt", 10),
+                ("rd", 8),
+                ("shamt", 0),
+                ("funct", 0x20)
+            ]
+        );
+
+        let addi = &hover(source, true, 2, 1).unwrap().assembled[0];
+        assert_eq!((addi.word, addi.format.as_str()), (0x2128_FFFB, "I"));
+        assert_eq!(addi.fields[3].meaning.as_deref(), Some("-5"));
+
+        let j = &hover(source, true, 3, 1).unwrap().assembled[0];
+        assert_eq!((j.word, j.format.as_str()), (0x0810_0000, "J"));
+        assert_eq!(j.fields[1].meaning.as_deref(), Some("0x00400000"));
+    }
+
+    #[test]
+    fn shows_forms_when_the_program_doesnt_compile() {
+        let hover = hover("main:\n\tli $t0, 1\n\tj nowhere\n", false, 1, 1).unwrap();
+
+        assert!(hover.is_pseudo);
+        assert!(hover.assembled.is_empty());
+        assert!(!hover.forms.is_empty());
+    }
+
+    #[test]
+    fn only_describes_mnemonics_mipsy_parses() {
+        let source = "main:\n\tli $t0, 1\n\tadd $t0 $t1\n";
+
+        assert!(hover(source, false, 1, 5).is_none());
+        assert!(hover(source, false, 2, 2).is_none());
+        assert!(hover(source, false, 0, 1).is_none());
+    }
+}
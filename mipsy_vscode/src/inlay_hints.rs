@@ -0,0 +1,126 @@
+use mipsy_lib::Binary;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    decode::{word_at, Decoded, Flow, Format},
+    lexer::utf16_col,
+    parsed::ParsedFile,
+    symbols::{Symbol, SymbolKind},
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum InlayHintKind {
+    Address,
+    Value,
+    BranchOffset,
+}
+
+/// The resolved value of a label or constant, shown just after where it's used.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InlayHint {
+    /// 0-indexed, with the column (in UTF-16 code units) at the end of the
+    /// label or constant
+    pub line: u32,
+    pub col: u32,
+    pub label: String,
+    pub kind: InlayHintKind,
+    pub tooltip: String,
+}
+
+/// The (encoded) offset of the branch on `line` which goes to `target`, in
+/// instructions relative to the one after the branch. Pseudo-instructions
+/// like `bge` compile to several instructions, so every instruction from the
+/// line is checked.
+fn branch_offset(binary: &Binary, file_tag: &str, line: u32, target: u32) -> Option<i16> {
+    let mut addresses: Vec<u32> = binary
+        .line_numbers
+        .iter()
+        .filter(|(_, (tag, line_num))| &**tag == file_tag && *line_num == line + 1)
+        .map(|(&address, _)| address)
+        .collect();
+    addresses.sort_unstable();
+
+    addresses.into_iter().rev().find_map(|address| {
+        let inst = Decoded::new(word_at(binary, address)?);
+        let goes_to_target = match inst.flow(address) {
+            Flow::Branch(dest) | Flow::Jump(dest) | Flow::Call(dest) => dest == target,
+            _ => false,
+        };
+
+        // `j` and `jal` are absolute, so have no offset
+        (inst.format() == Format::I && goes_to_target).then_some(inst.imm)
+    })
+}
+
+/// Hints for every use of a label or constant in the file tagged `file_tag`,
+/// giving what it resolved to in the compiled program. `tab_size` is the one
+/// mipsy was configured with.
+pub fn inlay_hints(
+    binary: &Binary,
+    symbols: &[Symbol],
+    source: &str,
+    file_tag: &str,
+    tab_size: u32,
+) -> Vec<InlayHint> {
+    let mut hints = vec![];
+
+    for (line_num, line) in ParsedFile::parse(file_tag, source, tab_size)
+        .lines
+        .iter()
+        .enumerate()
+    {
+        let line_num = line_num as u32;
+
+        for (token, name_use) in line.symbol_names() {
+            if name_use.is_definition() {
+                continue;
+            }
+
+            let symbol = match symbols.iter().find(|symbol| symbol.name == token.text) {
+                Some(symbol) => symbol,
+                None => continue,
+            };
+
+            let (label, kind, tooltip) = match (symbol.kind, symbol.address, symbol.value) {
+                (SymbolKind::Constant, _, Some(value)) => (
+                    std::format!("= {}", value),
+                    InlayHintKind::Value,
+                    std::format!("`{}` = {} ({:#010x})", symbol.name, value, value as u32),
+                ),
+                (SymbolKind::TextLabel | SymbolKind::DataLabel, Some(address), _) => {
+                    match branch_offset(binary, file_tag, line_num, address) {
+                        Some(offset) => (
+                            std::format!("= {:#010x} ({:+})", address, offset),
+                            InlayHintKind::BranchOffset,
+                            std::format!(
+                                "`{}` is at {:#010x}, {} instruction{} {} from the instruction after the branch",
+                                symbol.name,
+                                address,
+                                offset.unsigned_abs(),
+                                if offset.unsigned_abs() == 1 { "" } else { "s" },
+                                if offset < 0 { "back" } else { "on" },
+                            ),
+                        ),
+                        None => (
+                            std::format!("= {:#010x}", address),
+                            InlayHintKind::Address,
+                            std::format!("`{}` is at {:#010x}", symbol.name, address),
+                        ),
+                    }
+                }
+                _ => continue,
+            };
+
+            hints.push(InlayHint {
+                line: line_num,
+                col: utf16_col(line.text, token.end),
+                label,
+                kind,
+                tooltip,
+            });
+        }
+    }
+
+    hints
+}
@@ -0,0 +1,330 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenKind {
+    /// labels, constants and instruction mnemonics
+    Identifier,
+    /// `.text`, `.word`, etc
+    Directive,
+    /// `$t0`, `$8`, `$f12`
+    Register,
+    Number,
+    Char,
+    String,
+    UnterminatedChar,
+    UnterminatedString,
+    Comma,
+    Colon,
+    LParen,
+    RParen,
+    Equals,
+    /// `+`, `-`, `<<`, etc in constant expressions
+    Operator,
+    Comment,
+    Unknown,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// byte offsets into the line
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Token<'_> {
+    /// Whether this token can be (the whole of) a simple operand.
+    pub fn is_atom(&self) -> bool {
+        matches!(
+            self.kind,
+            TokenKind::Identifier
+                | TokenKind::Register
+                | TokenKind::Number
+                | TokenKind::Char
+                | TokenKind::String
+        )
+    }
+}
+
+pub fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+pub fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn scan_literal(rest: &str, quote: char) -> Option<usize> {
+    let mut chars = rest.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if c == quote {
+            return Some(i + c.len_utf8());
+        }
+    }
+    None
+}
+
+/// Splits a single line of MIPS source into tokens. This never fails:
+/// anything unrecognised becomes a `TokenKind::Unknown` token.
+pub fn tokenise_line(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut pos = 0;
+
+    while let Some(c) = line[pos..].chars().next() {
+        let rest = &line[pos..];
+
+        if c.is_whitespace() {
+            pos += c.len_utf8();
+            continue;
+        }
+
+        let take_while = |skip: usize, pred: fn(char) -> bool| {
+            skip + rest[skip..].find(|c| !pred(c)).unwrap_or(rest.len() - skip)
+        };
+
+        let (kind, len) = match c {
+            '#' => (TokenKind::Comment, rest.len()),
+            '"' => match scan_literal(rest, '"') {
+                Some(len) => (TokenKind::String, len),
+                None => (TokenKind::UnterminatedString, rest.len()),
+            },
+            '\'' => match scan_literal(rest, '\'') {
+                Some(len) => (TokenKind::Char, len),
+                None => (TokenKind::UnterminatedChar, rest.len()),
+            },
+            '$' => match take_while(1, |c| c.is_ascii_alphanumeric()) {
+                1 => (TokenKind::Unknown, 1),
+                len => (TokenKind::Register, len),
+            },
+            '.' => match take_while(1, |c| c.is_ascii_alphanumeric() || c == '_') {
+                1 => (TokenKind::Unknown, 1),
+                len => (TokenKind::Directive, len),
+            },
+            c if c.is_ascii_digit() => (TokenKind::Number, take_while(0, is_identifier_char)),
+            c if is_identifier_start(c) => {
+                (TokenKind::Identifier, take_while(0, is_identifier_char))
+            }
+            ',' => (TokenKind::Comma, 1),
+            ':' => (TokenKind::Colon, 1),
+            '(' => (TokenKind::LParen, 1),
+            ')' => (TokenKind::RParen, 1),
+            '=' => (TokenKind::Equals, 1),
+            '<' | '>' if rest[1..].starts_with(c) => (TokenKind::Operator, 2),
+            '+' | '-' | '*' | '/' | '%' | '&' | '|' | '^' | '~' => (TokenKind::Operator, 1),
+            c => (TokenKind::Unknown, c.len_utf8()),
+        };
+
+        tokens.push(Token {
+            kind,
+            text: &rest[..len],
+            start: pos,
+            end: pos + len,
+        });
+        pos += len;
+    }
+
+    tokens
+}
+
+/// Parses a number literal the same way mipsy does (decimal, `0x`, `0b` and
+/// `0o` prefixes).
+pub fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()
+    } else if let Some(oct) = lower.strip_prefix("0o") {
+        i64::from_str_radix(oct, 8).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+/// The tab size mipsy uses when reporting columns, if it isn't configured.
+pub const DEFAULT_TAB_SIZE: u32 = 8;
+
+/// The tab size set by a `#![tabsize(N)]` attribute, which mipsy uses when
+/// reporting columns, or else `default` (from mipsy's config).
+pub fn tab_size_of(source: &str, default: u32) -> u32 {
+    source
+        .lines()
+        .filter_map(|line| {
+            let attribute = line.trim().strip_prefix("#![")?.strip_suffix(']')?;
+            let size = attribute
+                .trim()
+                .strip_prefix("tabsize")?
+                .trim()
+                .strip_prefix('(')?
+                .strip_suffix(')')?;
+            size.trim().parse().ok()
+        })
+        .find(|&size| size > 0)
+        .unwrap_or(default)
+}
+
+/// Converts a byte offset into the 1-indexed, tab-expanded column that mipsy
+/// uses in its error reports.
+pub fn display_col(line: &str, byte_offset: usize, tab_size: u32) -> u32 {
+    let mut col = 0;
+    for c in line[..byte_offset.min(line.len())].chars() {
+        if c == '\t' {
+            col += tab_size - (col % tab_size);
+        } else {
+            col += 1;
+        }
+    }
+    col + 1
+}
+
+/// The inverse of `display_col`.
+pub fn byte_offset_of_display_col(line: &str, display_col: u32, tab_size: u32) -> usize {
+    let mut col = 1;
+    for (i, c) in line.char_indices() {
+        if col >= display_col {
+            return i;
+        }
+        if c == '\t' {
+            col += tab_size - ((col - 1) % tab_size);
+        } else {
+            col += 1;
+        }
+    }
+    line.len()
+}
+
+/// Converts a byte offset into the 0-indexed column, in UTF-16 code units,
+/// that the language server protocol uses.
+pub fn utf16_col(line: &str, byte_offset: usize) -> u32 {
+    line[..byte_offset.min(line.len())].encode_utf16().count() as u32
+}
+
+/// The inverse of `utf16_col`.
+pub fn byte_offset_of_utf16_col(line: &str, col: u32) -> usize {
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= col as usize {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(line: &str) -> Vec<(TokenKind, &str)> {
+        tokenise_line(line)
+            .into_iter()
+            .map(|token| (token.kind, token.text))
+            .collect()
+    }
+
+    #[test]
+    fn tokenises_an_instruction() {
+        assert_eq!(
+            kinds("main:\tlw $t0, -4($sp) # load"),
+            [
+                (TokenKind::Identifier, "main"),
+                (TokenKind::Colon, ":"),
+                (TokenKind::Identifier, "lw"),
+                (TokenKind::Register, "$t0"),
+                (TokenKind::Comma, ","),
+                (TokenKind::Operator, "-"),
+                (TokenKind::Number, "4"),
+                (TokenKind::LParen, "("),
+                (TokenKind::Register, "$sp"),
+                (TokenKind::RParen, ")"),
+                (TokenKind::Comment, "# load"),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenises_directives_and_constants() {
+        assert_eq!(
+            kinds(".word SIZE << 2, 'a'"),
+            [
+                (TokenKind::Directive, ".word"),
+                (TokenKind::Identifier, "SIZE"),
+                (TokenKind::Operator, "<<"),
+                (TokenKind::Number, "2"),
+                (TokenKind::Comma, ","),
+                (TokenKind::Char, "'a'"),
+            ]
+        );
+        assert_eq!(
+            kinds("N = 0x10"),
+            [
+                (TokenKind::Identifier, "N"),
+                (TokenKind::Equals, "="),
+                (TokenKind::Number, "0x10"),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenises_literals_with_escapes() {
+        assert_eq!(
+            kinds(r#".asciiz "a \"quote\" # not a comment""#),
+            [
+                (TokenKind::Directive, ".asciiz"),
+                (TokenKind::String, r#""a \"quote\" # not a comment""#),
+            ]
+        );
+        assert_eq!(kinds(r"'\''"), [(TokenKind::Char, r"'\''")]);
+        assert_eq!(
+            kinds(r#""no end"#),
+            [(TokenKind::UnterminatedString, r#""no end"#)]
+        );
+        assert_eq!(kinds("'x"), [(TokenKind::UnterminatedChar, "'x")]);
+    }
+
+    #[test]
+    fn never_fails() {
+        assert_eq!(
+            kinds("$ . @ é"),
+            [
+                (TokenKind::Unknown, "$"),
+                (TokenKind::Unknown, "."),
+                (TokenKind::Unknown, "@"),
+                (TokenKind::Unknown, "é"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_numbers_like_mipsy() {
+        assert_eq!(parse_number("42"), Some(42));
+        assert_eq!(parse_number("0x1F"), Some(31));
+        assert_eq!(parse_number("0b101"), Some(5));
+        assert_eq!(parse_number("0o17"), Some(15));
+        assert_eq!(parse_number("0xg"), None);
+        assert_eq!(parse_number("4abc"), None);
+    }
+
+    #[test]
+    fn reads_the_tab_size_attribute() {
+        assert_eq!(tab_size_of("#![tabsize(4)]\nmain:", 8), 4);
+        assert_eq!(tab_size_of("#![tabsize(0)]\nmain:", 8), 8);
+        assert_eq!(tab_size_of("main:", 8), 8);
+    }
+
+    #[test]
+    fn converts_columns() {
+        let line = "\tadd\t$t0";
+        assert_eq!(display_col(line, 1, 8), 9);
+        assert_eq!(display_col(line, 4, 8), 12);
+        assert_eq!(display_col(line, 5, 4), 9);
+        assert_eq!(byte_offset_of_display_col(line, 9, 8), 1);
+        assert_eq!(byte_offset_of_display_col(line, 17, 8), 5);
+        assert_eq!(byte_offset_of_display_col(line, 100, 8), line.len());
+
+        let line = "'😀' # x";
+        assert_eq!(utf16_col(line, 5), 3);
+        assert_eq!(byte_offset_of_utf16_col(line, 3), 5);
+    }
+}
@@ -2,16 +2,65 @@ use mipsy_lib::{
     compile::{get_kernel, CompilerOptions},
     decompile::decompile_inst_into_parts,
     error::runtime::ErrorContext,
-    runtime::{RuntimeSyscallGuard, SteppedRuntime, PAGE_SIZE},
+    runtime::{state::State, RuntimeSyscallGuard, SteppedRuntime, PAGE_SIZE},
     util::{get_segment, Segment},
     Binary, InstSet, MipsyError, Runtime, Safe, KTEXT_BOT, TEXT_BOT,
 };
 use mipsy_parser::{MpProgram, TaggedFile};
 use mipsy_utils::MipsyConfig;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, fmt::Display, rc::Rc, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    rc::Rc,
+    str::FromStr,
+};
 use wasm_bindgen::prelude::*;
 
+mod assembled;
+mod call_graph;
+mod call_stack;
+mod calling_convention;
+mod cfg;
+mod completion;
+mod condition;
+mod dead_code;
+mod decode;
+mod diagnostics;
+mod expr;
+mod formatter;
+mod hover;
+mod inlay_hints;
+mod lexer;
+mod lint;
+mod options;
+mod outline;
+mod parsed;
+mod registers;
+mod semantic_tokens;
+mod signature_help;
+mod source;
+mod stepping;
+mod symbols;
+mod syntax;
+mod syscalls;
+#[cfg(test)]
+mod testing;
+mod uninitialised;
+mod watchpoints;
+
+use call_stack::CallStack;
+use condition::{Breakpoint, BreakpointHit, BreakpointStatus, Location, SourceBreakpoint};
+use decode::{word_at, Decoded, Flow};
+pub use formatter::{format_program, FormatOptions, RegisterStyle};
+use lexer::{byte_offset_of_display_col, display_col, tab_size_of};
+pub use options::ProgramOptions;
+use parsed::ParsedFile;
+use registers::{FP, SP};
+use stepping::{Position, Stepping};
+use syntax::{explain_parse_error, operand_formats, SyntaxProblem};
+use watchpoints::{DataBreakpoint, WatchTarget, WatchpointInfo, Watchpoints};
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ErrorReport {
     message: String,
@@ -22,6 +71,8 @@ pub struct ErrorReport {
     col: u32,
     col_end: u32,
     is_warning: bool,
+    /// a lower-severity warning, about something that's probably fine
+    is_hint: bool,
     is_multfile_related: bool,
 }
 
@@ -36,7 +87,7 @@ struct FilenameAndSource {
     source: String,
 }
 
-fn check_source(
+fn compile_sources(
     iset: &InstSet,
     filename: &str,
     source: &str,
@@ -44,7 +95,7 @@ fn check_source(
     config: &MipsyConfig,
     extra_files: &[FilenameAndSource],
     check_main: bool,
-) -> Option<ErrorReport> {
+) -> Result<Binary, MipsyError> {
     let mut tagged_files = vec![TaggedFile::new(Some(filename), source)];
     tagged_files.extend(
         extra_files
@@ -58,37 +109,253 @@ fn check_source(
         MpProgram::new(vec![], vec![])
     };
 
-    match mipsy_lib::compile_with_kernel(iset, tagged_files, &mut kernel, compiler_options, config)
-    {
-        Ok(_) => None,
-        Err(err) => match err {
-            MipsyError::Parser(parse_err) => Some(ErrorReport {
-                message: String::from("Parse failure: check your syntax!"), // is there actually no further info?
-                localised: true,
-                file_tag: (*parse_err.file_tag()).to_owned(),
-                line: parse_err.line(),
-                col: parse_err.col(),
-                col_end: parse_err.col(),
-                tips: vec![],
-                is_warning: false,
-                is_multfile_related: false,
-            }),
-            MipsyError::Compiler(compile_err) => Some(ErrorReport {
-                message: compile_err.error().message(),
-                localised: compile_err.error().should_highlight_line(),
-                file_tag: (*compile_err.file_tag()).to_owned(),
-                line: compile_err.line(),
-                col: compile_err.col(),
-                col_end: compile_err.col_end(),
-                tips: compile_err.error().tips(),
-                is_warning: false,
-                is_multfile_related: false,
-            }),
-            MipsyError::Runtime(_) => None, // should be unreachable?
-        },
+    mipsy_lib::compile_with_kernel(iset, tagged_files, &mut kernel, compiler_options, config)
+}
+
+fn parse_error_report(
+    iset: &InstSet,
+    file_tag: &str,
+    source: &str,
+    line: u32,
+    col: u32,
+    tab_size: u32,
+) -> ErrorReport {
+    let tab_size = tab_size_of(source, tab_size);
+    let line_text = source
+        .lines()
+        .nth(line.saturating_sub(1) as usize)
+        .unwrap_or("");
+
+    let offset = byte_offset_of_display_col(line_text, col, tab_size);
+    let SyntaxProblem {
+        message,
+        tips,
+        start,
+        end,
+    } = explain_parse_error(line_text, offset, |mnemonic| {
+        operand_formats(iset, mnemonic)
+    });
+
+    ErrorReport {
+        message,
+        localised: true,
+        file_tag: file_tag.to_owned(),
+        line,
+        col: display_col(line_text, start, tab_size),
+        col_end: display_col(line_text, end, tab_size),
+        tips,
+        is_warning: false,
+        is_hint: false,
+        is_multfile_related: false,
     }
 }
 
+/// Converts an error from mipsy into a report, where `tab_size` is the one
+/// mipsy was configured with.
+fn error_report(
+    iset: &InstSet,
+    err: &MipsyError,
+    sources: &[(&str, &str)],
+    tab_size: u32,
+) -> Option<ErrorReport> {
+    match err {
+        MipsyError::Parser(parse_err) => {
+            let file_tag = parse_err.file_tag();
+            let source = sources
+                .iter()
+                .find(|&&(tag, _)| tag == &*file_tag)
+                .map_or("", |&(_, source)| source);
+
+            Some(parse_error_report(
+                iset,
+                &file_tag,
+                source,
+                parse_err.line(),
+                parse_err.col(),
+                tab_size,
+            ))
+        }
+        MipsyError::Compiler(compile_err) => Some(ErrorReport {
+            message: compile_err.error().message(),
+            localised: compile_err.error().should_highlight_line(),
+            file_tag: (*compile_err.file_tag()).to_owned(),
+            line: compile_err.line(),
+            col: compile_err.col(),
+            col_end: compile_err.col_end(),
+            tips: compile_err.error().tips(),
+            is_warning: false,
+            is_hint: false,
+            is_multfile_related: false,
+        }),
+        MipsyError::Runtime(_) => None, // should be unreachable?
+    }
+}
+
+fn all_sources<'a>(
+    filename: &'a str,
+    source: &'a str,
+    extra_files: &'a [FilenameAndSource],
+) -> Vec<(&'a str, &'a str)> {
+    std::iter::once((filename, source))
+        .chain(
+            extra_files
+                .iter()
+                .map(|file| (file.filename.as_str(), file.source.as_str())),
+        )
+        .collect()
+}
+
+fn check_source(
+    iset: &InstSet,
+    filename: &str,
+    source: &str,
+    compiler_options: &CompilerOptions,
+    config: &MipsyConfig,
+    extra_files: &[FilenameAndSource],
+    check_main: bool,
+) -> Option<ErrorReport> {
+    compile_sources(
+        iset,
+        filename,
+        source,
+        compiler_options,
+        config,
+        extra_files,
+        check_main,
+    )
+    .err()
+    .and_then(|err| {
+        error_report(
+            iset,
+            &err,
+            &all_sources(filename, source, extra_files),
+            config.tab_size,
+        )
+    })
+}
+
+/// Compiles every file as `ParsedFile` parsed it, so that the lines which
+/// don't parse are left out (but for what they define) rather than stopping
+/// the compile.
+fn compile_parsed(
+    iset: &InstSet,
+    files: &[(&str, ParsedFile)],
+    compiler_options: &CompilerOptions,
+    config: &MipsyConfig,
+    check_main: bool,
+) -> Result<Binary, MipsyError> {
+    let mut items: Vec<_> = files
+        .iter()
+        .flat_map(|(tag, parsed)| parsed.program_items(tag))
+        .collect();
+
+    if check_main {
+        items.extend(get_kernel().items().iter().cloned());
+    }
+
+    mipsy_lib::compile::compile(
+        &mut MpProgram::new(items, vec![]),
+        config,
+        compiler_options,
+        iset,
+    )
+}
+
+/// Every problem with the primary file, from one parse of each file and one
+/// compile of what parsed: every line which doesn't parse, the error mipsy
+/// stops compiling at, and if it compiles, the warnings about it.
+fn diagnostics(
+    primary_source: &str,
+    primary_filename: &str,
+    other_files: &[FilenameAndSource],
+    max_problems: usize,
+    check_main: bool,
+    options: &ProgramOptions,
+) -> Vec<ErrorReport> {
+    let compiler_options = &options.compiler_options();
+    let config = &options.config();
+    let iset = &mipsy_instructions::inst_set();
+
+    let sources = all_sources(primary_filename, primary_source, other_files);
+    let parsed: Vec<(&str, ParsedFile)> = sources
+        .iter()
+        .map(|&(tag, source)| (tag, ParsedFile::parse(tag, source, config.tab_size)))
+        .collect();
+
+    let mut reports: Vec<ErrorReport> = sources
+        .iter()
+        .zip(&parsed)
+        .flat_map(|(&(tag, source), (_, file))| {
+            file.errors().map(move |(line, col)| {
+                parse_error_report(iset, tag, source, line, col, config.tab_size)
+            })
+        })
+        .collect();
+    let unparsed_lines: HashSet<u32> = reports
+        .iter()
+        .filter(|report| report.file_tag == primary_filename)
+        .map(|report| report.line)
+        .collect();
+
+    let binary = match compile_parsed(iset, &parsed, compiler_options, config, check_main) {
+        Ok(binary) => Some(binary),
+        Err(err) => {
+            reports.extend(error_report(iset, &err, &sources, config.tab_size));
+            None
+        }
+    };
+
+    let (mut all_errors, elsewhere): (Vec<_>, Vec<_>) = reports.into_iter().partition(|err| {
+        err.file_tag == primary_filename || err.file_tag.is_empty() || other_files.is_empty()
+    });
+
+    if let Some(err) = elsewhere.first() {
+        all_errors.push(ErrorReport {
+            message: std::format!("there's an error in another file ({}: {}), which may be obscuring errors in this one", err.file_tag, err.message),
+            localised: false,
+            tips: vec![],
+            file_tag: primary_filename.to_owned(),
+            line: 0,
+            col: 0,
+            col_end: 0,
+            is_warning: true,
+            is_hint: false,
+            is_multfile_related: true
+        });
+    }
+
+    if let Some(binary) = binary {
+        let warnings = lint::lint_program(
+            &binary,
+            &sources,
+            primary_filename,
+            check_main,
+            config.tab_size,
+        );
+
+        all_errors.extend(
+            warnings
+                .into_iter()
+                .filter(|warning| !unparsed_lines.contains(&warning.line)),
+        );
+    }
+
+    // errors first, so that they're never cut off in favour of warnings (or
+    // warnings in favour of hints)
+    all_errors.sort_by_key(|err| {
+        (
+            err.is_warning,
+            err.is_hint,
+            err.localised,
+            err.line,
+            err.col,
+        )
+    });
+    all_errors.truncate(max_problems);
+
+    all_errors
+}
+
 #[wasm_bindgen]
 pub fn test_compile(
     primary_source: &str,
@@ -96,107 +363,660 @@ pub fn test_compile(
     other_files: JsValue,
     max_problems: usize,
     check_main: bool,
+    options: JsValue,
 ) -> Result<JsValue, JsValue> {
-    let compiler_options = &CompilerOptions::new(vec![]);
-    let config = &MipsyConfig::default();
-    let iset = &mipsy_instructions::inst_set();
+    let options = ProgramOptions::from_js(options)?;
+    let other_files: Vec<FilenameAndSource> = serde_wasm_bindgen::from_value(other_files)?;
 
-    let mut all_errors: Vec<ErrorReport> = vec![];
-    let mut error_lines: Vec<u32> = vec![];
+    Ok(serde_wasm_bindgen::to_value(&ValidationResult {
+        errors: diagnostics(
+            primary_source,
+            primary_filename,
+            &other_files,
+            max_problems,
+            check_main,
+            &options,
+        ),
+    })?)
+}
+
+/// Warns about functions which don't follow the calling convention. Programs
+/// that don't compile have no warnings, as `test_compile` already reports why.
+#[wasm_bindgen]
+pub fn check_calling_convention(
+    primary_source: &str,
+    primary_filename: &str,
+    other_files: JsValue,
+    check_main: bool,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    let options = ProgramOptions::from_js(options)?;
+    let compiler_options = &options.compiler_options();
+    let config = &options.config();
+    let iset = &mipsy_instructions::inst_set();
 
-    let other_files: Vec<_> = serde_wasm_bindgen::from_value(other_files)?;
+    let other_files: Vec<FilenameAndSource> = serde_wasm_bindgen::from_value(other_files)?;
 
-    while all_errors.len() < max_problems {
-        let err = check_source(
-            iset,
+    let errors = match compile_sources(
+        iset,
+        primary_filename,
+        primary_source,
+        compiler_options,
+        config,
+        &other_files,
+        check_main,
+    ) {
+        Ok(binary) => calling_convention::check_program(
+            &binary,
+            primary_source,
             primary_filename,
-            &primary_source
-                .lines()
-                .enumerate()
-                .map(|(i, line)| {
-                    if error_lines.contains(&u32::try_from(i).unwrap()) {
-                        ""
-                    } else {
-                        line
-                    }
+            config.tab_size,
+        ),
+        Err(_) => vec![],
+    };
+
+    Ok(serde_wasm_bindgen::to_value(&ValidationResult { errors })?)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SymbolTable {
+    symbols: Vec<symbols::Symbol>,
+}
+
+/// Finds every label and constant in the program, along with their addresses
+/// (or values) if it compiles.
+#[wasm_bindgen]
+pub fn get_symbols(
+    primary_source: &str,
+    primary_filename: &str,
+    other_files: JsValue,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    let options = ProgramOptions::from_js(options)?;
+    let compiler_options = &options.compiler_options();
+    let config = &options.config();
+    let iset = &mipsy_instructions::inst_set();
+
+    let other_files: Vec<FilenameAndSource> = serde_wasm_bindgen::from_value(other_files)?;
+
+    let binary = compile_sources(
+        iset,
+        primary_filename,
+        primary_source,
+        compiler_options,
+        config,
+        &other_files,
+        false,
+    )
+    .ok();
+
+    Ok(serde_wasm_bindgen::to_value(&SymbolTable {
+        symbols: symbols::symbol_table(
+            &all_sources(primary_filename, primary_source, &other_files),
+            binary.as_ref(),
+            config.tab_size,
+        ),
+    })?)
+}
+
+fn is_instruction_name(iset: &InstSet, name: &str) -> bool {
+    iset.native_set()
+        .iter()
+        .any(|sig| sig.name().eq_ignore_ascii_case(name))
+        || iset
+            .pseudo_set()
+            .iter()
+            .any(|sig| sig.name().eq_ignore_ascii_case(name))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct References {
+    name: Option<String>,
+    references: Vec<symbols::Reference>,
+}
+
+/// Finds every use of the label or constant at a (0-indexed) position in the
+/// file tagged `file_tag`.
+#[wasm_bindgen]
+pub fn find_references(
+    primary_source: &str,
+    primary_filename: &str,
+    other_files: JsValue,
+    file_tag: &str,
+    line: u32,
+    col: u32,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    let options = ProgramOptions::from_js(options)?;
+    let config = &options.config();
+
+    let other_files: Vec<FilenameAndSource> = serde_wasm_bindgen::from_value(other_files)?;
+    let sources = all_sources(primary_filename, primary_source, &other_files);
+
+    let name = sources
+        .iter()
+        .find(|&&(tag, _)| tag == file_tag)
+        .and_then(|&(tag, source)| symbols::name_at(tag, source, line, col, config.tab_size));
+
+    let references = name.map_or_else(Vec::new, |name| {
+        symbols::references_to(&sources, name, config.tab_size)
+    });
+
+    Ok(serde_wasm_bindgen::to_value(&References {
+        name: name.map(String::from),
+        references,
+    })?)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RenameResult {
+    edits: Vec<symbols::Reference>,
+    error: Option<String>,
+}
+
+/// Works out the edits to rename the label or constant at a (0-indexed)
+/// position to `new_name`. If it can't be renamed, `error` explains why.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn rename_symbol(
+    primary_source: &str,
+    primary_filename: &str,
+    other_files: JsValue,
+    file_tag: &str,
+    line: u32,
+    col: u32,
+    new_name: &str,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    let options = ProgramOptions::from_js(options)?;
+    let config = &options.config();
+    let iset = &mipsy_instructions::inst_set();
+
+    let other_files: Vec<FilenameAndSource> = serde_wasm_bindgen::from_value(other_files)?;
+    let sources = all_sources(primary_filename, primary_source, &other_files);
+    let symbols = symbols::symbol_table(&sources, None, config.tab_size);
+
+    let name = sources
+        .iter()
+        .find(|&&(tag, _)| tag == file_tag)
+        .and_then(|&(tag, source)| symbols::name_at(tag, source, line, col, config.tab_size))
+        .filter(|name| symbols.iter().any(|symbol| symbol.name == *name));
+
+    let result = match name {
+        None => RenameResult {
+            edits: vec![],
+            error: Some("only labels and constants defined in this program can be renamed".into()),
+        },
+        Some(name) if name == new_name => RenameResult {
+            edits: vec![],
+            error: None,
+        },
+        Some(name) => {
+            match symbols::check_rename(&sources, &symbols, new_name, config.tab_size, |name| {
+                is_instruction_name(iset, name)
+            }) {
+                Ok(()) => RenameResult {
+                    edits: symbols::references_to(&sources, name, config.tab_size),
+                    error: None,
+                },
+                Err(error) => RenameResult {
+                    edits: vec![],
+                    error: Some(error),
+                },
+            }
+        }
+    };
+
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SemanticTokensLegend {
+    token_types: Vec<String>,
+    token_modifiers: Vec<String>,
+}
+
+#[wasm_bindgen]
+pub fn semantic_tokens_legend() -> Result<JsValue, JsValue> {
+    Ok(serde_wasm_bindgen::to_value(&SemanticTokensLegend {
+        token_types: semantic_tokens::TOKEN_TYPES.map(String::from).to_vec(),
+        token_modifiers: semantic_tokens::TOKEN_MODIFIERS.map(String::from).to_vec(),
+    })?)
+}
+
+/// Classifies the tokens of the primary file for semantic highlighting, in
+/// the delta-encoded format of the language server protocol.
+#[wasm_bindgen]
+pub fn semantic_tokens(
+    primary_source: &str,
+    primary_filename: &str,
+    other_files: JsValue,
+    options: JsValue,
+) -> Result<Vec<u32>, JsValue> {
+    let options = ProgramOptions::from_js(options)?;
+    let config = &options.config();
+    let iset = &mipsy_instructions::inst_set();
+
+    let other_files: Vec<FilenameAndSource> = serde_wasm_bindgen::from_value(other_files)?;
+    let symbols = symbols::symbol_table(
+        &all_sources(primary_filename, primary_source, &other_files),
+        None,
+        config.tab_size,
+    );
+
+    Ok(semantic_tokens::encode_semantic_tokens(
+        &ParsedFile::parse(primary_filename, primary_source, config.tab_size),
+        &symbols,
+        |mnemonic| {
+            let is_native = iset
+                .native_set()
+                .iter()
+                .any(|sig| sig.name().eq_ignore_ascii_case(mnemonic));
+
+            if is_native {
+                Some(false)
+            } else if is_instruction_name(iset, mnemonic) {
+                Some(true)
+            } else {
+                None
+            }
+        },
+    ))
+}
+
+/// Describes the instruction whose mnemonic is at a (0-indexed) position in
+/// the file tagged `file_tag`: what it assembles to, or expands to if it's a
+/// pseudo-instruction.
+#[wasm_bindgen]
+pub fn hover_instruction(
+    primary_source: &str,
+    primary_filename: &str,
+    other_files: JsValue,
+    file_tag: &str,
+    line: u32,
+    col: u32,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    let options = ProgramOptions::from_js(options)?;
+    let compiler_options = &options.compiler_options();
+    let config = &options.config();
+    let iset = &mipsy_instructions::inst_set();
+
+    let other_files: Vec<FilenameAndSource> = serde_wasm_bindgen::from_value(other_files)?;
+    let sources = all_sources(primary_filename, primary_source, &other_files);
+
+    let binary = compile_sources(
+        iset,
+        primary_filename,
+        primary_source,
+        compiler_options,
+        config,
+        &other_files,
+        false,
+    )
+    .ok();
+
+    let hover = sources
+        .iter()
+        .find(|&&(tag, _)| tag == file_tag)
+        .and_then(|&(_, source)| {
+            hover::instruction_hover(
+                iset,
+                binary.as_ref(),
+                source,
+                file_tag,
+                line,
+                col,
+                config.tab_size,
+            )
+        });
+
+    Ok(serde_wasm_bindgen::to_value(&hover)?)
+}
+
+/// Lists the operand forms of the instruction on `line`, and which operand the
+/// cursor (at a 0-indexed column) is in.
+#[wasm_bindgen]
+pub fn get_signature_help(line: &str, col: u32, options: JsValue) -> Result<JsValue, JsValue> {
+    // the forms are the same whatever the options are, but they're still
+    // checked like everywhere else
+    ProgramOptions::from_js(options)?;
+    let iset = &mipsy_instructions::inst_set();
+
+    Ok(serde_wasm_bindgen::to_value(
+        &signature_help::signature_help(iset, line, col),
+    )?)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Completions {
+    completions: Vec<completion::Completion>,
+}
+
+/// Suggests what could be written at a (0-indexed) position in the file
+/// tagged `file_tag`, based on what's valid there.
+#[wasm_bindgen]
+pub fn get_completions(
+    primary_source: &str,
+    primary_filename: &str,
+    other_files: JsValue,
+    file_tag: &str,
+    line: u32,
+    col: u32,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    let options = ProgramOptions::from_js(options)?;
+    let config = &options.config();
+    let iset = &mipsy_instructions::inst_set();
+
+    let other_files: Vec<FilenameAndSource> = serde_wasm_bindgen::from_value(other_files)?;
+    let sources = all_sources(primary_filename, primary_source, &other_files);
+    let symbols = symbols::symbol_table(&sources, None, config.tab_size);
+
+    let line_text = sources
+        .iter()
+        .find(|&&(tag, _)| tag == file_tag)
+        .and_then(|&(_, source)| source.lines().nth(line as usize))
+        .unwrap_or("");
+
+    Ok(serde_wasm_bindgen::to_value(&Completions {
+        completions: completion::completions(iset, &symbols, line_text, col),
+    })?)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct InlayHints {
+    hints: Vec<inlay_hints::InlayHint>,
+}
+
+/// Shows what each label and constant used in the file tagged `file_tag`
+/// resolves to, and how far each branch goes. Only works once the program
+/// compiles.
+#[wasm_bindgen]
+pub fn get_inlay_hints(
+    primary_source: &str,
+    primary_filename: &str,
+    other_files: JsValue,
+    file_tag: &str,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    let options = ProgramOptions::from_js(options)?;
+    let compiler_options = &options.compiler_options();
+    let config = &options.config();
+    let iset = &mipsy_instructions::inst_set();
+
+    let other_files: Vec<FilenameAndSource> = serde_wasm_bindgen::from_value(other_files)?;
+    let sources = all_sources(primary_filename, primary_source, &other_files);
+
+    let hints = match compile_sources(
+        iset,
+        primary_filename,
+        primary_source,
+        compiler_options,
+        config,
+        &other_files,
+        false,
+    ) {
+        Ok(binary) => {
+            let symbols = symbols::symbol_table(&sources, Some(&binary), config.tab_size);
+            sources
+                .iter()
+                .find(|&&(tag, _)| tag == file_tag)
+                .map_or(vec![], |&(_, source)| {
+                    inlay_hints::inlay_hints(&binary, &symbols, source, file_tag, config.tab_size)
                 })
-                .collect::<Vec<&str>>()
-                .join("\n"),
-            compiler_options,
-            config,
-            &other_files,
-            check_main,
-        );
+        }
+        Err(_) => vec![],
+    };
 
-        match err {
-            None => break,
-            Some(err) => {
-                if err.file_tag != primary_filename
-                    && !err.file_tag.is_empty()
-                    && !other_files.is_empty()
-                {
-                    if all_errors.is_empty() {
-                        all_errors.push(ErrorReport {
-                            message: std::format!("there's an error in another file ({}: {}), which may be obscuring errors in this one", err.file_tag, err.message),
-                            localised: false,
-                            tips: vec![],
-                            file_tag: primary_filename.to_owned(),
-                            line: 0,
-                            col: 0,
-                            col_end: 0,
-                            is_warning: true,
-                            is_multfile_related: true
-                        })
-                    }
+    Ok(serde_wasm_bindgen::to_value(&InlayHints { hints })?)
+}
 
-                    // different file - just give up now
-                    break;
-                }
+#[derive(Serialize, Deserialize)]
+pub struct AssembledLines {
+    lines: Vec<assembled::AssembledLine>,
+}
 
-                if error_lines.iter().any(|line| err.line <= *line) {
-                    // error occured before a previously received error - probably spurious
-                    break;
-                }
+/// The reverse of `Binary::line_numbers`: which addresses each line of the
+/// file tagged `file_tag` assembled to, and the words or bytes there.
+#[wasm_bindgen]
+pub fn get_assembled_lines(
+    primary_source: &str,
+    primary_filename: &str,
+    other_files: JsValue,
+    file_tag: &str,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    let options = ProgramOptions::from_js(options)?;
+    let compiler_options = &options.compiler_options();
+    let config = &options.config();
+    let iset = &mipsy_instructions::inst_set();
+
+    let other_files: Vec<FilenameAndSource> = serde_wasm_bindgen::from_value(other_files)?;
+    let sources = all_sources(primary_filename, primary_source, &other_files);
+
+    let lines = match compile_sources(
+        iset,
+        primary_filename,
+        primary_source,
+        compiler_options,
+        config,
+        &other_files,
+        false,
+    ) {
+        Ok(binary) => assembled::assembled_lines(&binary, &sources, file_tag, config.tab_size),
+        Err(_) => vec![],
+    };
+
+    Ok(serde_wasm_bindgen::to_value(&AssembledLines { lines })?)
+}
 
-                let line = err.line - 1;
-                let localised = err.localised;
+#[derive(Serialize, Deserialize)]
+pub struct Outline {
+    nodes: Vec<outline::OutlineNode>,
+    /// the (0-indexed, inclusive) line ranges which can be folded
+    folding_ranges: Vec<[u32; 2]>,
+}
+
+/// The segments, functions and labels of the file tagged `file_tag`, nested
+/// inside each other, for document symbols and folding. Functions are found
+/// as the calling convention checks find them, if the program compiles.
+#[wasm_bindgen]
+pub fn get_outline(
+    primary_source: &str,
+    primary_filename: &str,
+    other_files: JsValue,
+    file_tag: &str,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    let options = ProgramOptions::from_js(options)?;
+    let compiler_options = &options.compiler_options();
+    let config = &options.config();
+    let iset = &mipsy_instructions::inst_set();
+
+    let other_files: Vec<FilenameAndSource> = serde_wasm_bindgen::from_value(other_files)?;
+    let sources = all_sources(primary_filename, primary_source, &other_files);
+
+    let binary = compile_sources(
+        iset,
+        primary_filename,
+        primary_source,
+        compiler_options,
+        config,
+        &other_files,
+        false,
+    )
+    .ok();
+
+    let files: Vec<ParsedFile> = sources
+        .iter()
+        .map(|&(tag, source)| ParsedFile::parse(tag, source, config.tab_size))
+        .collect();
+    let functions = outline::function_names(binary.as_ref(), &files);
+
+    let nodes = sources
+        .iter()
+        .zip(&files)
+        .find(|&(&(tag, _), _)| tag == file_tag)
+        .map_or(vec![], |(_, file)| outline::outline(file, &functions));
+
+    Ok(serde_wasm_bindgen::to_value(&Outline {
+        folding_ranges: outline::folding_ranges(&nodes),
+        nodes,
+    })?)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ControlFlowGraphResult {
+    graph: Option<cfg::ControlFlowGraph>,
+    /// the same graph, in Graphviz's DOT language
+    dot: Option<String>,
+    error: Option<String>,
+}
+
+/// Splits the function starting at the label `function` into basic blocks,
+/// returning its control flow graph both as JSON and as DOT.
+#[wasm_bindgen]
+pub fn get_control_flow_graph(
+    primary_source: &str,
+    primary_filename: &str,
+    other_files: JsValue,
+    function: &str,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    let options = ProgramOptions::from_js(options)?;
+    let compiler_options = &options.compiler_options();
+    let config = &options.config();
+    let iset = &mipsy_instructions::inst_set();
+
+    let other_files: Vec<FilenameAndSource> = serde_wasm_bindgen::from_value(other_files)?;
+
+    let graph = compile_sources(
+        iset,
+        primary_filename,
+        primary_source,
+        compiler_options,
+        config,
+        &other_files,
+        false,
+    )
+    .map_err(|_| "your program needs to compile before its control flow can be shown".to_string())
+    .and_then(|binary| cfg::control_flow_graph(&binary, iset, function));
+
+    let result = match graph {
+        Ok(graph) => ControlFlowGraphResult {
+            dot: Some(cfg::to_dot(&graph)),
+            graph: Some(graph),
+            error: None,
+        },
+        Err(error) => ControlFlowGraphResult {
+            graph: None,
+            dot: None,
+            error: Some(error),
+        },
+    };
+
+    Ok(serde_wasm_bindgen::to_value(&result)?)
+}
 
-                all_errors.push(err);
+/// The static call graph of the program: its functions, and every call from
+/// one to another, for the call hierarchy.
+#[wasm_bindgen]
+pub fn get_call_graph(
+    primary_source: &str,
+    primary_filename: &str,
+    other_files: JsValue,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    let options = ProgramOptions::from_js(options)?;
+    let compiler_options = &options.compiler_options();
+    let config = &options.config();
+    let iset = &mipsy_instructions::inst_set();
 
-                if !localised {
-                    break;
-                }
+    let other_files: Vec<FilenameAndSource> = serde_wasm_bindgen::from_value(other_files)?;
+    let sources = all_sources(primary_filename, primary_source, &other_files);
 
-                error_lines.push(line);
-            }
+    let graph = match compile_sources(
+        iset,
+        primary_filename,
+        primary_source,
+        compiler_options,
+        config,
+        &other_files,
+        false,
+    ) {
+        Ok(binary) => {
+            let symbols = symbols::symbol_table(&sources, Some(&binary), config.tab_size);
+            call_graph::call_graph(&binary, &sources, &symbols)
         }
-    }
+        Err(_) => call_graph::CallGraph::default(),
+    };
 
-    Ok(serde_wasm_bindgen::to_value(&ValidationResult {
-        errors: all_errors,
-    })?)
+    Ok(serde_wasm_bindgen::to_value(&graph)?)
+}
+
+/// Lines up the labels, instructions, operands and comments of a file into
+/// columns, without changing what it compiles to.
+#[wasm_bindgen]
+pub fn format_source(
+    source: &str,
+    filename: &str,
+    format_options: JsValue,
+    options: JsValue,
+) -> Result<String, JsValue> {
+    let options = ProgramOptions::from_js(options)?;
+    let format_options: FormatOptions = if format_options.is_undefined() || format_options.is_null()
+    {
+        FormatOptions::default()
+    } else {
+        serde_wasm_bindgen::from_value(format_options)?
+    };
+
+    Ok(format_program(
+        source,
+        filename,
+        &format_options,
+        options.config().tab_size,
+    ))
 }
 
 fn compile_from_source(
     source: &str,
     filename: &str,
+    extra_files: &[FilenameAndSource],
     reason: &str,
     iset: &InstSet,
+    options: &ProgramOptions,
 ) -> Result<Binary, String> {
-    let compiler_options = &CompilerOptions::new(vec![]);
-    let config = &MipsyConfig::default();
+    let compiler_options = &options.compiler_options();
+    let config = &options.config();
 
-    match mipsy_lib::compile(
-        iset,
-        vec![TaggedFile::new(Some(filename), source)],
-        compiler_options,
-        config,
-    ) {
+    let mut tagged_files = vec![TaggedFile::new(Some(filename), source)];
+    tagged_files.extend(
+        extra_files
+            .iter()
+            .map(|extra_file| TaggedFile::new(Some(&extra_file.filename), &extra_file.source)),
+    );
+
+    match mipsy_lib::compile(iset, tagged_files, compiler_options, config) {
         Ok(binary) => Ok(binary),
-        Err(_) => match check_source(iset, filename, source, compiler_options, config, &[], true) {
+        Err(_) => match check_source(
+            iset,
+            filename,
+            source,
+            compiler_options,
+            config,
+            extra_files,
+            true,
+        ) {
             Some(err) => Err(std::format!(
-                "Your MIPS program has an error so can't be {}: {}{}",
+                "Your MIPS program has an error so can't be {}: {}{}{}",
                 reason,
+                if extra_files.is_empty() || !err.localised {
+                    "".into()
+                } else {
+                    std::format!("{}, ", err.file_tag)
+                },
                 if err.localised {
                     std::format!("line {}: ", err.line)
                 } else {
@@ -210,10 +1030,14 @@ fn compile_from_source(
 }
 
 #[wasm_bindgen]
-pub fn decompile_source(source: &str, filename: &str) -> String {
+pub fn decompile_source(source: &str, filename: &str, options: JsValue) -> String {
     let iset = &mipsy_instructions::inst_set();
+    let options = match ProgramOptions::from_js(options) {
+        Ok(options) => options,
+        Err(_) => return "Hmm, the options for compiling your program don't make sense.".into(),
+    };
 
-    compile_from_source(source, filename, "dissassembled", iset)
+    compile_from_source(source, filename, &[], "dissassembled", iset, &options)
         .map(|binary| mipsy_lib::decompile(iset, &binary))
         .unwrap_or_else(|msg| msg)
 }
@@ -230,7 +1054,18 @@ struct RegisterCache {
 pub struct DebugRuntime {
     mipsy_runtime: Option<SteppedRuntime>,
     binary: Binary,
-    breakpoint_addrs: HashSet<u32>,
+    /// every breakpoint, by (file tag, line)
+    breakpoints: HashMap<(Rc<str>, u32), Breakpoint>,
+    /// the address of the first instruction on each of those lines
+    breakpoint_addrs: HashMap<u32, (Rc<str>, u32)>,
+    /// messages from logpoints, waiting to be shown
+    log_messages: Vec<String>,
+    watchpoints: Watchpoints,
+    /// why the program last stopped at a watchpoint, waiting to be shown
+    watchpoint_hit: Option<ChangeReport>,
+    /// a step over or out that a syscall interrupted
+    stepping: Option<Stepping>,
+    call_stack: CallStack,
     registers: Option<RegisterCache>,
     last_pc: Option<u32>,
     iset: InstSet,
@@ -249,20 +1084,77 @@ pub enum StepResult {
 pub struct DisassembleResponse {
     address: u32,
     instruction: String,
+    file_tag: Option<String>,
     line_num: Option<u32>,
     instruction_bytes: Option<String>,
     symbols: Option<String>,
 }
 
+/// A change to a register or memory (or a read of memory), with the
+/// instruction which made it, e.g. when a watchpoint stops the program.
+#[derive(Serialize, Deserialize)]
+pub struct ChangeReport {
+    pc: u32,
+    instruction: String,
+    file_tag: Option<String>,
+    line: Option<u32>,
+    description: String,
+}
+
+/// Where an instruction came from.
+#[derive(Serialize, Deserialize)]
+pub struct SourcePosition {
+    file_tag: String,
+    /// 1-indexed
+    line: u32,
+}
+
 #[wasm_bindgen]
 impl DebugRuntime {
     pub fn step_debug(&mut self) -> Result<JsValue, JsValue> {
-        let step_result = match self.mipsy_runtime.take() {
+        self.stepping = None;
+        let step_result = self.step_once();
+
+        Ok(serde_wasm_bindgen::to_value(&step_result)?)
+    }
+
+    fn step_once(&mut self) -> StepResult {
+        match self.mipsy_runtime.take() {
             Some(Ok(runtime)) => {
+                let pc = runtime.timeline().state().pc();
+                let inst = word_at(&self.binary, pc).map(Decoded::new);
+                let read = |location| read_location(&runtime, location);
+                self.watchpoints.before_step(pc, inst, &read);
+
+                let sp = read(Location::Register(SP));
+                let fp = read(Location::Register(FP));
+                let jumped_to = inst
+                    .and_then(|inst| read(Location::Register(inst.rs)))
+                    .map(|address| address as u32);
+
                 match runtime.step() {
                     Ok(new_stepped_runtime) => {
                         self.mipsy_runtime = Some(new_stepped_runtime);
+
+                        let next_pc = match &self.mipsy_runtime {
+                            Some(Ok(runtime)) => Some(runtime.timeline().state().pc()),
+                            _ => None,
+                        };
+                        let mismatch = self.call_stack.after_step(
+                            pc,
+                            self.flow_at(pc),
+                            next_pc,
+                            sp,
+                            fp,
+                            jumped_to,
+                        );
+                        if let Some(mismatch) = mismatch {
+                            self.log_messages
+                                .push(std::format!("[call stack] {}", mismatch));
+                        }
+
                         self.invalidate_register_cache();
+                        self.check_for_breakpoint();
 
                         StepResult::StepSuccess
                     }
@@ -297,11 +1189,203 @@ impl DebugRuntime {
                 StepResult::AtSyscallGuard
             }
             None => StepResult::NoRuntime,
+        }
+    }
+
+    /// Runs until the program gets to another line, treating a call (until
+    /// it returns to the next instruction with the same `$sp`) as one step.
+    /// Like `step_debug`, this returns `AtSyscallGuard` at a syscall (or
+    /// breakpoint), and calling it again after the syscall carries on.
+    pub fn step_over(&mut self) -> Result<JsValue, JsValue> {
+        if !matches!(self.stepping, Some(Stepping::Over { .. })) {
+            let from = self.position();
+            self.stepping = Some(Stepping::over(from));
+        }
+
+        self.run_stepping()
+    }
+
+    /// Runs until the current function returns with `jr $ra`, like
+    /// `step_over`.
+    pub fn step_out(&mut self) -> Result<JsValue, JsValue> {
+        if !matches!(self.stepping, Some(Stepping::Out { .. })) {
+            self.stepping = Some(Stepping::out());
+        }
+
+        self.run_stepping()
+    }
+
+    /// Stops a step over or out which was interrupted by a syscall.
+    pub fn cancel_stepping(&mut self) {
+        self.stepping = None;
+    }
+
+    fn run_stepping(&mut self) -> Result<JsValue, JsValue> {
+        const MAX_STEPS: u32 = 1_000_000;
+
+        for _ in 0..MAX_STEPS {
+            let (pc, sp) = match &self.mipsy_runtime {
+                Some(Ok(runtime)) => (
+                    runtime.timeline().state().pc(),
+                    read_location(runtime, Location::Register(SP)),
+                ),
+                Some(Err(guard)) => {
+                    if matches!(guard, RuntimeSyscallGuard::Breakpoint(_)) {
+                        self.stepping = None;
+                    }
+                    return Ok(serde_wasm_bindgen::to_value(&StepResult::AtSyscallGuard)?);
+                }
+                None => {
+                    self.stepping = None;
+                    return Ok(serde_wasm_bindgen::to_value(&StepResult::NoRuntime)?);
+                }
+            };
+
+            let position = self.position();
+            let flow = self.flow_at(pc);
+            let stepping = match &mut self.stepping {
+                Some(stepping) => stepping,
+                None => break,
+            };
+            if stepping.is_done(pc, sp, &position) {
+                self.stepping = None;
+                return Ok(serde_wasm_bindgen::to_value(&StepResult::StepSuccess)?);
+            }
+
+            stepping.before_step(pc, flow, sp);
+
+            match self.step_once() {
+                StepResult::StepSuccess | StepResult::AtSyscallGuard => {}
+                result => {
+                    self.stepping = None;
+                    return Ok(serde_wasm_bindgen::to_value(&result)?);
+                }
+            }
+        }
+
+        if self.stepping.take().is_some() {
+            self.log_messages.push(std::format!(
+                "stopped after running {} instructions without getting there (is there an infinite loop?)",
+                MAX_STEPS
+            ));
+        }
+
+        Ok(serde_wasm_bindgen::to_value(&StepResult::StepSuccess)?)
+    }
+
+    /// The same as `step_over`, but backwards. Returns false if it stopped
+    /// early, at a breakpoint or the start of the program.
+    pub fn step_over_back(&mut self) -> bool {
+        let from = self.position();
+        self.run_stepping_back(Stepping::over(from))
+    }
+
+    /// Goes back to the call of the current function. Returns false if it
+    /// stopped early, at a breakpoint or the start of the program.
+    pub fn step_out_back(&mut self) -> bool {
+        self.run_stepping_back(Stepping::out())
+    }
+
+    fn run_stepping_back(&mut self, mut stepping: Stepping) -> bool {
+        self.stepping = None;
+        let (mut runtime, _) = match self.force_get_runtime() {
+            Some(runtime) => runtime,
+            None => return false,
         };
 
-        self.check_for_breakpoint();
+        let done = loop {
+            let undone_to = runtime.timeline().state().pc();
+            let undone_sp = read_location(&runtime, Location::Register(SP));
+            if !self.undo_step(&mut runtime) {
+                break false;
+            }
 
-        Ok(serde_wasm_bindgen::to_value(&step_result)?)
+            let pc = runtime.timeline().state().pc();
+            let sp = read_location(&runtime, Location::Register(SP));
+            let position = self.binary.line_numbers.get(&pc).cloned();
+            let flow = self.flow_at(pc);
+            self.call_stack.undo(pc, flow);
+
+            if stepping.is_done_going_back(pc, flow, sp, &position, undone_to, undone_sp) {
+                break true;
+            }
+
+            let at_breakpoint = self
+                .breakpoint_addrs
+                .get(&pc)
+                .and_then(|location| self.breakpoints.get(location))
+                .is_some_and(|breakpoint| {
+                    breakpoint.stops_going_back(&|location| read_location(&runtime, location))
+                });
+            if at_breakpoint {
+                break false;
+            }
+        };
+
+        self.mipsy_runtime = Some(Ok(runtime));
+        self.watchpoints.forget_step();
+        self.invalidate_register_cache();
+
+        done
+    }
+
+    /// Goes back one step, taking back the hit counted at a breakpoint if
+    /// that's where the step being undone got to. Returns whether there was a
+    /// step to go back.
+    fn undo_step(&mut self, runtime: &mut Runtime) -> bool {
+        let breakpoint = self
+            .breakpoint_addrs
+            .get(&runtime.timeline().state().pc())
+            .cloned();
+        let counted = breakpoint
+            .as_ref()
+            .and_then(|location| self.breakpoints.get(location))
+            .is_some_and(|breakpoint| {
+                breakpoint.counts_hit(&|location| read_location(runtime, location))
+            });
+
+        if !runtime.timeline_mut().pop_last_state() {
+            return false;
+        }
+
+        if counted {
+            if let Some(breakpoint) =
+                breakpoint.and_then(|location| self.breakpoints.get_mut(&location))
+            {
+                breakpoint.unhit();
+            }
+        }
+
+        true
+    }
+
+    fn flow_at(&self, pc: u32) -> Flow {
+        word_at(&self.binary, pc).map_or(Flow::Next, |word| Decoded::new(word).flow(pc))
+    }
+
+    /// The functions which have been called and haven't returned yet,
+    /// innermost first, starting with the current instruction. These are
+    /// worked out by keeping track of each call and return as the program
+    /// runs (or steps backwards).
+    pub fn get_call_stack(&self) -> Result<JsValue, JsValue> {
+        let frames = match self.last_pc {
+            Some(pc) => self.call_stack.frames(pc, &self.binary.line_numbers),
+            None => vec![],
+        };
+
+        Ok(serde_wasm_bindgen::to_value(&frames)?)
+    }
+
+    /// Where the current instruction came from.
+    fn position(&self) -> Position {
+        match &self.mipsy_runtime {
+            Some(Ok(runtime)) => self
+                .binary
+                .line_numbers
+                .get(&runtime.timeline().state().pc())
+                .cloned(),
+            _ => None,
+        }
     }
 
     pub fn perform_disassembly(&self, start_address: u32, count: u32) -> Result<JsValue, JsValue> {
@@ -312,11 +1396,7 @@ impl DebugRuntime {
         for i in 0..count {
             let address = start_address + 4 * i;
             let word = (|| {
-                let line_num = self
-                    .binary
-                    .line_numbers
-                    .get(&address)
-                    .map(|&(_, line_num)| line_num);
+                let line_num = self.binary.line_numbers.get(&address);
 
                 let (index, vec) = match get_segment(address) {
                     Segment::Text => Some((address - TEXT_BOT, &binary.text)),
@@ -348,6 +1428,7 @@ impl DebugRuntime {
                     let decompiled = decompile_inst_into_parts(binary, iset, word, text_addr);
                     DisassembleResponse {
                         address,
+                        file_tag: line_num.map(|(tag, _)| tag.to_string()),
                         instruction: std::format!(
                             "{:7} {}",
                             decompiled
@@ -355,7 +1436,7 @@ impl DebugRuntime {
                                 .unwrap_or("[unknown instruction]".into()),
                             decompiled.arguments.join(", ")
                         ),
-                        line_num,
+                        line_num: line_num.map(|&(_, line)| line),
                         instruction_bytes: Some(std::format!("0x{:08X}", word)),
                         symbols: None,
                     }
@@ -363,13 +1444,15 @@ impl DebugRuntime {
                 Some((_, line_num, Safe::Uninitialised)) => DisassembleResponse {
                     address,
                     instruction: "[uninitialised]".into(),
-                    line_num,
+                    file_tag: line_num.map(|(tag, _)| tag.to_string()),
+                    line_num: line_num.map(|&(_, line)| line),
                     instruction_bytes: Some("  ????????".into()),
                     symbols: None,
                 },
                 None => DisassembleResponse {
                     address,
                     instruction: "".into(),
+                    file_tag: None,
                     line_num: None,
                     instruction_bytes: None,
                     symbols: None,
@@ -382,32 +1465,34 @@ impl DebugRuntime {
 
     pub fn get_syscall_type(&self) -> String {
         use mipsy_lib::runtime::RuntimeSyscallGuard::*;
-        match &self.mipsy_runtime {
-            Some(Ok(_)) => "none",
+        let number = match &self.mipsy_runtime {
             Some(Err(guard)) => match guard {
-                PrintInt(_, _) => "print",
-                PrintFloat(_, _) => "print",
-                PrintDouble(_, _) => "print",
-                PrintString(_, _) => "print",
-                PrintChar(_, _) => "print",
-                ReadInt(_) => "read_int",
-                ReadFloat(_) => "read_float",
-                ReadDouble(_) => "read_double",
-                ReadChar(_) => "read_character",
-                ReadString(_, _) => "read_string",
-                Sbrk(_, _) => "sbrk",
-                Exit(_) => "exit",
-                Open(_, _) => "open",
-                Read(_, _) => "read",
-                Write(_, _) => "write",
-                Close(_, _) => "close",
-                ExitStatus(_, _) => "exit",
-                Breakpoint(_) => "breakpoint",
-                Trap(_) => "trap",
+                PrintInt(_, _) => 1,
+                PrintFloat(_, _) => 2,
+                PrintDouble(_, _) => 3,
+                PrintString(_, _) => 4,
+                ReadInt(_) => 5,
+                ReadFloat(_) => 6,
+                ReadDouble(_) => 7,
+                ReadString(_, _) => 8,
+                Sbrk(_, _) => 9,
+                Exit(_) => 10,
+                PrintChar(_, _) => 11,
+                ReadChar(_) => 12,
+                Open(_, _) => 13,
+                Read(_, _) => 14,
+                Write(_, _) => 15,
+                Close(_, _) => 16,
+                ExitStatus(_, _) => 17,
+                Breakpoint(_) => return "breakpoint".into(),
+                Trap(_) => return "trap".into(),
             },
-            None => "none",
-        }
-        .into()
+            Some(Ok(_)) | None => return "none".into(),
+        };
+
+        syscalls::find_syscall(number)
+            .map_or("none", |syscall| syscall.kind)
+            .into()
     }
 
     pub fn do_print(&mut self) -> String {
@@ -555,16 +1640,87 @@ impl DebugRuntime {
         }
     }
 
+    /// Stops if the instruction which just ran triggered a watchpoint (see
+    /// `take_watchpoint_hit`), or at the breakpoint on the current
+    /// instruction, if there is one and its condition and hit count say to.
+    /// Logpoints don't stop, but leave a message for `take_log_messages`.
     pub fn check_for_breakpoint(&mut self) {
-        if let Some(Ok(runtime)) = &self.mipsy_runtime {
-            if self
-                .breakpoint_addrs
-                .contains(&runtime.timeline().state().pc())
-            {
-                self.mipsy_runtime = Some(Err(mipsy_lib::runtime::RuntimeSyscallGuard::Breakpoint(
-                    self.mipsy_runtime.take().unwrap().ok().unwrap(), // ughh
-                )))
+        let runtime = match &self.mipsy_runtime {
+            Some(Ok(runtime)) => runtime,
+            _ => return,
+        };
+        let read = |location| read_location(runtime, location);
+
+        let watchpoint_hit = self.watchpoints.after_step(&read);
+        let watched = watchpoint_hit.is_some();
+        if let Some(hit) = watchpoint_hit {
+            self.watchpoint_hit = Some(self.change_report(hit.pc, hit.description));
+        }
+
+        let stop = match self
+            .breakpoint_addrs
+            .get(&runtime.timeline().state().pc())
+            .and_then(|location| self.breakpoints.get_mut(location))
+            .map(|breakpoint| breakpoint.hit(&read))
+        {
+            None | Some(BreakpointHit::Continue) => false,
+            Some(BreakpointHit::Stop) => true,
+            Some(BreakpointHit::Log(message)) => {
+                self.log_messages.push(message);
+                false
+            }
+            Some(BreakpointHit::Error(message)) => {
+                self.log_messages.push(message);
+                true
+            }
+        };
+
+        if stop || watched {
+            if let Some(Ok(runtime)) = self.mipsy_runtime.take() {
+                self.mipsy_runtime = Some(Err(RuntimeSyscallGuard::Breakpoint(runtime)));
+            }
+        }
+    }
+
+    /// The messages logged by logpoints since this was last called.
+    pub fn take_log_messages(&mut self) -> Vec<JsValue> {
+        self.log_messages.drain(..).map(JsValue::from).collect()
+    }
+
+    pub fn has_log_messages(&self) -> bool {
+        !self.log_messages.is_empty()
+    }
+
+    /// Why the program stopped at a watchpoint, if it did since this was
+    /// last called.
+    pub fn take_watchpoint_hit(&mut self) -> Result<JsValue, JsValue> {
+        Ok(serde_wasm_bindgen::to_value(&self.watchpoint_hit.take())?)
+    }
+
+    fn change_report(&self, pc: u32, description: String) -> ChangeReport {
+        let instruction = match word_at(&self.binary, pc) {
+            Some(word) => {
+                let decompiled = decompile_inst_into_parts(&self.binary, &self.iset, word, pc);
+                std::format!(
+                    "{} {}",
+                    decompiled
+                        .inst_name
+                        .unwrap_or("[unknown instruction]".into()),
+                    decompiled.arguments.join(", ")
+                )
+                .trim()
+                .to_string()
             }
+            None => "[unknown instruction]".into(),
+        };
+        let position = self.binary.line_numbers.get(&pc);
+
+        ChangeReport {
+            pc,
+            instruction,
+            file_tag: position.map(|(tag, _)| tag.to_string()),
+            line: position.map(|&(_, line)| line),
+            description,
         }
     }
 
@@ -644,8 +1800,11 @@ impl DebugRuntime {
         vec
     }
 
-    pub fn get_line_num(&mut self) -> Option<u32> {
-        self.get_pc()
+    /// The file and line the current instruction came from, as a
+    /// `SourcePosition`.
+    pub fn get_line_num(&mut self) -> Result<JsValue, JsValue> {
+        let position = self
+            .get_pc()
             .filter(|&pc| pc <= mipsy_lib::compile::TEXT_TOP)
             .and_then(|pc| {
                 self.binary.line_numbers.get(&pc).or_else(|| {
@@ -660,7 +1819,12 @@ impl DebugRuntime {
                     lines.last().map(|&(_, pair)| pair)
                 })
             })
-            .map(|(_, line)| *line)
+            .map(|(file_tag, line)| SourcePosition {
+                file_tag: file_tag.to_string(),
+                line: *line,
+            });
+
+        Ok(serde_wasm_bindgen::to_value(&position)?)
     }
 
     fn force_get_runtime(&mut self) -> Option<(Runtime, bool)> {
@@ -705,15 +1869,109 @@ impl DebugRuntime {
             None => return false,
         };
 
-        let success = runtime.timeline_mut().pop_last_state();
+        let success = self.undo_step(&mut runtime);
+        self.watchpoints.forget_step();
+        self.stepping = None;
+
+        if success {
+            let pc = runtime.timeline().state().pc();
+            self.call_stack.undo(pc, self.flow_at(pc));
+        }
+
+        let hit_breakpoint = stop_on_breakpoint
+            && self
+                .breakpoint_addrs
+                .get(&runtime.timeline().state().pc())
+                .and_then(|location| self.breakpoints.get(location))
+                .is_some_and(|breakpoint| {
+                    breakpoint.stops_going_back(&|location| read_location(&runtime, location))
+                });
+
         self.mipsy_runtime = Some(Ok(runtime));
+        self.invalidate_register_cache();
+
+        success && !hit_breakpoint
+    }
+
+    /// Steps backwards to just before the last instruction which changed
+    /// `name` (a register, or an expression for an address with `bytes` of
+    /// memory, as for `watchpoint_info`), returning what it changed. If it
+    /// hasn't changed since the program started, the program stays where it
+    /// is and this returns nothing.
+    pub fn step_back_to_last_write(
+        &mut self,
+        name: &str,
+        bytes: Option<u32>,
+    ) -> Result<JsValue, JsValue> {
+        let (mut runtime, step_afterwards) = match self.force_get_runtime() {
+            Some(pair) => pair,
+            None => return Err("there's no program running".into()),
+        };
+
+        let target = WatchTarget::from_name(name, bytes, &self.binary.labels, &|location| {
+            read_location(&runtime, location)
+        });
+
+        // look back through the timeline for the change before going anywhere
+        let change = target.as_ref().ok().and_then(|target| {
+            let timeline = runtime.timeline();
+            let values_at = |step| {
+                timeline
+                    .nth_state(step)
+                    .map(|state| target.values(&|location| read_state(state, location)))
+            };
+
+            let mut value = values_at(timeline.timeline_len() - 1)?;
+            for step in (0..timeline.timeline_len() - 1).rev() {
+                let before = values_at(step)?;
+                if before != value {
+                    return Some((step, before, value));
+                }
+                value = before;
+            }
+
+            None
+        });
+
+        let (target, (step, old, new)) = match (target, change) {
+            (Ok(target), Some(change)) => (target, change),
+            (target, _) => {
+                self.mipsy_runtime = if step_afterwards {
+                    Some(runtime.step().unwrap_or_else(|(runtime, _)| Ok(runtime)))
+                } else {
+                    Some(Ok(runtime))
+                };
+
+                return match target {
+                    Ok(_) => Ok(JsValue::NULL),
+                    Err(err) => {
+                        Err(std::format!("couldn't work out what `{}` is: {}", name, err).into())
+                    }
+                };
+            }
+        };
+
+        while runtime.timeline().timeline_len() > step + 1 && self.undo_step(&mut runtime) {
+            let pc = runtime.timeline().state().pc();
+            self.call_stack.undo(pc, self.flow_at(pc));
+        }
 
+        let pc = runtime.timeline().state().pc();
+        self.mipsy_runtime = Some(Ok(runtime));
+        self.watchpoints.forget_step();
         self.invalidate_register_cache();
 
-        let hit_breakpoint =
-            stop_on_breakpoint && self.breakpoint_addrs.contains(&self.get_pc().unwrap_or(0));
+        let report = self.change_report(
+            pc,
+            std::format!(
+                "{} changed from {} to {}",
+                target.describe(),
+                target.format_values(&old),
+                target.format_values(&new)
+            ),
+        );
 
-        success && !hit_breakpoint
+        Ok(serde_wasm_bindgen::to_value(&Some(report))?)
     }
 
     pub fn remove_runtime(&mut self) {
@@ -759,43 +2017,248 @@ impl DebugRuntime {
         result
     }
 
-    pub fn set_breakpoints_from_lines(&mut self, breakpoint_lines: Vec<u32>) -> Vec<u32> {
-        if breakpoint_lines.is_empty() {
-            self.breakpoint_addrs.clear();
-            return vec![];
+    /// Replaces the breakpoints in the file tagged `file_tag` with
+    /// `breakpoints` (DAP `SourceBreakpoint`s, with a line and optionally a
+    /// condition, hit condition and log message), returning whether each
+    /// one could be set.
+    pub fn set_breakpoints(
+        &mut self,
+        file_tag: &str,
+        breakpoints: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let breakpoints: Vec<SourceBreakpoint> = serde_wasm_bindgen::from_value(breakpoints)?;
+        let file_tag: Rc<str> = file_tag.into();
+
+        let mut first_addrs: HashMap<u32, u32> = HashMap::new();
+        for (&addr, (tag, line)) in &self.binary.line_numbers {
+            if **tag == *file_tag {
+                let first = first_addrs.entry(*line).or_insert(addr);
+                *first = addr.min(*first);
+            }
         }
 
-        let memory_locations = self
-            .binary
-            .line_numbers
+        let replaced: Vec<(Rc<str>, u32)> = self
+            .breakpoints
+            .keys()
+            .filter(|(tag, _)| *tag == file_tag)
+            .cloned()
+            .collect();
+        let mut previous: HashMap<u32, Breakpoint> = replaced
+            .into_iter()
+            .filter_map(|location| self.breakpoints.remove_entry(&location))
+            .map(|((_, line), breakpoint)| (line, breakpoint))
+            .collect();
+        self.breakpoint_addrs.retain(|_, (tag, _)| *tag != file_tag);
+
+        let statuses: Vec<BreakpointStatus> = breakpoints
             .iter()
-            .filter(|(_, (_, line))| breakpoint_lines.contains(line))
-            .collect::<Vec<_>>();
+            .map(|source| {
+                let addr = match first_addrs.get(&source.line) {
+                    Some(&addr) => addr,
+                    None => {
+                        return BreakpointStatus {
+                            line: source.line,
+                            verified: false,
+                            message: Some("there's no instruction on this line".into()),
+                        }
+                    }
+                };
+
+                match Breakpoint::new(source, &self.binary.labels) {
+                    Ok(mut breakpoint) => {
+                        if let Some(previous) = previous.remove(&source.line) {
+                            breakpoint.carry_over_hits(&previous);
+                        }
 
-        self.breakpoint_addrs = memory_locations.iter().map(|(&addr, _)| addr).collect();
+                        let location = (file_tag.clone(), source.line);
+                        self.breakpoints.insert(location.clone(), breakpoint);
+                        self.breakpoint_addrs.insert(addr, location);
 
-        memory_locations
-            .iter()
-            .map(|(_, &(_, line))| line)
-            .collect()
+                        BreakpointStatus {
+                            line: source.line,
+                            verified: true,
+                            message: None,
+                        }
+                    }
+                    Err(message) => BreakpointStatus {
+                        line: source.line,
+                        verified: false,
+                        message: Some(message),
+                    },
+                }
+            })
+            .collect();
+
+        Ok(serde_wasm_bindgen::to_value(&statuses)?)
+    }
+
+    /// What can be watched for `name` (DAP's `dataBreakpointInfo`): a
+    /// register, or an expression for an address, with `bytes` (by default
+    /// a word) of memory from there.
+    pub fn watchpoint_info(&mut self, name: &str, bytes: Option<u32>) -> Result<JsValue, JsValue> {
+        let (runtime, step_afterwards) = match self.force_get_runtime() {
+            Some(pair) => pair,
+            None => return Err("there's no program running".into()),
+        };
+
+        let target = WatchTarget::from_name(name, bytes, &self.binary.labels, &|location| {
+            read_location(&runtime, location)
+        });
+
+        self.mipsy_runtime = if step_afterwards {
+            Some(runtime.step().unwrap_or_else(|(runtime, _)| Ok(runtime)))
+        } else {
+            Some(Ok(runtime))
+        };
+
+        let info = match target {
+            Ok(target) => WatchpointInfo {
+                data_id: Some(target.data_id()),
+                description: target.describe(),
+                access_types: target.access_types(),
+            },
+            Err(err) => WatchpointInfo {
+                data_id: None,
+                description: std::format!("`{}` can't be watched: {}", name, err),
+                access_types: vec![],
+            },
+        };
+
+        Ok(serde_wasm_bindgen::to_value(&info)?)
+    }
+
+    /// Replaces every watchpoint with `watchpoints` (DAP `DataBreakpoint`s,
+    /// whose `dataId`s come from `watchpoint_info`), returning whether each
+    /// one could be set.
+    pub fn set_watchpoints(&mut self, watchpoints: JsValue) -> Result<JsValue, JsValue> {
+        let watchpoints: Vec<DataBreakpoint> = serde_wasm_bindgen::from_value(watchpoints)?;
+        let statuses = self.watchpoints.set(&watchpoints, &self.binary.labels);
+
+        Ok(serde_wasm_bindgen::to_value(&statuses)?)
+    }
+}
+
+/// Reads a register or byte of memory for a breakpoint's condition.
+fn read_location(runtime: &Runtime, location: Location) -> Option<i32> {
+    read_state(runtime.timeline().state(), location)
+}
+
+fn read_state(state: &State, location: Location) -> Option<i32> {
+    match location {
+        Location::Register(reg) => match state.registers()[reg as usize] {
+            Safe::Valid(value) => Some(value),
+            Safe::Uninitialised => None,
+        },
+        Location::Hi => state.read_hi().ok(),
+        Location::Lo => state.read_lo().ok(),
+        Location::Pc => Some(state.pc() as i32),
+        Location::Byte(addr) => {
+            let offset = addr % PAGE_SIZE as u32;
+            match state.pages().get(&(addr - offset))?[offset as usize] {
+                Safe::Valid(byte) => Some(byte.into()),
+                Safe::Uninitialised => None,
+            }
+        }
     }
 }
 
+/// Compiles a program to be run in the debugger, along with any other files
+/// that make up the program (like `test_compile`).
 #[wasm_bindgen]
-pub fn make_new_runtime(source: &str, filename: &str) -> Result<DebugRuntime, String> {
+pub fn make_new_runtime(
+    source: &str,
+    filename: &str,
+    other_files: JsValue,
+    options: JsValue,
+) -> Result<DebugRuntime, String> {
     let iset = mipsy_instructions::inst_set();
+    let other_files: Vec<FilenameAndSource> = serde_wasm_bindgen::from_value(other_files)
+        .map_err(|_| "the other files of your program couldn't be read".to_string())?;
+    let options = ProgramOptions::from_js(options)
+        .map_err(|_| "the options for running your program don't make sense".to_string())?;
 
-    compile_from_source(source, filename, "run", &iset).map(|binary| {
+    compile_from_source(source, filename, &other_files, "run", &iset, &options).map(|binary| {
         let mut runtime = DebugRuntime {
             binary: binary.to_owned(),
-            mipsy_runtime: Some(Ok(mipsy_lib::runtime(&binary, &[]))),
-            breakpoint_addrs: HashSet::new(),
+            mipsy_runtime: Some(Ok(mipsy_lib::runtime(&binary, &options.runtime_args()))),
+            breakpoints: HashMap::new(),
+            breakpoint_addrs: HashMap::new(),
+            log_messages: vec![],
+            watchpoints: Watchpoints::default(),
+            watchpoint_hit: None,
+            stepping: None,
+            call_stack: CallStack::new(&binary),
             registers: None,
             last_pc: None,
             iset,
-            sources: vec![(filename.into(), source.into())],
+            sources: all_sources(filename, source, &other_files)
+                .into_iter()
+                .map(|(tag, source)| (tag.into(), source.into()))
+                .collect(),
         };
         runtime.invalidate_register_cache();
         runtime
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(source: &str, other_files: &[FilenameAndSource]) -> Vec<(u32, bool)> {
+        diagnostics(
+            source,
+            "test.s",
+            other_files,
+            100,
+            true,
+            &ProgramOptions::default(),
+        )
+        .into_iter()
+        .map(|err| (err.line, err.is_warning))
+        .collect()
+    }
+
+    #[test]
+    fn reports_every_line_which_doesnt_parse() {
+        let source = "main:\n\tadd $t0 $t1\n\tli $v0, 1\n\tfoo bar baz\n\tjr $ra\n";
+
+        assert_eq!(problems(source, &[]), [(2, false), (4, false)]);
+    }
+
+    #[test]
+    fn reports_a_compile_error_before_a_parse_error() {
+        let source = "main:\n\tj nowhere\n\tadd $t0 $t1\n\tjr $ra\n";
+
+        assert_eq!(problems(source, &[]), [(2, false), (3, false)]);
+    }
+
+    #[test]
+    fn keeps_the_labels_on_lines_which_dont_parse() {
+        let source = "main:\tj end\nend:\tadd $t0 $t1\nN = 1 +\n\tli $t0, N\n\tjr $ra\n";
+
+        assert_eq!(problems(source, &[]), [(2, false), (3, false)]);
+    }
+
+    #[test]
+    fn mentions_errors_in_other_files_once() {
+        let other = FilenameAndSource {
+            filename: "other.s".into(),
+            source: "f:\tadd $t0 $t1\ng:\tadd $t0 $t1\n".into(),
+        };
+        let source = "main:\n\tjal f\n\tadd $t0 $t1\n\tjr $ra\n";
+
+        assert_eq!(problems(source, &[other]), [(3, false), (0, true)]);
+    }
+
+    #[test]
+    fn stops_at_max_problems() {
+        let source = "main:\n\tadd $t0 $t1\n\tadd $t0 $t1\n\tadd $t0 $t1\n\tjr $ra\n";
+        let reports = diagnostics(source, "test.s", &[], 2, true, &ProgramOptions::default());
+
+        assert_eq!(
+            reports.iter().map(|err| err.line).collect::<Vec<_>>(),
+            [2, 3]
+        );
+    }
+}
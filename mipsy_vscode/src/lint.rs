@@ -0,0 +1,295 @@
+use std::collections::HashSet;
+
+use mipsy_lib::{
+    util::{get_segment, Segment},
+    Binary,
+};
+use mipsy_parser::{MpArgument, MpImmediate, MpItem, MpNumber};
+
+use crate::{
+    dead_code,
+    decode::{register_bit, registers_in, text_instructions, Flow, RegisterSet},
+    diagnostics::Warnings,
+    parsed::{NameUse, ParsedFile},
+    registers::{is_temporary, register_name, K0, K1, ZERO},
+    uninitialised, ErrorReport,
+};
+
+/// How many bytes a load/store instruction accesses, so how its address should be aligned.
+fn access_alignment(mnemonic: &str) -> Option<i64> {
+    match mnemonic {
+        "lh" | "lhu" | "sh" => Some(2),
+        "lw" | "sw" | "ll" | "sc" | "lwc1" | "swc1" | "l.s" | "s.s" => Some(4),
+        "ldc1" | "sdc1" | "l.d" | "s.d" => Some(8),
+        _ => None,
+    }
+}
+
+pub fn is_branch_or_jump(mnemonic: &str) -> bool {
+    matches!(mnemonic, "j" | "jal") || (mnemonic.starts_with('b') && mnemonic != "break")
+}
+
+/// Warnings which can be found by looking at the compiled instructions.
+fn lint_instructions(binary: &Binary, warnings: &mut Warnings) {
+    let file_tag = warnings.file_tag().to_owned();
+    let labelled: HashSet<u32> = binary.labels.values().copied().collect();
+    let temporaries: RegisterSet = (0..32)
+        .filter(|&reg| is_temporary(reg))
+        .map(register_bit)
+        .fold(0, |set, bit| set | bit);
+
+    // temporaries that may have been overwritten by the last function call
+    let mut clobbered: RegisterSet = 0;
+
+    for (address, inst) in text_instructions(binary) {
+        if labelled.contains(&address) {
+            // control flow could come from anywhere
+            clobbered = 0;
+        }
+
+        let line = binary
+            .line_numbers
+            .get(&address)
+            .filter(|(tag, _)| **tag == *file_tag)
+            .map(|&(_, line)| line);
+
+        if let Some(line) = line {
+            if inst.writes() & register_bit(ZERO) != 0 && !inst.is_nop() {
+                warnings.warn_register(
+                    line,
+                    ZERO,
+                    "writing to `$zero` has no effect".into(),
+                    vec!["`$zero` always contains 0, no matter what's written to it".into()],
+                );
+            }
+
+            for reg in [K0, K1] {
+                if (inst.reads() | inst.writes()) & register_bit(reg) != 0 {
+                    warnings.warn_register(
+                        line,
+                        reg,
+                        std::format!("`{}` is reserved for the kernel", register_name(reg)),
+                        vec!["the exception handler can change it at any time, so use a `$t` or `$s` register instead".into()],
+                    );
+                }
+            }
+
+            for reg in registers_in(inst.reads() & clobbered) {
+                warnings.warn_register(
+                    line,
+                    reg,
+                    std::format!(
+                        "`{}` is read after a function call, but isn't preserved across calls",
+                        register_name(reg)
+                    ),
+                    vec![
+                        "the called function is allowed to change any `$t` register".into(),
+                        "save the value in an `$s` register or on the stack instead".into(),
+                    ],
+                );
+            }
+        }
+
+        // only report each overwritten register once
+        clobbered &= !(inst.reads() | inst.writes());
+
+        if matches!(inst.flow(address), Flow::Call(_) | Flow::CallIndirect) {
+            clobbered = temporaries;
+        }
+    }
+}
+
+/// The label an operand is, as mipsy parsed it (e.g. `lw $t0, arr` or `j loop`).
+pub fn label_operand(argument: &MpArgument) -> Option<&str> {
+    match argument {
+        MpArgument::Number(MpNumber::Immediate(MpImmediate::LabelReference(name))) => Some(name),
+        _ => None,
+    }
+}
+
+/// Warnings which need to look at the operands as they're written in the source.
+fn lint_operands(binary: &Binary, source: &str, tab_size: u32, warnings: &mut Warnings) {
+    let file = ParsedFile::parse(warnings.file_tag(), source, tab_size);
+
+    for (index, line) in file.lines.iter().enumerate() {
+        let line_num = index as u32 + 1;
+        let instructions = line.items.iter().flatten().filter_map(|item| match item {
+            MpItem::Instruction(instruction) => Some(instruction),
+            _ => None,
+        });
+
+        for instruction in instructions {
+            let mnemonic = instruction.name().to_ascii_lowercase();
+            let arguments = instruction.arguments();
+
+            // where a label is written on the line, to point the warning at
+            let span_of = |label: &str| {
+                line.names()
+                    .into_iter()
+                    .find(|(token, name_use)| {
+                        *name_use == NameUse::Reference && token.text == label
+                    })
+                    .map_or_else(
+                        || line.statement_span(),
+                        |(token, _)| (token.start, token.end),
+                    )
+            };
+
+            let accessed = access_alignment(&mnemonic).and_then(|alignment| {
+                let label = label_operand(arguments.get(1)?)?;
+                Some((alignment, label, *binary.labels.get(label)?))
+            });
+            if let Some((alignment, label, address)) = accessed {
+                if i64::from(address) % alignment != 0 {
+                    let (start, end) = span_of(label);
+                    warnings.warn_span(
+                        line_num,
+                        start,
+                        end,
+                        std::format!(
+                            "`{}` accesses address 0x{:08X}, which isn't a multiple of {}",
+                            mnemonic,
+                            address,
+                            alignment
+                        ),
+                        vec![std::format!(
+                            "add `.align {}` before the label's data so that it's aligned",
+                            alignment.trailing_zeros()
+                        )],
+                    );
+                }
+            }
+
+            if is_branch_or_jump(&mnemonic) {
+                let target = arguments
+                    .last()
+                    .and_then(|argument| label_operand(argument));
+                let in_data = target.is_some_and(|label| {
+                    binary
+                        .labels
+                        .get(label)
+                        .is_some_and(|&address| matches!(get_segment(address), Segment::Data))
+                });
+
+                if let (Some(label), true) = (target, in_data) {
+                    let (start, end) = span_of(label);
+                    warnings.warn_span(
+                        line_num,
+                        start,
+                        end,
+                        std::format!(
+                            "`{}` is a label in the `.data` segment, not an instruction",
+                            label
+                        ),
+                        vec!["jumping to it would try to run your data as code".into()],
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Finds likely mistakes in a program that compiles successfully. Only warnings
+/// for the file tagged `file_tag` are returned. `check_main` is whether the
+/// program was compiled with the kernel, which is then where it starts running.
+pub fn lint_program(
+    binary: &Binary,
+    sources: &[(&str, &str)],
+    file_tag: &str,
+    check_main: bool,
+    tab_size: u32,
+) -> Vec<ErrorReport> {
+    let source = sources
+        .iter()
+        .find(|&&(tag, _)| tag == file_tag)
+        .map_or("", |&(_, source)| source);
+
+    let mut warnings = Warnings::new(file_tag, source, tab_size);
+    lint_instructions(binary, &mut warnings);
+    lint_operands(binary, source, tab_size, &mut warnings);
+    dead_code::check_dead_code(binary, sources, check_main, tab_size, &mut warnings);
+    uninitialised::check_uninitialised(binary, &mut warnings);
+
+    warnings.into_reports()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::compile;
+
+    /// The lines with a warning whose message contains `about`.
+    fn warned(source: &str, about: &str) -> Vec<u32> {
+        let binary = compile(source);
+
+        lint_program(&binary, &[("test.s", source)], "test.s", true, 8)
+            .into_iter()
+            .filter(|warning| warning.message.contains(about))
+            .map(|warning| warning.line)
+            .collect()
+    }
+
+    #[test]
+    fn warns_about_writing_to_zero() {
+        let source = "main:\n\tli $t0, 1\n\tadd $zero, $t0, $t0\n\tnop\n\tjr $ra\n";
+
+        assert_eq!(warned(source, "writing to `$zero`"), [3]);
+    }
+
+    #[test]
+    fn warns_about_kernel_registers() {
+        let source = "main:\n\tli $k0, 1\n\tmove $t0, $k1\n\tli $t1, 2\n\tjr $ra\n";
+
+        assert_eq!(warned(source, "reserved for the kernel"), [2, 3]);
+    }
+
+    #[test]
+    fn warns_about_temporaries_read_after_a_call() {
+        let source = "\
+main:
+\tli $t0, 1
+\tli $s0, 2
+\tjal f
+\tmove $a0, $t0
+\tmove $a1, $s0
+\tmove $a2, $t0
+\tjr $ra
+f:
+\tjr $ra
+";
+
+        assert_eq!(warned(source, "isn't preserved across calls"), [5]);
+    }
+
+    #[test]
+    fn warns_about_unaligned_accesses_to_labels() {
+        let source = "\
+main:
+\tlw $t0, bytes
+\tlb $t1, bytes
+\tlw $t2, word
+\tjr $ra
+\t.data
+c:\t.byte 1
+bytes:\t.byte 1, 2, 3, 4
+\t.align 2
+word:\t.word 5
+";
+
+        assert_eq!(warned(source, "isn't a multiple of 4"), [2]);
+    }
+
+    #[test]
+    fn warns_about_jumping_to_data() {
+        let source = "main:\n\tjal msg\n\tjal main\n\tjr $ra\n\t.data\nmsg:\t.asciiz \"hi\"\n";
+
+        assert_eq!(warned(source, "is a label in the `.data` segment"), [2]);
+    }
+
+    #[test]
+    fn warns_about_code_after_a_jump_or_return() {
+        let source = "main:\n\tj end\n\tli $t0, 1\nend:\n\tli $v0, 0\n\tjr $ra\n\tli $t1, 2\n";
+
+        assert_eq!(warned(source, "can never be run"), [3, 7]);
+    }
+}
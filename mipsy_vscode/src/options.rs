@@ -0,0 +1,65 @@
+use mipsy_lib::compile::CompilerOptions;
+use mipsy_utils::MipsyConfig;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+use crate::lexer::DEFAULT_TAB_SIZE;
+
+/// How to compile (and run) a program, from the editor's settings. These
+/// match the `mipsy` command line's options, so that the editor compiles the
+/// program the same way the course does.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ProgramOptions {
+    /// the arguments passed to `main` (in `argv`), after the program's name
+    pub args: Vec<String>,
+    /// the width of a tab when reporting columns, unless the file sets it
+    /// with `#![tabsize(N)]`
+    pub tab_size: u32,
+    /// whether to behave like SPIM, as `mipsy --spim` does
+    pub spim: bool,
+}
+
+impl Default for ProgramOptions {
+    fn default() -> Self {
+        ProgramOptions {
+            args: vec![],
+            tab_size: DEFAULT_TAB_SIZE,
+            spim: false,
+        }
+    }
+}
+
+impl ProgramOptions {
+    /// Reads the options passed to an exported function, where `undefined`
+    /// or `null` means the defaults. A tab size of 0 means the default, as it
+    /// does in `#![tabsize(N)]`.
+    pub fn from_js(options: JsValue) -> Result<Self, JsValue> {
+        if options.is_undefined() || options.is_null() {
+            return Ok(ProgramOptions::default());
+        }
+
+        let mut options: ProgramOptions = serde_wasm_bindgen::from_value(options)?;
+        if options.tab_size == 0 {
+            options.tab_size = DEFAULT_TAB_SIZE;
+        }
+
+        Ok(options)
+    }
+
+    pub fn compiler_options(&self) -> CompilerOptions {
+        CompilerOptions::new(self.args.clone())
+    }
+
+    pub fn config(&self) -> MipsyConfig {
+        MipsyConfig {
+            tab_size: self.tab_size,
+            spim: self.spim,
+        }
+    }
+
+    /// The arguments to give the runtime.
+    pub fn runtime_args(&self) -> Vec<&str> {
+        self.args.iter().map(String::as_str).collect()
+    }
+}
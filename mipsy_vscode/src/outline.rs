@@ -0,0 +1,360 @@
+use std::collections::{HashMap, HashSet};
+
+use mipsy_lib::Binary;
+use mipsy_parser::{MpDirective, MpItem};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    calling_convention::find_functions,
+    decode::{text_instructions, Decoded},
+    lexer::{tokenise_line, utf16_col, Token, TokenKind},
+    lint::label_operand,
+    parsed::{NameUse, ParsedFile, ParsedLine},
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum OutlineKind {
+    Segment,
+    Function,
+    /// a label in the text segment which isn't a function, e.g. a loop
+    Label,
+    DataLabel,
+    Constant,
+}
+
+/// A node of a file's outline, covering a range of lines.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OutlineNode {
+    pub name: String,
+    pub kind: OutlineKind,
+    /// 0-indexed; `end_line` is inclusive, and leaves off any blank lines at
+    /// the end
+    pub line: u32,
+    pub end_line: u32,
+    /// the (UTF-16) range of the name on `line`
+    pub col: u32,
+    pub col_end: u32,
+    pub children: Vec<OutlineNode>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Text,
+    Data,
+}
+
+fn segment_directive(item: &MpItem) -> Option<Segment> {
+    match item {
+        MpItem::Directive(MpDirective::Text | MpDirective::KText) => Some(Segment::Text),
+        MpItem::Directive(MpDirective::Data | MpDirective::KData) => Some(Segment::Data),
+        _ => None,
+    }
+}
+
+/// The functions in a program: those found by the calling convention checks
+/// if it compiles, or otherwise just the labels called with `jal` (or `bal`
+/// etc), along with `main` either way.
+pub fn function_names(binary: Option<&Binary>, files: &[ParsedFile]) -> HashSet<String> {
+    let mut functions: HashSet<String> = ["main".to_string()].into_iter().collect();
+
+    if let Some(binary) = binary {
+        let instructions: HashMap<u32, Decoded> = text_instructions(binary).collect();
+        functions.extend(
+            find_functions(binary, &instructions)
+                .into_iter()
+                .map(|function| function.name),
+        );
+        return functions;
+    }
+
+    for line in files.iter().flat_map(|file| &file.lines) {
+        for item in line.items.iter().flatten() {
+            let MpItem::Instruction(instruction) = item else {
+                continue;
+            };
+
+            let name = instruction.name().to_ascii_lowercase();
+            if name == "jal" || (name.starts_with('b') && name.ends_with("al")) {
+                functions.extend(
+                    instruction
+                        .arguments()
+                        .last()
+                        .and_then(|argument| label_operand(argument))
+                        .map(str::to_string),
+                );
+            }
+        }
+    }
+
+    functions
+}
+
+struct Builder<'a> {
+    lines: &'a [ParsedLine<'a>],
+    /// the segments finished so far, along with anything before the first one
+    roots: Vec<OutlineNode>,
+    /// the open segment, function and label, innermost last
+    open: Vec<OutlineNode>,
+}
+
+impl Builder<'_> {
+    fn node(&self, name: &str, kind: OutlineKind, line: u32, token: &Token) -> OutlineNode {
+        let text = self.lines[line as usize].text;
+
+        OutlineNode {
+            name: name.to_string(),
+            kind,
+            line,
+            end_line: line,
+            col: utf16_col(text, token.start),
+            col_end: utf16_col(text, token.end),
+            children: vec![],
+        }
+    }
+
+    /// The last non-blank line before `line`, but not before `start`.
+    fn last_content_line(&self, start: u32, line: u32) -> u32 {
+        (start..line)
+            .rev()
+            .find(|&line| !self.lines[line as usize].text.trim().is_empty())
+            .unwrap_or(start)
+    }
+
+    /// Closes open nodes until only `depth` are left, ending them just
+    /// before `line`.
+    fn close_to(&mut self, depth: usize, line: u32) {
+        while self.open.len() > depth {
+            let mut node = self.open.pop().expect("just checked");
+            node.end_line = self.last_content_line(node.line, line);
+
+            match self.open.last_mut() {
+                Some(parent) => parent.children.push(node),
+                None => self.roots.push(node),
+            }
+        }
+    }
+
+    /// Adds a constant to the innermost open segment or function.
+    fn add_constant(&mut self, node: OutlineNode) {
+        let parent = self
+            .open
+            .iter_mut()
+            .rev()
+            .find(|parent| matches!(parent.kind, OutlineKind::Segment | OutlineKind::Function));
+
+        match parent {
+            Some(parent) => parent.children.push(node),
+            None => self.roots.push(node),
+        }
+    }
+
+    fn depth_of(&self, kind: OutlineKind) -> Option<usize> {
+        self.open.iter().position(|node| node.kind == kind)
+    }
+}
+
+/// The outline of a file: its segments, the functions in its text segments
+/// (and their local labels), and its data labels and constants.
+pub fn outline(file: &ParsedFile, functions: &HashSet<String>) -> Vec<OutlineNode> {
+    let mut builder = Builder {
+        lines: &file.lines,
+        roots: vec![],
+        open: vec![],
+    };
+    let mut segment = Segment::Text;
+
+    for (line_num, line) in file.lines.iter().enumerate() {
+        let line_num = line_num as u32;
+
+        let items = line.items.as_deref().unwrap_or_default();
+        if let Some(new_segment) = items.iter().find_map(segment_directive) {
+            let directive = tokenise_line(line.text)
+                .into_iter()
+                .find(|token| token.kind == TokenKind::Directive);
+
+            if let Some(directive) = directive {
+                builder.close_to(0, line_num);
+                let node = builder.node(directive.text, OutlineKind::Segment, line_num, &directive);
+                builder.open.push(node);
+            }
+            segment = new_segment;
+        }
+
+        for (name, name_use) in line.names() {
+            if name_use == NameUse::Constant {
+                let node = builder.node(name.text, OutlineKind::Constant, line_num, &name);
+                builder.add_constant(node);
+                continue;
+            }
+            if name_use != NameUse::Label {
+                continue;
+            }
+
+            let is_function = segment == Segment::Text && functions.contains(name.text);
+
+            if is_function {
+                // a function ends at the next one
+                let depth = builder
+                    .depth_of(OutlineKind::Function)
+                    .or_else(|| builder.depth_of(OutlineKind::Label))
+                    .unwrap_or(builder.open.len());
+                builder.close_to(depth, line_num);
+
+                let node = builder.node(name.text, OutlineKind::Function, line_num, &name);
+                builder.open.push(node);
+            } else if segment == Segment::Text {
+                // local labels run until the next label
+                if let Some(depth) = builder.depth_of(OutlineKind::Label) {
+                    builder.close_to(depth, line_num);
+                }
+
+                let node = builder.node(name.text, OutlineKind::Label, line_num, &name);
+                builder.open.push(node);
+            } else {
+                if let Some(depth) = builder.depth_of(OutlineKind::DataLabel) {
+                    builder.close_to(depth, line_num);
+                }
+
+                let node = builder.node(name.text, OutlineKind::DataLabel, line_num, &name);
+                builder.open.push(node);
+            }
+        }
+    }
+
+    builder.close_to(0, file.lines.len() as u32);
+    builder.roots
+}
+
+/// Every multi-line node of an outline, as (start, end) line ranges to fold.
+pub fn folding_ranges(nodes: &[OutlineNode]) -> Vec<[u32; 2]> {
+    let mut ranges = vec![];
+
+    for node in nodes {
+        if node.end_line > node.line {
+            ranges.push([node.line, node.end_line]);
+        }
+        ranges.extend(folding_ranges(&node.children));
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::compile;
+
+    const PROGRAM: &str = "\
+N = 3
+
+\t.data
+arr:\t.word\t1, 2, 3
+msg:\t.asciiz\t\"hi\"
+
+\t.text
+main:
+\tjal\tsum
+\tjr\t$ra
+
+sum:
+\tli\t$t0, N
+sum_loop:
+\taddi\t$v0, $v0, 1
+\tbne\t$v0, $t0, sum_loop
+
+sum_end:
+\tjr\t$ra
+";
+
+    fn summary(nodes: &[OutlineNode]) -> Vec<(String, OutlineKind, u32, u32, usize)> {
+        nodes
+            .iter()
+            .map(|node| {
+                let children = node.children.len();
+                (
+                    node.name.clone(),
+                    node.kind,
+                    node.line,
+                    node.end_line,
+                    children,
+                )
+            })
+            .collect()
+    }
+
+    fn program_outline(binary: Option<&Binary>) -> Vec<OutlineNode> {
+        let file = ParsedFile::parse("test.s", PROGRAM, 8);
+        let functions = function_names(binary, std::slice::from_ref(&file));
+        outline(&file, &functions)
+    }
+
+    #[test]
+    fn nests_labels_inside_functions_inside_segments() {
+        let binary = compile(PROGRAM);
+        let nodes = program_outline(Some(&binary));
+
+        assert_eq!(
+            summary(&nodes),
+            [
+                ("N".to_string(), OutlineKind::Constant, 0, 0, 0),
+                (".data".to_string(), OutlineKind::Segment, 2, 4, 2),
+                (".text".to_string(), OutlineKind::Segment, 6, 18, 2),
+            ]
+        );
+        assert_eq!(
+            summary(&nodes[1].children),
+            [
+                ("arr".to_string(), OutlineKind::DataLabel, 3, 3, 0),
+                ("msg".to_string(), OutlineKind::DataLabel, 4, 4, 0),
+            ]
+        );
+
+        let text = &nodes[2].children;
+        assert_eq!(
+            summary(text),
+            [
+                ("main".to_string(), OutlineKind::Function, 7, 9, 0),
+                ("sum".to_string(), OutlineKind::Function, 11, 18, 2),
+            ]
+        );
+        assert_eq!(
+            summary(&text[1].children),
+            [
+                ("sum_loop".to_string(), OutlineKind::Label, 13, 15, 0),
+                ("sum_end".to_string(), OutlineKind::Label, 17, 18, 0),
+            ]
+        );
+        assert_eq!((text[1].col, text[1].col_end), (0, 3));
+    }
+
+    #[test]
+    fn finds_functions_without_a_compile() {
+        let functions = function_names(None, &[ParsedFile::parse("test.s", PROGRAM, 8)]);
+
+        assert_eq!(
+            functions,
+            ["main", "sum"].map(str::to_string).into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn finds_functions_which_are_never_called() {
+        // `helper` is never called with `jal`, but returns with `jr $ra`
+        let source = "main:\n\tjr\t$ra\nhelper:\n\tjr\t$ra\n";
+        let file = ParsedFile::parse("test.s", source, 8);
+
+        assert!(!function_names(None, std::slice::from_ref(&file)).contains("helper"));
+        assert!(function_names(Some(&compile(source)), &[file]).contains("helper"));
+    }
+
+    #[test]
+    fn folds_every_multi_line_node() {
+        let binary = compile(PROGRAM);
+
+        assert_eq!(
+            folding_ranges(&program_outline(Some(&binary))),
+            [[2, 4], [6, 18], [7, 9], [11, 18], [13, 15], [17, 18]]
+        );
+    }
+}
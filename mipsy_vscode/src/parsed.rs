@@ -0,0 +1,378 @@
+use std::rc::Rc;
+
+use mipsy_parser::{
+    parse_mips, MpArgument, MpConstValue, MpDirective, MpImmediate, MpItem, MpNumber, MpRegister,
+    TaggedFile,
+};
+
+use crate::lexer::{tab_size_of, tokenise_line, Token, TokenKind, DEFAULT_TAB_SIZE};
+
+/// What a name on a line is to mipsy.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NameUse {
+    /// where a label is defined
+    Label,
+    /// where a constant is defined
+    Constant,
+    Mnemonic,
+    /// a use of a label or constant
+    Reference,
+}
+
+impl NameUse {
+    pub fn is_definition(&self) -> bool {
+        matches!(self, NameUse::Label | NameUse::Constant)
+    }
+}
+
+/// One line of a file, as mipsy parses it.
+pub struct ParsedLine<'a> {
+    pub text: &'a str,
+    /// the labels, constant, directive and instruction on the line, or the
+    /// (1-indexed, tab-expanded) column where mipsy couldn't parse it
+    pub items: Result<Vec<MpItem>, u32>,
+}
+
+/// A file as mipsy parses it, split up by line.
+pub struct ParsedFile<'a> {
+    pub lines: Vec<ParsedLine<'a>>,
+}
+
+fn parse_line(file_tag: &str, text: &str, tab_size: u32) -> Result<Vec<MpItem>, u32> {
+    match parse_mips(vec![TaggedFile::new(Some(file_tag), text)], tab_size) {
+        Ok(program) => Ok(program
+            .items()
+            .iter()
+            .map(|(item, _, _)| item.clone())
+            .collect()),
+        Err(err) => Err(err.col),
+    }
+}
+
+fn const_references<'v>(value: &'v MpConstValue, names: &mut Vec<&'v str>) {
+    match value {
+        MpConstValue::Value(_) => {}
+        MpConstValue::Const(name) => names.push(name),
+        MpConstValue::Minus(value) | MpConstValue::Neg(value) => const_references(value, names),
+        MpConstValue::Sum(lhs, rhs)
+        | MpConstValue::Sub(lhs, rhs)
+        | MpConstValue::Mult(lhs, rhs)
+        | MpConstValue::Div(lhs, rhs)
+        | MpConstValue::Mod(lhs, rhs)
+        | MpConstValue::And(lhs, rhs)
+        | MpConstValue::Or(lhs, rhs)
+        | MpConstValue::Xor(lhs, rhs)
+        | MpConstValue::Shl(lhs, rhs)
+        | MpConstValue::Shr(lhs, rhs) => {
+            const_references(lhs, names);
+            const_references(rhs, names);
+        }
+    }
+}
+
+fn immediate_reference(immediate: &MpImmediate) -> Option<&str> {
+    match immediate {
+        MpImmediate::LabelReference(name) => Some(name),
+        MpImmediate::I16(_) | MpImmediate::U16(_) | MpImmediate::I32(_) | MpImmediate::U32(_) => {
+            None
+        }
+    }
+}
+
+/// The names an item defines, refers to (or is), in the order they're written.
+fn item_names<'i>(item: &'i MpItem, names: &mut Vec<(&'i str, NameUse)>) {
+    let mut references = vec![];
+
+    match item {
+        MpItem::Label(name) => names.push((name, NameUse::Label)),
+        MpItem::Constant(constant) => {
+            names.push((constant.label(), NameUse::Constant));
+            const_references(constant.value(), &mut references);
+        }
+        MpItem::Instruction(instruction) => {
+            names.push((instruction.name(), NameUse::Mnemonic));
+            for argument in instruction.arguments() {
+                let immediate = match argument {
+                    MpArgument::Register(MpRegister::Normal(_)) => None,
+                    MpArgument::Register(MpRegister::Offset(immediate, _)) => Some(immediate),
+                    MpArgument::Number(MpNumber::Immediate(immediate)) => Some(immediate),
+                    MpArgument::Number(
+                        MpNumber::Float32(_) | MpNumber::Float64(_) | MpNumber::Char(_),
+                    ) => None,
+                };
+                references.extend(immediate.and_then(immediate_reference));
+            }
+        }
+        MpItem::Directive(directive) => match directive {
+            MpDirective::Text
+            | MpDirective::Data
+            | MpDirective::KText
+            | MpDirective::KData
+            | MpDirective::Ascii(_)
+            | MpDirective::Asciiz(_) => {}
+            MpDirective::Byte(values) | MpDirective::Half(values) | MpDirective::Word(values) => {
+                for (value, repeat) in values {
+                    const_references(value, &mut references);
+                    if let Some(repeat) = repeat {
+                        const_references(repeat, &mut references);
+                    }
+                }
+            }
+            MpDirective::Float(values) => {
+                for repeat in values.iter().filter_map(|(_, repeat)| repeat.as_ref()) {
+                    const_references(repeat, &mut references);
+                }
+            }
+            MpDirective::Double(values) => {
+                for repeat in values.iter().filter_map(|(_, repeat)| repeat.as_ref()) {
+                    const_references(repeat, &mut references);
+                }
+            }
+            MpDirective::Align(value) | MpDirective::Space(value) => {
+                const_references(value, &mut references)
+            }
+            MpDirective::Globl(name) => references.push(name),
+        },
+    }
+
+    names.extend(
+        references
+            .into_iter()
+            .map(|name| (name, NameUse::Reference)),
+    );
+}
+
+impl<'a> ParsedLine<'a> {
+    /// Every name on the line (labels and constants where they're defined
+    /// and used, and the mnemonic) as mipsy parsed it, with the token it was
+    /// parsed from, or `None` if the line doesn't parse, or if somehow
+    /// mipsy's names can't be lined up with the line's tokens.
+    pub fn lined_up_names(&self) -> Option<Vec<(Token<'a>, NameUse)>> {
+        let items = self.items.as_ref().ok()?;
+
+        let mut names = vec![];
+        for item in items {
+            item_names(item, &mut names);
+        }
+
+        let tokens: Vec<Token<'a>> = tokenise_line(self.text)
+            .into_iter()
+            .filter(|token| token.kind == TokenKind::Identifier)
+            .collect();
+
+        let lines_up = tokens.len() == names.len()
+            && tokens
+                .iter()
+                .zip(&names)
+                .all(|(token, &(name, name_use))| match name_use {
+                    NameUse::Mnemonic => token.text.eq_ignore_ascii_case(name),
+                    _ => token.text == name,
+                });
+
+        lines_up.then(|| {
+            tokens
+                .into_iter()
+                .zip(names.into_iter().map(|(_, name_use)| name_use))
+                .collect()
+        })
+    }
+
+    /// Like `lined_up_names`, but empty if they can't be found.
+    pub fn names(&self) -> Vec<(Token<'a>, NameUse)> {
+        self.lined_up_names().unwrap_or_default()
+    }
+
+    /// The labels and constants defined on the line, and the labels and
+    /// constants its operands refer to.
+    pub fn symbol_names(&self) -> impl Iterator<Item = (Token<'a>, NameUse)> {
+        self.names()
+            .into_iter()
+            .filter(|(_, name_use)| *name_use != NameUse::Mnemonic)
+    }
+
+    pub fn mnemonic(&self) -> Option<Token<'a>> {
+        self.names()
+            .into_iter()
+            .find(|(_, name_use)| *name_use == NameUse::Mnemonic)
+            .map(|(token, _)| token)
+    }
+
+    pub fn comment(&self) -> Option<Token<'a>> {
+        tokenise_line(self.text)
+            .pop()
+            .filter(|token| token.kind == TokenKind::Comment)
+    }
+
+    /// The byte range covering the statement (excluding labels and comments),
+    /// or the whole line if there's no statement.
+    pub fn statement_span(&self) -> (usize, usize) {
+        let tokens = tokenise_line(self.text);
+        let mut statement = tokens.as_slice();
+
+        while let [name, colon, rest @ ..] = statement {
+            if name.kind != TokenKind::Identifier || colon.kind != TokenKind::Colon {
+                break;
+            }
+            statement = rest;
+        }
+        if let [rest @ .., comment] = statement {
+            if comment.kind == TokenKind::Comment {
+                statement = rest;
+            }
+        }
+
+        match (statement.first(), statement.last()) {
+            (Some(first), Some(last)) => (first.start, last.end),
+            _ => (0, self.text.len()),
+        }
+    }
+
+    /// Whether the line has nothing on it but (maybe) a comment.
+    pub fn is_blank(&self) -> bool {
+        self.items.as_ref().is_ok_and(Vec::is_empty)
+    }
+
+    /// The line's items, or if it doesn't parse, the labels and constant it
+    /// starts off defining (the constant as 0), so that leaving the rest of
+    /// the line out of the program doesn't cause knock-on errors (e.g.
+    /// unknown label) elsewhere.
+    pub fn items_or_definitions(&self, file_tag: &str) -> Vec<MpItem> {
+        if let Ok(items) = &self.items {
+            return items.clone();
+        }
+
+        let tokens = tokenise_line(self.text);
+        let mut definitions = String::new();
+        let mut rest = tokens.as_slice();
+
+        loop {
+            match rest {
+                [name, colon, after @ ..]
+                    if name.kind == TokenKind::Identifier && colon.kind == TokenKind::Colon =>
+                {
+                    definitions.push_str(name.text);
+                    definitions.push_str(": ");
+                    rest = after;
+                }
+                [name, equals, ..]
+                    if name.kind == TokenKind::Identifier && equals.kind == TokenKind::Equals =>
+                {
+                    definitions.push_str(name.text);
+                    definitions.push_str(" = 0");
+                    break;
+                }
+                _ => break,
+            }
+        }
+
+        parse_line(file_tag, &definitions, DEFAULT_TAB_SIZE).unwrap_or_default()
+    }
+}
+
+impl<'a> ParsedFile<'a> {
+    /// Parses the whole file in one go. Only if that fails is each line
+    /// parsed on its own, so that a line mipsy can't parse doesn't stop the
+    /// rest of the file from being understood (or hide the lines after it
+    /// which can't be parsed either). `tab_size` is the one mipsy is
+    /// configured with.
+    pub fn parse(file_tag: &str, source: &'a str, tab_size: u32) -> Self {
+        let mut lines: Vec<ParsedLine> = source
+            .lines()
+            .map(|text| ParsedLine {
+                text,
+                items: Ok(vec![]),
+            })
+            .collect();
+
+        match parse_mips(vec![TaggedFile::new(Some(file_tag), source)], tab_size) {
+            Ok(program) => {
+                for (item, _, line) in program.items() {
+                    let line = lines.get_mut((*line as usize).wrapping_sub(1));
+                    if let Some(Ok(items)) = line.map(|line| &mut line.items) {
+                        items.push(item.clone());
+                    }
+                }
+            }
+            Err(_) => {
+                // a line on its own doesn't have the file's `#![tabsize(N)]`
+                let tab_size = tab_size_of(source, tab_size);
+                for line in &mut lines {
+                    line.items = parse_line(file_tag, line.text, tab_size);
+                }
+            }
+        }
+
+        ParsedFile { lines }
+    }
+
+    /// The file as a program for mipsy to compile, with each item tagged with
+    /// its file and (1-indexed) line. Lines which don't parse are left out,
+    /// but for what `ParsedLine::items_or_definitions` finds on them.
+    pub fn program_items(&self, file_tag: &str) -> Vec<(MpItem, Option<Rc<str>>, u32)> {
+        let tag: Rc<str> = Rc::from(file_tag);
+
+        self.lines
+            .iter()
+            .enumerate()
+            .flat_map(|(index, line)| {
+                let tag = tag.clone();
+                line.items_or_definitions(file_tag)
+                    .into_iter()
+                    .map(move |item| (item, Some(tag.clone()), index as u32 + 1))
+            })
+            .collect()
+    }
+
+    /// The (1-indexed) line and column of every line which doesn't parse.
+    pub fn errors(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.lines.iter().enumerate().filter_map(|(index, line)| {
+            line.items
+                .as_ref()
+                .err()
+                .map(|&col| (index as u32 + 1, col))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(source: &str) -> Vec<Vec<(&str, NameUse)>> {
+        ParsedFile::parse("test.s", source, 8)
+            .lines
+            .iter()
+            .map(|line| {
+                line.names()
+                    .into_iter()
+                    .map(|(token, name_use)| (token.text, name_use))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_names_where_mipsy_does() {
+        use NameUse::*;
+
+        assert_eq!(
+            names("N = M + 1\nmain: la $t0, arr # arr\n.data\narr: .word N:M\n.globl main"),
+            [
+                vec![("N", Constant), ("M", Reference)],
+                vec![("main", Label), ("la", Mnemonic), ("arr", Reference)],
+                vec![],
+                vec![("arr", Label), ("N", Reference), ("M", Reference)],
+                vec![("main", Reference)],
+            ]
+        );
+    }
+
+    #[test]
+    fn recovers_at_the_next_line() {
+        let file = ParsedFile::parse("test.s", "a:\n\tadd $t0 $t1\nb: jr $ra", 4);
+
+        assert_eq!(file.errors().collect::<Vec<_>>(), [(2, 13)]);
+        assert!(file.lines[1].names().is_empty());
+        assert_eq!(file.lines[2].names().len(), 2);
+    }
+}
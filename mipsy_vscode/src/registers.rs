@@ -0,0 +1,59 @@
+pub const GP_REGISTER_NAMES: [&str; 32] = [
+    "zero", "at", "v0", "v1", "a0", "a1", "a2", "a3", "t0", "t1", "t2", "t3", "t4", "t5", "t6",
+    "t7", "s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "t8", "t9", "k0", "k1", "gp", "sp", "fp",
+    "ra",
+];
+
+pub const ZERO: u32 = 0;
+pub const V0: u32 = 2;
+pub const V1: u32 = 3;
+pub const A0: u32 = 4;
+pub const A3: u32 = 7;
+pub const K0: u32 = 26;
+pub const K1: u32 = 27;
+pub const SP: u32 = 29;
+pub const FP: u32 = 30;
+pub const RA: u32 = 31;
+
+pub fn is_temporary(reg: u32) -> bool {
+    (8..=15).contains(&reg) || reg == 24 || reg == 25
+}
+
+/// `$s0`-`$s7` and `$fp`, which functions must restore before returning.
+pub fn is_callee_saved(reg: u32) -> bool {
+    (16..=23).contains(&reg) || reg == FP
+}
+
+/// The index of a general purpose register, given its name with or without
+/// the leading `$` (e.g. `$t0`, `t0`, `$8`).
+pub fn register_index(name: &str) -> Option<u32> {
+    let name = name.strip_prefix('$').unwrap_or(name);
+
+    if let Ok(index) = name.parse::<u32>() {
+        return (index < 32).then_some(index);
+    }
+
+    GP_REGISTER_NAMES
+        .iter()
+        .position(|&reg| reg == name)
+        .map(|index| index as u32)
+        .or(match name {
+            "s8" => Some(30),
+            _ => None,
+        })
+}
+
+pub fn is_fpu_register(name: &str) -> bool {
+    let name = name.strip_prefix('$').unwrap_or(name);
+    name.strip_prefix('f')
+        .and_then(|index| index.parse::<u32>().ok())
+        .is_some_and(|index| index < 32)
+}
+
+pub fn is_register(name: &str) -> bool {
+    register_index(name).is_some() || is_fpu_register(name)
+}
+
+pub fn register_name(index: u32) -> String {
+    std::format!("${}", GP_REGISTER_NAMES[index as usize % 32])
+}
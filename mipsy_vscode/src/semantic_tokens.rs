@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use crate::{
+    lexer::{tokenise_line, utf16_col, TokenKind},
+    parsed::{NameUse, ParsedFile},
+    symbols::{Symbol, SymbolKind},
+};
+
+/// The token types, in the order of the legend given to the editor.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum TokenType {
+    Instruction,
+    PseudoInstruction,
+    Directive,
+    /// registers and constants, which are told apart by their modifiers
+    Variable,
+    Label,
+    String,
+    Number,
+    Comment,
+}
+
+pub const TOKEN_TYPES: [&str; 8] = [
+    "keyword",
+    "macro",
+    "decorator",
+    "variable",
+    "label",
+    "string",
+    "number",
+    "comment",
+];
+
+pub const TOKEN_MODIFIERS: [&str; 4] = ["declaration", "readonly", "defaultLibrary", "undefined"];
+
+const DECLARATION: u32 = 1 << 0;
+const READONLY: u32 = 1 << 1;
+const DEFAULT_LIBRARY: u32 = 1 << 2;
+const UNDEFINED: u32 = 1 << 3;
+
+/// Classifies every token in a file as mipsy parsed it, returning them in
+/// the relative, 5 integers per token format of the language server
+/// protocol. Names are classified by what mipsy parsed them as, and only
+/// comments are classified on lines mipsy can't parse.
+/// `is_pseudo_instruction` returns `None` for unknown mnemonics.
+pub fn encode_semantic_tokens(
+    file: &ParsedFile,
+    symbols: &[Symbol],
+    is_pseudo_instruction: impl Fn(&str) -> Option<bool>,
+) -> Vec<u32> {
+    let kinds: HashMap<&str, SymbolKind> = symbols
+        .iter()
+        .map(|symbol| (symbol.name.as_str(), symbol.kind))
+        .collect();
+
+    let mut data = vec![];
+    let (mut prev_line, mut prev_col) = (0, 0);
+
+    for (line_num, line) in file.lines.iter().enumerate() {
+        let names: HashMap<usize, NameUse> = line
+            .names()
+            .into_iter()
+            .map(|(token, name_use)| (token.start, name_use))
+            .collect();
+        let parses = line.items.is_ok();
+
+        for token in tokenise_line(line.text) {
+            let classified = match token.kind {
+                TokenKind::Comment => Some((TokenType::Comment, 0)),
+                _ if !parses => None,
+                TokenKind::String | TokenKind::Char => Some((TokenType::String, 0)),
+                TokenKind::Number => Some((TokenType::Number, 0)),
+                TokenKind::Register => Some((TokenType::Variable, DEFAULT_LIBRARY)),
+                TokenKind::Directive => Some((TokenType::Directive, 0)),
+                TokenKind::Identifier => match names.get(&token.start) {
+                    Some(NameUse::Mnemonic) => match is_pseudo_instruction(token.text) {
+                        Some(true) => Some((TokenType::PseudoInstruction, DEFAULT_LIBRARY)),
+                        Some(false) => Some((TokenType::Instruction, DEFAULT_LIBRARY)),
+                        None => None,
+                    },
+                    Some(&name_use) => {
+                        let declaration = if name_use.is_definition() {
+                            DECLARATION
+                        } else {
+                            0
+                        };
+                        match kinds.get(token.text) {
+                            Some(SymbolKind::Constant) => {
+                                Some((TokenType::Variable, READONLY | declaration))
+                            }
+                            Some(_) => Some((TokenType::Label, declaration)),
+                            None => Some((TokenType::Label, UNDEFINED)),
+                        }
+                    }
+                    None => None,
+                },
+                _ => None,
+            };
+
+            let (token_type, modifiers) = match classified {
+                Some(classified) => classified,
+                None => continue,
+            };
+
+            let line_num = line_num as u32;
+            let col = utf16_col(line.text, token.start);
+            let len = utf16_col(line.text, token.end) - col;
+
+            data.extend([
+                line_num - prev_line,
+                if line_num == prev_line {
+                    col - prev_col
+                } else {
+                    col
+                },
+                len,
+                token_type as u32,
+                modifiers,
+            ]);
+            (prev_line, prev_col) = (line_num, col);
+        }
+    }
+
+    data
+}
@@ -0,0 +1,215 @@
+use mipsy_lib::{inst::instruction::ArgumentType, InstSet};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    lexer::{utf16_col, TokenKind},
+    source::SourceLine,
+};
+
+/// One way of writing an instruction's operands, e.g. `sw $rt, imm($rs)`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OperandForm {
+    label: String,
+    /// the (UTF-16) range of each operand within `label`
+    operands: Vec<[u32; 2]>,
+    is_pseudo: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SignatureHelp {
+    mnemonic: String,
+    forms: Vec<OperandForm>,
+    /// the form which best matches what's been written so far
+    active_form: usize,
+    /// which operand the cursor is in
+    active_operand: usize,
+}
+
+fn operand_label(arg: &ArgumentType) -> String {
+    match arg {
+        ArgumentType::Rd => "$rd".into(),
+        ArgumentType::Rs => "$rs".into(),
+        ArgumentType::Rt => "$rt".into(),
+        ArgumentType::Fd => "$fd".into(),
+        ArgumentType::Fs => "$fs".into(),
+        ArgumentType::Ft => "$ft".into(),
+        ArgumentType::Shamt => "shamt".into(),
+        ArgumentType::I16 => "imm16".into(),
+        ArgumentType::U16 => "uimm16".into(),
+        ArgumentType::I32 => "imm32".into(),
+        ArgumentType::U32 => "uimm32".into(),
+        ArgumentType::J => "label".into(),
+        ArgumentType::OffRs => "imm16($rs)".into(),
+        ArgumentType::OffRt => "imm16($rt)".into(),
+        ArgumentType::Off32Rs => "imm32($rs)".into(),
+        ArgumentType::Off32Rt => "imm32($rt)".into(),
+        ArgumentType::F32 => "float32".into(),
+        ArgumentType::F64 => "float64".into(),
+    }
+}
+
+fn operand_form(mnemonic: &str, format: &[ArgumentType], is_pseudo: bool) -> OperandForm {
+    let mut label = mnemonic.to_string();
+    let mut operands = vec![];
+
+    for (i, arg) in format.iter().enumerate() {
+        label.push_str(if i == 0 { " " } else { ", " });
+
+        let start = label.encode_utf16().count() as u32;
+        label.push_str(&operand_label(arg));
+        operands.push([start, label.encode_utf16().count() as u32]);
+    }
+
+    OperandForm {
+        label,
+        operands,
+        is_pseudo,
+    }
+}
+
+/// Every way of writing the operands of an instruction, from its native and
+/// pseudo-instruction forms in the instruction set.
+pub fn operand_forms(iset: &InstSet, mnemonic: &str) -> Vec<OperandForm> {
+    let name = mnemonic.to_ascii_lowercase();
+
+    let native = iset
+        .native_set()
+        .iter()
+        .filter(|sig| sig.name().eq_ignore_ascii_case(&name))
+        .map(|sig| operand_form(&name, sig.compile_ops().format(), false));
+    let pseudo = iset
+        .pseudo_set()
+        .iter()
+        .filter(|sig| sig.name().eq_ignore_ascii_case(&name))
+        .map(|sig| operand_form(&name, sig.compile_ops().format(), true));
+
+    let mut forms: Vec<OperandForm> = vec![];
+    for form in native.chain(pseudo) {
+        // the same form can be both a real and pseudo-instruction
+        if !forms.iter().any(|existing| existing.label == form.label) {
+            forms.push(form);
+        }
+    }
+
+    forms
+}
+
+/// Lists every way of writing the operands of the instruction on `line`, if
+/// the cursor (a 0-indexed, UTF-16 column) is after its mnemonic.
+pub fn signature_help(iset: &InstSet, line: &str, col: u32) -> Option<SignatureHelp> {
+    let source_line = SourceLine::parse(line);
+    let mnemonic = source_line.mnemonic()?;
+
+    let in_comment = source_line
+        .comment
+        .is_some_and(|comment| col > utf16_col(line, comment.start));
+
+    if col <= utf16_col(line, mnemonic.end) || in_comment {
+        return None;
+    }
+
+    let name = mnemonic.text.to_ascii_lowercase();
+    let forms = operand_forms(iset, &name);
+
+    if forms.is_empty() {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut active_operand = 0;
+    for token in source_line.tokens() {
+        if token.start < mnemonic.end || utf16_col(line, token.end) > col {
+            continue;
+        }
+
+        match token.kind {
+            TokenKind::LParen => depth += 1,
+            TokenKind::RParen => depth -= 1,
+            TokenKind::Comma if depth <= 0 => active_operand += 1,
+            _ => {}
+        }
+    }
+
+    let written = source_line.operands().len().max(active_operand + 1);
+    let active_form = forms
+        .iter()
+        .position(|form| form.operands.len() == written)
+        .or_else(|| {
+            forms
+                .iter()
+                .position(|form| form.operands.len() > active_operand)
+        })
+        .unwrap_or(0);
+
+    Some(SignatureHelp {
+        mnemonic: name,
+        forms,
+        active_form,
+        active_operand,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn help(line: &str, col: usize) -> Option<SignatureHelp> {
+        signature_help(&mipsy_instructions::inst_set(), line, col as u32)
+    }
+
+    fn labels(help: &SignatureHelp) -> Vec<&str> {
+        help.forms.iter().map(|form| form.label.as_str()).collect()
+    }
+
+    #[test]
+    fn lists_every_form_of_a_store() {
+        let line = "\tsw $t0, ";
+        let help = help(line, line.len()).unwrap();
+
+        assert_eq!(help.mnemonic, "sw");
+        assert!(labels(&help).contains(&"sw $rt, imm16($rs)"));
+        assert!(labels(&help).contains(&"sw $rt, label"));
+        assert_eq!(help.active_operand, 1);
+    }
+
+    #[test]
+    fn ignores_commas_inside_parentheses() {
+        let line = "\tsw $t0, 4($sp, x";
+        assert_eq!(help(line, line.len()).unwrap().active_operand, 1);
+
+        let line = "\tsw $t0, 4($sp)";
+        assert_eq!(help(line, line.len() - 2).unwrap().active_operand, 1);
+        assert_eq!(help(line, 5).unwrap().active_operand, 0);
+    }
+
+    #[test]
+    fn marks_where_each_operand_is_in_a_form() {
+        let line = "\tsw $t0, 4($sp)";
+        let help = help(line, line.len()).unwrap();
+        let form = help
+            .forms
+            .iter()
+            .find(|form| form.label == "sw $rt, imm16($rs)")
+            .unwrap();
+
+        assert_eq!(form.operands, [[3, 6], [8, 18]]);
+        assert!(!form.is_pseudo);
+    }
+
+    #[test]
+    fn picks_the_form_with_as_many_operands() {
+        let line = "\tli $t0, 1";
+        let help = help(line, line.len()).unwrap();
+
+        assert_eq!(help.forms[help.active_form].operands.len(), 2);
+        assert!(help.forms[help.active_form].is_pseudo);
+    }
+
+    #[test]
+    fn only_helps_after_a_known_mnemonic() {
+        assert!(help("\tsw $t0, 4($sp)", 2).is_none());
+        assert!(help("\tsw $t0, 4($sp) # x, y", 21).is_none());
+        assert!(help("\tnope $t0, ", 10).is_none());
+        assert!(help("label:", 6).is_none());
+    }
+}
@@ -0,0 +1,259 @@
+use crate::lexer::{tokenise_line, Token, TokenKind};
+
+/// One comma-separated operand of an instruction or directive.
+#[derive(Clone, Debug)]
+pub struct Operand<'a> {
+    pub tokens: Vec<Token<'a>>,
+    /// the byte offset into the line where the operand ends; for an empty
+    /// operand, where it should have been
+    pub end: usize,
+}
+
+#[derive(Clone, Debug)]
+pub enum Statement<'a> {
+    Empty,
+    Constant {
+        name: Token<'a>,
+        value: Vec<Token<'a>>,
+    },
+    Directive {
+        name: Token<'a>,
+        operands: Vec<Operand<'a>>,
+    },
+    Instruction {
+        mnemonic: Token<'a>,
+        operands: Vec<Operand<'a>>,
+    },
+    /// the line doesn't start with anything that could begin a statement
+    Invalid {
+        tokens: Vec<Token<'a>>,
+    },
+}
+
+/// A lightweight, error-tolerant view of a single line of MIPS source. Unlike
+/// `mipsy_parser`, this keeps the column of every token, and always succeeds.
+#[derive(Clone, Debug)]
+pub struct SourceLine<'a> {
+    pub text: &'a str,
+    pub statement: Statement<'a>,
+    pub comment: Option<Token<'a>>,
+}
+
+fn split_operands<'a>(tokens: &[Token<'a>]) -> Vec<Operand<'a>> {
+    if tokens.is_empty() {
+        return vec![];
+    }
+
+    let mut operands = vec![];
+    let mut current: Vec<Token<'a>> = vec![];
+    let mut operand_start = tokens[0].start;
+    let mut depth = 0i32;
+
+    for token in tokens {
+        match token.kind {
+            TokenKind::LParen => depth += 1,
+            TokenKind::RParen => depth -= 1,
+            TokenKind::Comma if depth <= 0 => {
+                operands.push(Operand {
+                    end: current.last().map_or(operand_start, |t| t.end),
+                    tokens: std::mem::take(&mut current),
+                });
+                operand_start = token.end;
+                continue;
+            }
+            _ => {}
+        }
+        current.push(*token);
+    }
+
+    operands.push(Operand {
+        end: current.last().map_or(operand_start, |t| t.end),
+        tokens: current,
+    });
+
+    operands
+}
+
+impl<'a> SourceLine<'a> {
+    pub fn parse(text: &'a str) -> Self {
+        let mut tokens = tokenise_line(text);
+
+        let comment = match tokens.last() {
+            Some(token) if token.kind == TokenKind::Comment => tokens.pop(),
+            _ => None,
+        };
+
+        // skip over any labels (`name:`) before the statement
+        let mut rest = tokens.as_slice();
+        while let [label, colon, remaining @ ..] = rest {
+            if label.kind != TokenKind::Identifier || colon.kind != TokenKind::Colon {
+                break;
+            }
+            rest = remaining;
+        }
+
+        let statement = match rest {
+            [] => Statement::Empty,
+            [name, equals, value @ ..]
+                if name.kind == TokenKind::Identifier && equals.kind == TokenKind::Equals =>
+            {
+                Statement::Constant {
+                    name: *name,
+                    value: value.to_vec(),
+                }
+            }
+            [name, operands @ ..] if name.kind == TokenKind::Directive => Statement::Directive {
+                name: *name,
+                operands: split_operands(operands),
+            },
+            [mnemonic, operands @ ..] if mnemonic.kind == TokenKind::Identifier => {
+                Statement::Instruction {
+                    mnemonic: *mnemonic,
+                    operands: split_operands(operands),
+                }
+            }
+            tokens => Statement::Invalid {
+                tokens: tokens.to_vec(),
+            },
+        };
+
+        SourceLine {
+            text,
+            statement,
+            comment,
+        }
+    }
+
+    pub fn mnemonic(&self) -> Option<&Token<'a>> {
+        match &self.statement {
+            Statement::Instruction { mnemonic, .. } => Some(mnemonic),
+            _ => None,
+        }
+    }
+
+    pub fn operands(&self) -> &[Operand<'a>] {
+        match &self.statement {
+            Statement::Instruction { operands, .. } | Statement::Directive { operands, .. } => {
+                operands
+            }
+            _ => &[],
+        }
+    }
+
+    /// The byte range covering the statement (excluding labels and comments),
+    /// or the whole line if there's no statement.
+    pub fn statement_span(&self) -> (usize, usize) {
+        let tokens: &[Token] = match &self.statement {
+            Statement::Empty => &[],
+            Statement::Constant { name, value, .. } => {
+                return (name.start, value.last().map_or(name.end, |token| token.end))
+            }
+            Statement::Directive {
+                name: first,
+                operands,
+            }
+            | Statement::Instruction {
+                mnemonic: first,
+                operands,
+            } => {
+                let end = operands
+                    .last()
+                    .map_or(first.end, |operand| operand.end.max(first.end));
+                return (first.start, end);
+            }
+            Statement::Invalid { tokens } => tokens,
+        };
+
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => (first.start, last.end),
+            _ => (0, self.text.len()),
+        }
+    }
+
+    /// Every token on the line, in order, including labels and the comment.
+    pub fn tokens(&self) -> Vec<Token<'a>> {
+        tokenise_line(self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(tokens: &[Token]) -> Vec<String> {
+        tokens.iter().map(|token| token.text.to_string()).collect()
+    }
+
+    fn operand_texts(line: &SourceLine) -> Vec<Vec<String>> {
+        line.operands()
+            .iter()
+            .map(|operand| texts(&operand.tokens))
+            .collect()
+    }
+
+    #[test]
+    fn parses_labels_and_instructions() {
+        let line = SourceLine::parse("a: b:  sw $ra, 4($sp)  # save");
+
+        assert_eq!(line.mnemonic().map(|token| token.text), Some("sw"));
+        assert_eq!(
+            operand_texts(&line),
+            [vec!["$ra"], vec!["4", "(", "$sp", ")"]]
+        );
+        assert_eq!(line.comment.map(|token| token.text), Some("# save"));
+        assert_eq!(line.statement_span(), (7, 21));
+    }
+
+    #[test]
+    fn parses_constants() {
+        let line = SourceLine::parse("SIZE = 4 * (N + 1)");
+
+        match &line.statement {
+            Statement::Constant { name, value } => {
+                assert_eq!(name.text, "SIZE");
+                assert_eq!(texts(value), ["4", "*", "(", "N", "+", "1", ")"]);
+            }
+            other => panic!("expected a constant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_directives() {
+        let line = SourceLine::parse("arr: .word 1, 2:3");
+
+        match &line.statement {
+            Statement::Directive { name, .. } => assert_eq!(name.text, ".word"),
+            other => panic!("expected a directive, got {:?}", other),
+        }
+        assert_eq!(operand_texts(&line), [vec!["1"], vec!["2", ":", "3"]]);
+    }
+
+    #[test]
+    fn keeps_empty_operands() {
+        let line = SourceLine::parse("add $t0, , $t1,");
+        let operands = line.operands();
+
+        assert_eq!(operands.len(), 4);
+        assert!(operands[1].tokens.is_empty());
+        assert_eq!(operands[1].end, 8);
+        assert!(operands[3].tokens.is_empty());
+        assert_eq!(operands[3].end, line.text.len());
+    }
+
+    #[test]
+    fn only_splits_operands_outside_parentheses() {
+        let line = SourceLine::parse("li $t0, (1, 2)");
+        assert_eq!(operand_texts(&line).len(), 2);
+    }
+
+    #[test]
+    fn parses_empty_and_invalid_lines() {
+        let line = SourceLine::parse("   # just a comment");
+        assert!(matches!(line.statement, Statement::Empty));
+        assert_eq!(line.statement_span(), (0, line.text.len()));
+
+        let line = SourceLine::parse("$t0 add");
+        assert!(matches!(line.statement, Statement::Invalid { .. }));
+        assert_eq!(line.statement_span(), (0, 7));
+    }
+}
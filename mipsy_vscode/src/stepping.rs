@@ -0,0 +1,302 @@
+use std::rc::Rc;
+
+use crate::decode::Flow;
+
+/// Where an instruction came from: (file tag, line).
+pub type Position = Option<(Rc<str>, u32)>;
+
+fn is_call(flow: Flow) -> bool {
+    matches!(flow, Flow::Call(_) | Flow::CallIndirect)
+}
+
+/// A step over or step out which is under way. Running one can be
+/// interrupted by a syscall, after which it carries on from where it was.
+pub enum Stepping {
+    /// Runs until a different line, treating each call as one step.
+    Over {
+        from: Position,
+        /// the (address, `$sp`) a call being stepped over returns to
+        call: Option<(u32, Option<i32>)>,
+        /// how many calls deep the program is inside that call, which can
+        /// come back through the same address with the same `$sp` before it
+        /// returns (e.g. when it calls itself without using the stack)
+        depth: u32,
+        /// the address after a call which has just run, to tell whether it
+        /// didn't branch (a `bltzal` or `bgezal` whose condition was false)
+        after_call: Option<u32>,
+    },
+    /// Runs until the current function returns (`jr $ra`).
+    Out {
+        /// how many calls deep the program has gone since
+        depth: u32,
+        returned: bool,
+        /// like `Over`'s
+        after_call: Option<u32>,
+    },
+}
+
+impl Stepping {
+    pub fn over(from: Position) -> Self {
+        Stepping::Over {
+            from,
+            call: None,
+            depth: 0,
+            after_call: None,
+        }
+    }
+
+    pub fn out() -> Self {
+        Stepping::Out {
+            depth: 0,
+            returned: false,
+            after_call: None,
+        }
+    }
+
+    /// Whether the program has got where it's going, now that it's at `pc`.
+    pub fn is_done(&mut self, pc: u32, sp: Option<i32>, position: &Position) -> bool {
+        match self {
+            Stepping::Over {
+                from,
+                call,
+                depth,
+                after_call,
+            } => {
+                if after_call.take() == Some(pc) {
+                    *depth = depth.saturating_sub(1);
+                }
+
+                if let Some((address, call_sp)) = *call {
+                    if *depth > 0 || pc != address || sp != call_sp {
+                        return false;
+                    }
+                    *call = None;
+                }
+
+                position.is_some() && position != from
+            }
+            Stepping::Out {
+                depth,
+                returned,
+                after_call,
+            } => {
+                if after_call.take() == Some(pc) {
+                    *depth = depth.saturating_sub(1);
+                }
+
+                *returned
+            }
+        }
+    }
+
+    /// Keeps track of calls and returns, before running the instruction at
+    /// `pc`.
+    pub fn before_step(&mut self, pc: u32, flow: Flow, sp: Option<i32>) {
+        match self {
+            Stepping::Over {
+                call,
+                depth,
+                after_call,
+                ..
+            } => match flow {
+                _ if is_call(flow) => {
+                    call.get_or_insert((pc.wrapping_add(4), sp));
+                    *depth += 1;
+                    *after_call = Some(pc.wrapping_add(4));
+                }
+                Flow::Return if call.is_some() => *depth = depth.saturating_sub(1),
+                _ => {}
+            },
+            Stepping::Out {
+                depth,
+                returned,
+                after_call,
+            } => match flow {
+                _ if is_call(flow) => {
+                    *depth += 1;
+                    *after_call = Some(pc.wrapping_add(4));
+                }
+                Flow::Return if *depth == 0 => *returned = true,
+                Flow::Return => *depth -= 1,
+                _ => {}
+            },
+        }
+    }
+
+    /// The same as `is_done` and `before_step` together, but going
+    /// backwards: the instruction at `pc` has just been undone, which had
+    /// taken the program to `undone_to` (with `$sp` at `undone_sp`).
+    pub fn is_done_going_back(
+        &mut self,
+        pc: u32,
+        flow: Flow,
+        sp: Option<i32>,
+        position: &Position,
+        undone_to: u32,
+        undone_sp: Option<i32>,
+    ) -> bool {
+        // a call which didn't branch didn't call anything
+        let flow = match flow {
+            _ if is_call(flow) && undone_to == pc.wrapping_add(4) => Flow::Next,
+            flow => flow,
+        };
+
+        match self {
+            Stepping::Over {
+                from, call, depth, ..
+            } => {
+                match *call {
+                    // going back into the end of a call, so keep going until its start
+                    None if flow == Flow::Return => {
+                        *call = Some((undone_to, undone_sp));
+                        *depth = 1;
+                        return false;
+                    }
+                    None => {}
+                    Some((address, call_sp)) => {
+                        match flow {
+                            Flow::Return => *depth += 1,
+                            _ if is_call(flow) => *depth = depth.saturating_sub(1),
+                            _ => {}
+                        }
+
+                        if !is_call(flow)
+                            || *depth > 0
+                            || pc.wrapping_add(4) != address
+                            || sp != call_sp
+                        {
+                            return false;
+                        }
+                        *call = None;
+                    }
+                }
+
+                position.is_some() && position != from
+            }
+            Stepping::Out { depth, .. } => match flow {
+                Flow::Return => {
+                    *depth += 1;
+                    false
+                }
+                _ if is_call(flow) && *depth == 0 => true,
+                _ if is_call(flow) => {
+                    *depth -= 1;
+                    false
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A trace of (pc, flow, `$sp`, line) of the instructions a program ran.
+    type Trace = [(u32, Flow, i32, u32)];
+
+    const F: u32 = 0x00400100;
+    const G: u32 = 0x00400200;
+    const H: u32 = 0x00400300;
+    const SP: i32 = 0x7FFFFF00;
+
+    fn at(line: u32) -> Position {
+        Some((Rc::from("test.s"), line))
+    }
+
+    /// Runs forwards from the start of `trace`, returning where it stops.
+    fn run(mut stepping: Stepping, trace: &Trace) -> Option<usize> {
+        for (index, &(pc, flow, sp, line)) in trace.iter().enumerate() {
+            if stepping.is_done(pc, Some(sp), &at(line)) {
+                return Some(index);
+            }
+            stepping.before_step(pc, flow, Some(sp));
+        }
+
+        None
+    }
+
+    /// Runs backwards from the end of `trace`, returning where it stops.
+    fn run_back(mut stepping: Stepping, trace: &Trace) -> Option<usize> {
+        for index in (0..trace.len() - 1).rev() {
+            let (pc, flow, sp, line) = trace[index];
+            let (undone_to, _, undone_sp, _) = trace[index + 1];
+
+            if stepping.is_done_going_back(
+                pc,
+                flow,
+                Some(sp),
+                &at(line),
+                undone_to,
+                Some(undone_sp),
+            ) {
+                return Some(index);
+            }
+        }
+
+        None
+    }
+
+    /// `f` calling itself without using the stack, so every level of it
+    /// comes back to the same address with the same `$sp`.
+    const RECURSION: &Trace = &[
+        (F, Flow::Branch(F + 8), SP, 10),
+        (F + 4, Flow::Call(F), SP, 11),
+        (F, Flow::Branch(F + 8), SP, 10),
+        (F + 4, Flow::Call(F), SP, 11),
+        (F, Flow::Branch(F + 8), SP, 10),
+        (F + 8, Flow::Return, SP, 12),
+        (F + 8, Flow::Return, SP, 12),
+        (F + 8, Flow::Return, SP, 12),
+    ];
+
+    /// `f` calling `g`, which calls `h`, each with their own stack frame.
+    const NESTED: &Trace = &[
+        (F, Flow::Next, SP, 10),
+        (F + 4, Flow::Call(G), SP, 11),
+        (G, Flow::Call(H), SP - 8, 20),
+        (H, Flow::Return, SP - 8, 30),
+        (G + 4, Flow::Return, SP - 8, 21),
+        (F + 8, Flow::Return, SP, 12),
+        (0x00400014, Flow::Next, SP, 6),
+    ];
+
+    #[test]
+    fn steps_over_a_call() {
+        assert_eq!(run(Stepping::over(at(11)), &NESTED[1..]), Some(4));
+        // not a call, so just the next line
+        assert_eq!(run(Stepping::over(at(10)), NESTED), Some(1));
+    }
+
+    #[test]
+    fn steps_over_recursive_calls_at_the_same_sp() {
+        // the inner calls come back through `f + 8` with the same `$sp` first
+        assert_eq!(run(Stepping::over(at(11)), &RECURSION[1..]), Some(6));
+        assert_eq!(run_back(Stepping::over(at(12)), RECURSION), Some(1));
+    }
+
+    #[test]
+    fn steps_out_through_nested_calls() {
+        assert_eq!(run(Stepping::out(), NESTED), Some(6));
+        // from inside `g`, after `h` has returned, back to `f`
+        assert_eq!(run(Stepping::out(), &NESTED[4..]), Some(1));
+
+        assert_eq!(run_back(Stepping::out(), &NESTED[..5]), Some(1));
+        assert_eq!(run_back(Stepping::out(), &NESTED[..4]), Some(2));
+    }
+
+    #[test]
+    fn treats_a_branch_and_link_which_isnt_taken_as_one_step() {
+        let trace: &Trace = &[
+            (F + 4, Flow::Call(G), SP, 11),
+            (G, Flow::Call(H), SP, 20),
+            (G + 4, Flow::Return, SP, 21),
+            (F + 8, Flow::Next, SP, 12),
+        ];
+
+        assert_eq!(run(Stepping::over(at(20)), &trace[1..]), Some(1));
+        assert_eq!(run(Stepping::out(), &trace[1..]), Some(2));
+        assert_eq!(run_back(Stepping::out(), &trace[..3]), Some(0));
+    }
+}
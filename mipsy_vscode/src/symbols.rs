@@ -0,0 +1,436 @@
+use mipsy_lib::{
+    util::{get_segment, Segment},
+    Binary,
+};
+use serde::{Deserialize, Serialize};
+
+use mipsy_parser::{MpDirective, MpItem};
+
+use crate::{
+    expr::parsed_constant_values,
+    lexer::{is_identifier_char, is_identifier_start, utf16_col, Token},
+    parsed::{NameUse, ParsedFile},
+    registers::is_register,
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SymbolKind {
+    TextLabel,
+    DataLabel,
+    Constant,
+}
+
+/// A label or constant defined in the program.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub file_tag: String,
+    /// 0-indexed, as are the columns, which are in UTF-16 code units like
+    /// the language server protocol
+    pub line: u32,
+    pub col: u32,
+    pub col_end: u32,
+    /// where a label ends up, if the program compiles
+    pub address: Option<u32>,
+    /// the value of a constant, if it can be worked out
+    pub value: Option<i64>,
+    /// the block of comments just before the definition, or else the comment
+    /// at the end of its line
+    pub docs: String,
+}
+
+/// Which segment `.text`, `.data`, etc switch to, as far as symbol kinds are concerned.
+fn segment_directive(item: &MpItem) -> Option<SymbolKind> {
+    match item {
+        MpItem::Directive(MpDirective::Text | MpDirective::KText) => Some(SymbolKind::TextLabel),
+        MpItem::Directive(MpDirective::Data | MpDirective::KData) => Some(SymbolKind::DataLabel),
+        _ => None,
+    }
+}
+
+fn comment_text<'a>(comment: &Token<'a>) -> &'a str {
+    let text = comment.text.strip_prefix('#').unwrap_or(comment.text);
+    text.strip_prefix(' ').unwrap_or(text).trim_end()
+}
+
+/// Every label and constant defined in the given files, as mipsy parses
+/// them. `binary` is the compiled program, if it compiles, which is used to
+/// resolve addresses.
+pub fn symbol_table(
+    sources: &[(&str, &str)],
+    binary: Option<&Binary>,
+    tab_size: u32,
+) -> Vec<Symbol> {
+    let files: Vec<(&str, ParsedFile)> = sources
+        .iter()
+        .map(|&(file_tag, source)| (file_tag, ParsedFile::parse(file_tag, source, tab_size)))
+        .collect();
+
+    let constants = parsed_constant_values(files.iter().map(|(_, file)| file));
+
+    let mut symbols = vec![];
+
+    for (file_tag, file) in &files {
+        let mut segment = SymbolKind::TextLabel;
+        let mut docs_lines: Vec<&str> = vec![];
+
+        for (line_num, line) in file.lines.iter().enumerate() {
+            let comment = line.comment();
+            let block_docs = docs_lines.join("\n").trim().to_string();
+            let docs = if block_docs.is_empty() {
+                comment.as_ref().map_or("", comment_text).to_string()
+            } else {
+                block_docs
+            };
+
+            let items = line.items.as_deref().unwrap_or_default();
+            for kind in items.iter().filter_map(segment_directive) {
+                segment = kind;
+            }
+
+            for (token, name_use) in line.names() {
+                let (kind, address, value) = match name_use {
+                    NameUse::Label => {
+                        let address =
+                            binary.and_then(|binary| binary.labels.get(token.text).copied());
+                        let kind = match address.map(get_segment) {
+                            Some(Segment::Text | Segment::KText) => SymbolKind::TextLabel,
+                            Some(_) => SymbolKind::DataLabel,
+                            None => segment,
+                        };
+                        (kind, address, None)
+                    }
+                    NameUse::Constant => (
+                        SymbolKind::Constant,
+                        None,
+                        constants.get(token.text).copied(),
+                    ),
+                    NameUse::Mnemonic | NameUse::Reference => continue,
+                };
+
+                symbols.push(Symbol {
+                    name: token.text.to_string(),
+                    kind,
+                    file_tag: file_tag.to_string(),
+                    line: line_num as u32,
+                    col: utf16_col(line.text, token.start),
+                    col_end: utf16_col(line.text, token.end),
+                    address,
+                    value,
+                    docs: docs.clone(),
+                });
+            }
+
+            // a blank line ends the block of comments, as does anything else
+            match comment {
+                Some(comment) if line.is_blank() => docs_lines.push(comment_text(&comment)),
+                _ => docs_lines.clear(),
+            }
+        }
+    }
+
+    symbols
+}
+
+/// A use, or the definition, of a label or constant.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Reference {
+    pub file_tag: String,
+    /// 0-indexed, with columns in UTF-16 code units, like `Symbol`
+    pub line: u32,
+    pub col: u32,
+    pub col_end: u32,
+    pub is_definition: bool,
+}
+
+/// The name of the label or constant at a (0-indexed, UTF-16) position in a
+/// file, as mipsy parses it.
+pub fn name_at<'a>(
+    file_tag: &str,
+    source: &'a str,
+    line: u32,
+    col: u32,
+    tab_size: u32,
+) -> Option<&'a str> {
+    let file = ParsedFile::parse(file_tag, source, tab_size);
+    let line = file.lines.get(line as usize)?;
+
+    line.symbol_names()
+        .map(|(token, _)| token)
+        .find(|token| {
+            utf16_col(line.text, token.start) <= col && col <= utf16_col(line.text, token.end)
+        })
+        .map(|token| token.text)
+}
+
+/// Every use of the label or constant called `name`, including its definition,
+/// in all of the given files, as mipsy parses them.
+pub fn references_to(sources: &[(&str, &str)], name: &str, tab_size: u32) -> Vec<Reference> {
+    let mut references = vec![];
+
+    for &(file_tag, source) in sources {
+        let file = ParsedFile::parse(file_tag, source, tab_size);
+
+        for (line_num, line) in file.lines.iter().enumerate() {
+            for (token, name_use) in line.symbol_names() {
+                if token.text == name {
+                    references.push(Reference {
+                        file_tag: file_tag.to_string(),
+                        line: line_num as u32,
+                        col: utf16_col(line.text, token.start),
+                        col_end: utf16_col(line.text, token.end),
+                        is_definition: name_use.is_definition(),
+                    });
+                }
+            }
+        }
+    }
+
+    references
+}
+
+/// Checks that a label or constant in the given files can be renamed to
+/// `new_name`, explaining why not if it can't. `symbols` is their symbol table.
+pub fn check_rename(
+    sources: &[(&str, &str)],
+    symbols: &[Symbol],
+    new_name: &str,
+    tab_size: u32,
+    is_instruction: impl Fn(&str) -> bool,
+) -> Result<(), String> {
+    let is_valid =
+        new_name.starts_with(is_identifier_start) && new_name.chars().all(is_identifier_char);
+
+    if !is_valid {
+        return Err(std::format!(
+            "`{}` isn't a valid name: names must start with a letter or underscore, and only contain letters, digits, underscores and dots",
+            new_name
+        ));
+    }
+
+    if let Some(existing) = symbols.iter().find(|symbol| symbol.name == new_name) {
+        return Err(std::format!(
+            "there's already a {} called `{}` ({}, line {})",
+            match existing.kind {
+                SymbolKind::TextLabel | SymbolKind::DataLabel => "label",
+                SymbolKind::Constant => "constant",
+            },
+            new_name,
+            existing.file_tag,
+            existing.line + 1
+        ));
+    }
+
+    if is_instruction(new_name) {
+        return Err(std::format!(
+            "`{}` is the name of an instruction, so it would be confusing as a label or constant",
+            new_name
+        ));
+    }
+
+    if is_register(new_name) {
+        return Err(std::format!(
+            "`{}` is the name of a register, so it would be confusing as a label or constant",
+            new_name
+        ));
+    }
+
+    // every use has to be found, or the rename would leave some behind
+    for &(file_tag, source) in sources {
+        let file = ParsedFile::parse(file_tag, source, tab_size);
+
+        for (line_num, line) in file.lines.iter().enumerate() {
+            if line.lined_up_names().is_some() {
+                continue;
+            }
+
+            return Err(if line.items.is_err() {
+                std::format!(
+                    "line {} of {} doesn't parse, so the names on it can't be found: fix it before renaming",
+                    line_num + 1,
+                    file_tag
+                )
+            } else {
+                std::format!(
+                    "the names on line {} of {} can't be told apart from the rest of the line, so renaming could miss some of them",
+                    line_num + 1,
+                    file_tag
+                )
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::compile;
+
+    fn symbols(source: &str) -> Vec<Symbol> {
+        symbol_table(&[("test.s", source)], None, 8)
+    }
+
+    #[test]
+    fn finds_chained_labels() {
+        let found = symbols("a: b:\tli $t0, 1\n");
+
+        let names: Vec<_> = found
+            .iter()
+            .map(|symbol| {
+                (
+                    symbol.name.as_str(),
+                    symbol.line,
+                    symbol.col,
+                    symbol.col_end,
+                )
+            })
+            .collect();
+        assert_eq!(names, [("a", 0, 0, 1), ("b", 0, 3, 4)]);
+    }
+
+    #[test]
+    fn ignores_names_in_strings_and_comments() {
+        let source =
+            "main:\tla $a0, msg # j fake\n\t.data\nmsg:\t.asciiz \"fake: x\" # not: a label\n";
+
+        let names: Vec<_> = symbols(source)
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.kind))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("main".to_string(), SymbolKind::TextLabel),
+                ("msg".to_string(), SymbolKind::DataLabel)
+            ]
+        );
+        assert!(references_to(&[("test.s", source)], "fake", 8).is_empty());
+    }
+
+    #[test]
+    fn finds_addresses_and_values() {
+        let source = "M = 3\nN = 2 * M\nmain:\tjr $ra\n\t.data\nw:\t.word N\n";
+        let binary = compile(source);
+
+        let found = symbol_table(&[("test.s", source)], Some(&binary), 8);
+        let value_of = |name: &str| {
+            let symbol = found.iter().find(|symbol| symbol.name == name).unwrap();
+            (symbol.address, symbol.value)
+        };
+        assert_eq!(value_of("N"), (None, Some(6)));
+        assert_eq!(value_of("M"), (None, Some(3)));
+        assert_eq!(value_of("main"), (Some(binary.labels["main"]), None));
+        assert_eq!(value_of("w"), (Some(binary.labels["w"]), None));
+    }
+
+    #[test]
+    fn takes_docs_from_the_comments_just_before() {
+        let source = "\
+# Adds one.
+#   Really.
+inc:\taddi $v0, $a0, 1
+\tjr $ra
+# stale
+
+dec:\taddi $v0, $a0, -1 # subtracts one
+\tjr $ra
+";
+
+        let docs: Vec<_> = symbols(source)
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.docs))
+            .collect();
+        assert_eq!(
+            docs,
+            [
+                ("inc".to_string(), "Adds one.\n  Really.".to_string()),
+                ("dec".to_string(), "subtracts one".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_references_in_every_file() {
+        let main = "main:\tjal f\n\tj main\n";
+        let other = "f:\tbeq $a0, $zero, f\n\tjr $ra\n";
+
+        let references: Vec<_> = references_to(&[("main.s", main), ("f.s", other)], "f", 8)
+            .into_iter()
+            .map(|reference| {
+                (
+                    reference.file_tag,
+                    reference.line,
+                    reference.col,
+                    reference.is_definition,
+                )
+            })
+            .collect();
+        assert_eq!(
+            references,
+            [
+                ("main.s".to_string(), 0, 10, false),
+                ("f.s".to_string(), 0, 0, true),
+                ("f.s".to_string(), 0, 19, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn explains_why_a_rename_isnt_allowed() {
+        let source = "main:\tjr $ra\nN = 1\n";
+        let found = symbols(source);
+        let check =
+            |name| check_rename(&[("test.s", source)], &found, name, 8, |name| name == "add");
+
+        assert_eq!(check("loop"), Ok(()));
+        assert!(check("1st").unwrap_err().contains("isn't a valid name"));
+        assert!(check("N")
+            .unwrap_err()
+            .contains("already a constant called `N`"));
+        assert!(check("add").unwrap_err().contains("name of an instruction"));
+        assert!(check("t0").unwrap_err().contains("name of a register"));
+    }
+
+    #[test]
+    fn renames_across_files() {
+        let sources = [
+            ("main.s", "main:\tjal f\n\tjr $ra\n"),
+            ("f.s", "f:\tbeqz $a0, f # f\n\tjr $ra\n"),
+        ];
+        let found = symbol_table(&sources, None, 8);
+
+        assert_eq!(check_rename(&sources, &found, "g", 8, |_| false), Ok(()));
+        let edits: Vec<_> = references_to(&sources, "f", 8)
+            .into_iter()
+            .map(|reference| (reference.file_tag, reference.line, reference.col))
+            .collect();
+        assert_eq!(
+            edits,
+            [
+                ("main.s".to_string(), 0, 10),
+                ("f.s".to_string(), 0, 0),
+                ("f.s".to_string(), 0, 13),
+            ]
+        );
+    }
+
+    #[test]
+    fn refuses_to_rename_when_a_line_doesnt_parse() {
+        let sources = [
+            ("main.s", "main:\tjal f\n\tjr $ra\n"),
+            ("f.s", "f:\tjr $ra\n\tadd $t0 f\n"),
+        ];
+        let found = symbol_table(&sources, None, 8);
+
+        let error = check_rename(&sources, &found, "g", 8, |_| false).unwrap_err();
+        assert!(
+            error.starts_with("line 2 of f.s doesn't parse"),
+            "{}",
+            error
+        );
+    }
+}
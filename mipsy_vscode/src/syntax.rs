@@ -0,0 +1,855 @@
+use mipsy_lib::{inst::instruction::ArgumentType, InstSet};
+
+use crate::{
+    lexer::{parse_number, tokenise_line, Token, TokenKind},
+    registers::{is_register, register_index, GP_REGISTER_NAMES},
+};
+
+/// What a directive's operands look like.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DirectiveOperands {
+    None,
+    /// a string, e.g. `.asciiz "hi"`
+    String,
+    /// values, each of which can be repeated, e.g. `.word 1, 2:10`
+    Values,
+    /// floating point numbers, each of which can be repeated
+    Floats,
+    /// a single value, e.g. `.space 8`
+    Value,
+    /// a single label, e.g. `.globl main`
+    Label,
+}
+
+pub struct Directive {
+    pub name: &'static str,
+    pub operands: DirectiveOperands,
+}
+
+const fn directive(name: &'static str, operands: DirectiveOperands) -> Directive {
+    Directive { name, operands }
+}
+
+/// The directives mipsy parses, which are the variants of its `MpDirective`.
+pub const DIRECTIVES: &[Directive] = &[
+    directive(".text", DirectiveOperands::None),
+    directive(".data", DirectiveOperands::None),
+    directive(".ktext", DirectiveOperands::None),
+    directive(".kdata", DirectiveOperands::None),
+    directive(".ascii", DirectiveOperands::String),
+    directive(".asciiz", DirectiveOperands::String),
+    directive(".byte", DirectiveOperands::Values),
+    directive(".half", DirectiveOperands::Values),
+    directive(".word", DirectiveOperands::Values),
+    directive(".float", DirectiveOperands::Floats),
+    directive(".double", DirectiveOperands::Floats),
+    directive(".space", DirectiveOperands::Value),
+    directive(".align", DirectiveOperands::Value),
+    directive(".globl", DirectiveOperands::Label),
+];
+
+/// A syntax error found on a single line, with a byte range of the line
+/// to underline.
+#[derive(Debug)]
+pub struct SyntaxProblem {
+    pub message: String,
+    pub tips: Vec<String>,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl SyntaxProblem {
+    fn at_token(token: &Token, message: String) -> Self {
+        SyntaxProblem {
+            message,
+            tips: vec![],
+            start: token.start,
+            end: token.end,
+        }
+    }
+
+    fn with_tip(mut self, tip: impl Into<String>) -> Self {
+        self.tips.push(tip.into());
+        self
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// The candidate closest to `name`, if any is close enough to plausibly be a typo.
+pub fn closest_match<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// How a token should be described in "expected X, found Y" messages.
+pub fn describe_token(token: Option<&Token>) -> String {
+    match token {
+        None => "the end of the line".into(),
+        Some(token) => match token.kind {
+            TokenKind::Comment => "a comment".into(),
+            TokenKind::UnterminatedString | TokenKind::String => "a string".into(),
+            _ => std::format!("`{}`", token.text),
+        },
+    }
+}
+
+/// The operands of every form of an instruction (native or pseudo) in the
+/// instruction set, or nothing if it isn't an instruction.
+pub fn operand_formats(iset: &InstSet, mnemonic: &str) -> Vec<Vec<ArgumentType>> {
+    iset.native_set()
+        .iter()
+        .filter(|sig| sig.name().eq_ignore_ascii_case(mnemonic))
+        .map(|sig| sig.compile_ops().format().clone())
+        .chain(
+            iset.pseudo_set()
+                .iter()
+                .filter(|sig| sig.name().eq_ignore_ascii_case(mnemonic))
+                .map(|sig| sig.compile_ops().format().clone()),
+        )
+        .collect()
+}
+
+/// What an operand of an instruction can be, as told by its signatures in
+/// the instruction set.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum OperandKind {
+    Register,
+    FpuRegister,
+    Number,
+    Float,
+    Label,
+    Address,
+}
+
+impl OperandKind {
+    fn of(arg: &ArgumentType) -> Self {
+        match arg {
+            ArgumentType::Rd | ArgumentType::Rs | ArgumentType::Rt => OperandKind::Register,
+            ArgumentType::Fd | ArgumentType::Fs | ArgumentType::Ft => OperandKind::FpuRegister,
+            ArgumentType::Shamt
+            | ArgumentType::I16
+            | ArgumentType::U16
+            | ArgumentType::I32
+            | ArgumentType::U32 => OperandKind::Number,
+            ArgumentType::F32 | ArgumentType::F64 => OperandKind::Float,
+            ArgumentType::J => OperandKind::Label,
+            ArgumentType::OffRs
+            | ArgumentType::OffRt
+            | ArgumentType::Off32Rs
+            | ArgumentType::Off32Rt => OperandKind::Address,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            OperandKind::Register => "a register",
+            OperandKind::FpuRegister => "a floating point register",
+            OperandKind::Number => "a number",
+            OperandKind::Float => "a floating point number",
+            OperandKind::Label => "a label",
+            OperandKind::Address => "an address",
+        }
+    }
+}
+
+/// What's expected next, for "expected X, found Y" messages.
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Expected {
+    Statement,
+    /// an operand which could be any of these (or anything, if empty, for
+    /// instructions which aren't in the instruction set)
+    Operand(Vec<OperandKind>),
+    Value,
+    String,
+    Float,
+    Label,
+    Register,
+    Token(&'static str),
+    CommaOrEnd,
+    End,
+}
+
+impl Expected {
+    fn describe(&self) -> String {
+        match self {
+            Expected::Statement => "an instruction, directive, label or constant".into(),
+            Expected::Operand(kinds) if kinds.is_empty() => "an operand".into(),
+            Expected::Operand(kinds) => {
+                let names: Vec<&str> = kinds.iter().map(OperandKind::describe).collect();
+                match names.split_last() {
+                    Some((last, [])) => last.to_string(),
+                    Some((last, rest)) => std::format!("{} or {}", rest.join(", "), last),
+                    None => unreachable!(),
+                }
+            }
+            Expected::Value => "a value".into(),
+            Expected::String => "a string".into(),
+            Expected::Float => "a floating point number".into(),
+            Expected::Label => "a label".into(),
+            Expected::Register => "a register".into(),
+            Expected::Token(token) => std::format!("`{}`", token),
+            Expected::CommaOrEnd => "`,` or the end of the line".into(),
+            Expected::End => "the end of the line".into(),
+        }
+    }
+
+    /// Whether a floating point register could be meant.
+    fn wants_fpu_register(&self) -> bool {
+        match self {
+            Expected::Operand(kinds) => kinds.contains(&OperandKind::FpuRegister),
+            _ => false,
+        }
+    }
+
+    /// Whether a general purpose register could be meant.
+    fn wants_register(&self) -> bool {
+        match self {
+            Expected::Operand(kinds) => {
+                kinds.is_empty()
+                    || kinds
+                        .iter()
+                        .any(|kind| matches!(kind, OperandKind::Register | OperandKind::Address))
+            }
+            Expected::Register => true,
+            _ => false,
+        }
+    }
+}
+
+/// Walks a statement the way mipsy's parser would, to work out what it was
+/// expecting where it went wrong.
+struct Walker<'t, 'a> {
+    tokens: &'t [Token<'a>],
+    pos: usize,
+    /// where the line ends (before any comment)
+    end: usize,
+}
+
+type Walked<T> = Result<T, SyntaxProblem>;
+
+impl<'a> Walker<'_, 'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_kind(&self) -> Option<TokenKind> {
+        self.peek().map(|token| token.kind)
+    }
+
+    fn is_at(&self, kind: TokenKind) -> bool {
+        self.peek_kind() == Some(kind)
+    }
+
+    /// The problem with finding the next token where `expected` should be.
+    fn unexpected(&self, expected: &Expected) -> SyntaxProblem {
+        let found = self.peek();
+        let (start, end) = match found {
+            Some(token) => (token.start, token.end),
+            None => (self.end, self.end + 1),
+        };
+
+        let problem = SyntaxProblem {
+            message: std::format!(
+                "expected {}, found {}",
+                expected.describe(),
+                describe_token(found)
+            ),
+            tips: vec![],
+            start,
+            end,
+        };
+
+        match found {
+            Some(token) if token.kind == TokenKind::Register && *expected == Expected::Value => {
+                problem.with_tip("registers can't be used in expressions")
+            }
+            _ => problem,
+        }
+    }
+
+    /// Takes the next token, as long as there's nothing wrong with it on its
+    /// own (e.g. an unknown register, or a string that doesn't end).
+    fn take(&mut self, expected: &Expected) -> Walked<Token<'a>> {
+        let token = *self.peek().ok_or_else(|| self.unexpected(expected))?;
+
+        let problem = match token.kind {
+            TokenKind::UnterminatedString => Some(
+                SyntaxProblem::at_token(
+                    &token,
+                    "expected a closing `\"`, found the end of the line".into(),
+                )
+                .with_tip("strings have to start and end on the same line"),
+            ),
+            TokenKind::UnterminatedChar => Some(
+                SyntaxProblem::at_token(
+                    &token,
+                    "expected a closing `'`, found the end of the line".into(),
+                )
+                .with_tip("character literals look like `'a'` or `'\\n'`"),
+            ),
+            TokenKind::Unknown if token.text == "$" => Some(
+                SyntaxProblem::at_token(&token, "expected a register name after `$`".into())
+                    .with_tip("registers are written like `$t0` or `$8`"),
+            ),
+            TokenKind::Unknown => Some(SyntaxProblem::at_token(
+                &token,
+                std::format!("unexpected character {}", describe_token(Some(&token))),
+            )),
+            TokenKind::Number
+                if parse_number(token.text).is_none() && !token.text.contains('.') =>
+            {
+                Some(SyntaxProblem::at_token(
+                    &token,
+                    std::format!("invalid number {}", describe_token(Some(&token))),
+                ))
+            }
+            TokenKind::Register if !is_register(token.text) => {
+                Some(unknown_register(&token, expected))
+            }
+            _ => None,
+        };
+
+        match problem {
+            Some(problem) => Err(problem),
+            None => {
+                self.pos += 1;
+                Ok(token)
+            }
+        }
+    }
+
+    fn take_kind(&mut self, kind: TokenKind, expected: Expected) -> Walked<Token<'a>> {
+        if self.is_at(kind) {
+            self.take(&expected)
+        } else {
+            Err(self.unexpected(&expected))
+        }
+    }
+
+    /// An expression made of numbers, constants, labels, character literals
+    /// and operators, as used in immediates and constant definitions.
+    fn expression(&mut self, expected: &Expected) -> Walked<()> {
+        let mut expected = expected.clone();
+        let mut depth = 0;
+
+        loop {
+            match self.peek() {
+                Some(token) if token.kind == TokenKind::Operator => {
+                    if !matches!(token.text, "-" | "+" | "~") {
+                        return Err(self.unexpected(&expected));
+                    }
+                    self.take(&expected)?;
+                    expected = Expected::Value;
+                    continue;
+                }
+                Some(token) if token.kind == TokenKind::LParen => {
+                    self.take(&expected)?;
+                    depth += 1;
+                    expected = Expected::Value;
+                    continue;
+                }
+                Some(token)
+                    if matches!(
+                        token.kind,
+                        TokenKind::Number
+                            | TokenKind::Identifier
+                            | TokenKind::Char
+                            | TokenKind::UnterminatedChar
+                            | TokenKind::Unknown
+                    ) =>
+                {
+                    self.take(&expected)?;
+                }
+                _ => return Err(self.unexpected(&expected)),
+            }
+
+            // after a value
+            loop {
+                match self.peek() {
+                    Some(token) if token.kind == TokenKind::Operator && token.text != "~" => {
+                        self.take(&expected)?;
+                        expected = Expected::Value;
+                        break;
+                    }
+                    Some(token) if token.kind == TokenKind::RParen && depth > 0 => {
+                        self.take(&expected)?;
+                        depth -= 1;
+                    }
+                    _ if depth > 0 => return Err(self.unexpected(&Expected::Token(")"))),
+                    _ => return Ok(()),
+                }
+            }
+        }
+    }
+
+    /// The `($reg)` part of an address.
+    fn address_register(&mut self) -> Walked<()> {
+        self.take_kind(TokenKind::LParen, Expected::Token("("))?;
+        self.take_kind(TokenKind::Register, Expected::Register)
+            .map_err(|problem| match self.peek() {
+                Some(token) if register_index(token.text).is_some() => {
+                    problem.with_tip(std::format!("did you mean `${}`?", token.text))
+                }
+                _ => problem,
+            })?;
+        self.take_kind(TokenKind::RParen, Expected::Token(")"))?;
+        Ok(())
+    }
+
+    fn operand(&mut self, expected: &Expected) -> Walked<()> {
+        match self.peek_kind() {
+            Some(TokenKind::Register) => {
+                self.take(expected)?;
+                Ok(())
+            }
+            Some(TokenKind::LParen)
+                if self.tokens.get(self.pos + 1).map(|token| token.kind)
+                    == Some(TokenKind::Register) =>
+            {
+                self.address_register()
+            }
+            None | Some(TokenKind::Comma) => Err(self.unexpected(expected)),
+            _ => {
+                self.expression(expected)?;
+                if self.is_at(TokenKind::LParen) {
+                    self.address_register()?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// After an operand: `,` if another can follow, or else the end.
+    fn after_operand(&mut self, more: bool) -> Walked<bool> {
+        match self.peek() {
+            None => Ok(false),
+            Some(token) if token.kind == TokenKind::Comma => {
+                self.pos += 1;
+                Ok(true)
+            }
+            Some(token) => {
+                let expected = if more {
+                    Expected::CommaOrEnd
+                } else {
+                    Expected::End
+                };
+                let problem = self.unexpected(&expected);
+                Err(
+                    if more && (token.is_atom() || token.kind == TokenKind::LParen) {
+                        problem.with_tip("did you forget a comma between operands?")
+                    } else {
+                        problem
+                    },
+                )
+            }
+        }
+    }
+
+    fn end(&mut self) -> Walked<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.unexpected(&Expected::End)),
+        }
+    }
+
+    fn instruction_operands(&mut self, formats: &[Vec<ArgumentType>]) -> Walked<()> {
+        let expected_at = |index: usize| {
+            let mut kinds = vec![];
+            for kind in formats
+                .iter()
+                .filter_map(|format| format.get(index))
+                .map(OperandKind::of)
+            {
+                if !kinds.contains(&kind) {
+                    kinds.push(kind);
+                }
+            }
+            kinds
+        };
+        let takes_more =
+            |count: usize| formats.is_empty() || formats.iter().any(|format| format.len() > count);
+
+        if self.peek().is_none() {
+            return Ok(());
+        }
+
+        let mut index = 0;
+        loop {
+            let expected = Expected::Operand(expected_at(index));
+            if index > 0 && self.peek().is_none() {
+                return Err(self
+                    .unexpected(&expected)
+                    .with_tip("there's a trailing comma after the last operand"));
+            }
+
+            self.operand(&expected)?;
+            index += 1;
+            if !self.after_operand(takes_more(index))? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn directive_operands(&mut self, operands: DirectiveOperands) -> Walked<()> {
+        match operands {
+            DirectiveOperands::None => self.end(),
+            DirectiveOperands::String => {
+                match self.peek_kind() {
+                    Some(TokenKind::String | TokenKind::UnterminatedString) => {
+                        self.take(&Expected::String)?;
+                    }
+                    _ => return Err(self.unexpected(&Expected::String)),
+                }
+                self.end()
+            }
+            DirectiveOperands::Value => {
+                self.expression(&Expected::Value)?;
+                self.end()
+            }
+            DirectiveOperands::Label => {
+                self.take_kind(TokenKind::Identifier, Expected::Label)?;
+                self.end()
+            }
+            DirectiveOperands::Values | DirectiveOperands::Floats => loop {
+                if operands == DirectiveOperands::Floats {
+                    if self.peek().is_some_and(|token| token.text == "-") {
+                        self.pos += 1;
+                    }
+                    self.take_kind(TokenKind::Number, Expected::Float)?;
+                } else {
+                    self.expression(&Expected::Value)?;
+                }
+
+                // the number of times to repeat it, e.g. `.word 0:10`
+                if self.is_at(TokenKind::Colon) {
+                    self.pos += 1;
+                    self.expression(&Expected::Value)?;
+                }
+
+                if !self.after_operand(true)? {
+                    return Ok(());
+                }
+                if self.peek().is_none() {
+                    let expected = if operands == DirectiveOperands::Floats {
+                        Expected::Float
+                    } else {
+                        Expected::Value
+                    };
+                    return Err(self
+                        .unexpected(&expected)
+                        .with_tip("there's a trailing comma after the last operand"));
+                }
+            },
+        }
+    }
+
+    fn statement(&mut self, formats: &dyn Fn(&str) -> Vec<Vec<ArgumentType>>) -> Walked<()> {
+        while let [label, colon, ..] = &self.tokens[self.pos..] {
+            if label.kind != TokenKind::Identifier || colon.kind != TokenKind::Colon {
+                break;
+            }
+            self.pos += 2;
+        }
+
+        let first = match self.peek() {
+            None => return Ok(()),
+            Some(first) => *first,
+        };
+        let second = self.tokens.get(self.pos + 1).map(|token| token.kind);
+
+        match (first.kind, second) {
+            (TokenKind::Identifier, Some(TokenKind::Equals)) => {
+                self.pos += 2;
+                self.expression(&Expected::Value)?;
+                self.end()
+            }
+            (TokenKind::Directive, _) => {
+                let directive = DIRECTIVES
+                    .iter()
+                    .find(|directive| directive.name == first.text)
+                    .ok_or_else(|| unknown_directive(&first))?;
+                self.pos += 1;
+                self.directive_operands(directive.operands)
+            }
+            (TokenKind::Identifier, _) => {
+                self.pos += 1;
+                self.instruction_operands(&formats(first.text))
+            }
+            _ => {
+                let problem = self.unexpected(&Expected::Statement);
+                Err(match second {
+                    Some(TokenKind::Colon) if first.kind == TokenKind::Number => {
+                        problem.with_tip("label names can't start with a digit")
+                    }
+                    Some(TokenKind::Colon) => problem
+                        .with_tip("label names can only contain letters, digits, `_` and `.`"),
+                    _ if first.kind == TokenKind::Register => {
+                        problem.with_tip("instructions have to start with a mnemonic, e.g. `add`")
+                    }
+                    _ => problem,
+                })
+            }
+        }
+    }
+}
+
+fn unknown_register(token: &Token, expected: &Expected) -> SyntaxProblem {
+    let problem = SyntaxProblem::at_token(token, std::format!("unknown register `{}`", token.text));
+
+    let fpu_names: Vec<String> = (0..32).map(|index| std::format!("f{}", index)).collect();
+    let mut names: Vec<&str> = vec![];
+    if expected.wants_register() || !expected.wants_fpu_register() {
+        names.extend(GP_REGISTER_NAMES);
+    }
+    if expected.wants_fpu_register() {
+        names.extend(fpu_names.iter().map(String::as_str));
+    }
+
+    match closest_match(&token.text[1..], names) {
+        Some(suggestion) => problem.with_tip(std::format!("did you mean `${}`?", suggestion)),
+        None => problem,
+    }
+}
+
+fn unknown_directive(token: &Token) -> SyntaxProblem {
+    let problem =
+        SyntaxProblem::at_token(token, std::format!("unknown directive `{}`", token.text));
+
+    match closest_match(
+        token.text,
+        DIRECTIVES.iter().map(|directive| directive.name),
+    ) {
+        Some(suggestion) => problem.with_tip(std::format!("did you mean `{}`?", suggestion)),
+        None => problem,
+    }
+}
+
+/// Explains why mipsy couldn't parse a line, given the byte offset mipsy
+/// stopped at, as `mipsy_parser` only reports where that was. `formats`
+/// gives the operands of each form of an instruction, from the instruction
+/// set, which are what's expected after its mnemonic.
+///
+/// The line is followed the way mipsy parses it, to find what was expected
+/// where it went wrong. If that doesn't agree with where mipsy stopped, it
+/// just points out what mipsy stopped at.
+pub fn explain_parse_error(
+    text: &str,
+    offset: usize,
+    formats: impl Fn(&str) -> Vec<Vec<ArgumentType>>,
+) -> SyntaxProblem {
+    let tokens = tokenise_line(text);
+    let (tokens, end) = match tokens.split_last() {
+        Some((comment, rest)) if comment.kind == TokenKind::Comment => (rest, comment.start),
+        _ => (tokens.as_slice(), text.trim_end().len()),
+    };
+
+    let mut walker = Walker {
+        tokens,
+        pos: 0,
+        end,
+    };
+
+    match walker.statement(&formats) {
+        Err(problem) if problem.end > offset => problem,
+        _ => {
+            let token = tokens.iter().find(|token| token.end > offset);
+            SyntaxProblem {
+                message: std::format!("unexpected {}", describe_token(token)),
+                tips: vec![],
+                start: token.map_or(offset, |token| token.start),
+                end: token.map_or(offset + 1, |token| token.end),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mipsy_parser::{parse_mips, TaggedFile};
+    use ArgumentType::*;
+
+    fn formats(mnemonic: &str) -> Vec<Vec<ArgumentType>> {
+        match mnemonic {
+            "add" => vec![vec![Rd, Rs, Rt], vec![Rd, Rs, I16]],
+            "add.s" => vec![vec![Fd, Fs, Ft]],
+            "li" => vec![vec![Rt, I32]],
+            "lw" => vec![vec![Rt, OffRs], vec![Rt, J]],
+            "jr" => vec![vec![Rs]],
+            "syscall" => vec![vec![]],
+            _ => vec![],
+        }
+    }
+
+    /// Explains an error where mipsy stopped at the first occurrence of `at`.
+    fn explain(text: &str, at: &str) -> SyntaxProblem {
+        let offset = text.find(at).unwrap();
+        explain_parse_error(text, offset, formats)
+    }
+
+    fn underlined<'a>(text: &'a str, problem: &SyntaxProblem) -> &'a str {
+        &text[problem.start..problem.end.min(text.len())]
+    }
+
+    #[test]
+    fn finds_missing_commas() {
+        let text = "add $t0 $t1, $t2";
+        let problem = explain(text, "$t1");
+
+        assert_eq!(
+            problem.message,
+            "expected `,` or the end of the line, found `$t1`"
+        );
+        assert_eq!(problem.tips, ["did you forget a comma between operands?"]);
+        assert_eq!(underlined(text, &problem), "$t1");
+    }
+
+    #[test]
+    fn finds_too_many_operands() {
+        let problem = explain("add $t0, $t1, $t2 )", ")");
+        assert_eq!(problem.message, "expected the end of the line, found `)`");
+    }
+
+    #[test]
+    fn finds_trailing_commas() {
+        let text = "add $t0, $t1, # comment";
+        let problem = explain_parse_error(text, text.find('#').unwrap(), formats);
+
+        assert_eq!(
+            problem.message,
+            "expected a register or a number, found the end of the line"
+        );
+        assert_eq!(
+            problem.tips,
+            ["there's a trailing comma after the last operand"]
+        );
+    }
+
+    #[test]
+    fn suggests_registers_of_the_expected_kind() {
+        let problem = explain("add $t0, $t1, $tO", "$tO");
+        assert_eq!(problem.message, "unknown register `$tO`");
+        assert_eq!(problem.tips, ["did you mean `$t0`?"]);
+
+        let problem = explain("add.s $f0, $f1, $ff2", "$ff2");
+        assert_eq!(problem.tips, ["did you mean `$f2`?"]);
+    }
+
+    #[test]
+    fn finds_registers_without_dollars() {
+        let text = "lw $t0, 4(sp)";
+        let problem = explain(text, "sp");
+
+        assert_eq!(problem.message, "expected a register, found `sp`");
+        assert_eq!(problem.tips, ["did you mean `$sp`?"]);
+        assert_eq!(underlined(text, &problem), "sp");
+    }
+
+    #[test]
+    fn finds_bad_expressions() {
+        let problem = explain("li $t0, 1 + * 2", "*");
+        assert_eq!(problem.message, "expected a value, found `*`");
+
+        let problem = explain("li $t0, (1 + 2", "2");
+        assert_eq!(problem.message, "expected `)`, found the end of the line");
+    }
+
+    #[test]
+    fn finds_unknown_directives() {
+        let problem = explain(".wrd 1", ".wrd");
+        assert_eq!(problem.message, "unknown directive `.wrd`");
+        assert_eq!(problem.tips, ["did you mean `.word`?"]);
+    }
+
+    #[test]
+    fn finds_bad_literals() {
+        let problem = explain(".asciiz \"hi", "\"");
+        assert_eq!(
+            problem.message,
+            "expected a closing `\"`, found the end of the line"
+        );
+
+        let problem = explain(".byte 'a", "'");
+        assert_eq!(
+            problem.message,
+            "expected a closing `'`, found the end of the line"
+        );
+
+        let problem = explain("li $t0, 0xg", "0xg");
+        assert_eq!(problem.message, "invalid number `0xg`");
+    }
+
+    #[test]
+    fn finds_bad_labels() {
+        let problem = explain("1abc: add $t0, $t1, $t2", "1abc");
+        assert_eq!(
+            problem.message,
+            "expected an instruction, directive, label or constant, found `1abc`"
+        );
+        assert_eq!(problem.tips, ["label names can't start with a digit"]);
+    }
+
+    #[test]
+    fn trusts_where_mipsy_stopped() {
+        // nothing looks wrong before where mipsy stopped, so just point at it
+        let text = "add $t0, $t1, $t2";
+        let problem = explain(text, "$t2");
+
+        assert_eq!(problem.message, "unexpected `$t2`");
+        assert_eq!(underlined(text, &problem), "$t2");
+    }
+
+    #[test]
+    fn knows_every_directive_mipsy_parses() {
+        for directive in DIRECTIVES {
+            let operands = match directive.operands {
+                DirectiveOperands::None => "",
+                DirectiveOperands::String => " \"hi\\n\"",
+                DirectiveOperands::Values => " 1, 'a', N + 1:2",
+                DirectiveOperands::Floats => " 1.5, -2.0:3",
+                DirectiveOperands::Value => " 2",
+                DirectiveOperands::Label => " main",
+            };
+            let text = std::format!("{}{}", directive.name, operands);
+
+            assert!(
+                parse_mips(vec![TaggedFile::new(Some("test.s"), &text)], 8).is_ok(),
+                "mipsy can't parse `{}`",
+                text
+            );
+
+            let tokens = tokenise_line(&text);
+            let mut walker = Walker {
+                tokens: &tokens,
+                pos: 0,
+                end: text.len(),
+            };
+            assert!(walker.statement(&formats).is_ok(), "`{}`", text);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+/// A syscall, by the number that goes in `$v0`.
+pub struct Syscall {
+    pub number: u32,
+    pub name: &'static str,
+    /// what the debug adapter is told is waiting on it, by
+    /// `DebugRuntime::get_syscall_type`
+    pub kind: &'static str,
+    /// whether it's one of the syscalls students usually use
+    pub is_common: bool,
+}
+
+const fn syscall(number: u32, name: &'static str, kind: &'static str, is_common: bool) -> Syscall {
+    Syscall {
+        number,
+        name,
+        kind,
+        is_common,
+    }
+}
+
+/// The syscalls mipsy supports.
+pub const SYSCALLS: &[Syscall] = &[
+    syscall(1, "print int", "print", true),
+    syscall(2, "print float", "print", false),
+    syscall(3, "print double", "print", false),
+    syscall(4, "print string", "print", true),
+    syscall(5, "read int", "read_int", true),
+    syscall(6, "read float", "read_float", false),
+    syscall(7, "read double", "read_double", false),
+    syscall(8, "read string", "read_string", false),
+    syscall(9, "sbrk", "sbrk", false),
+    syscall(10, "exit", "exit", false),
+    syscall(11, "print character", "print", true),
+    syscall(12, "read character", "read_character", true),
+    syscall(13, "open file", "open", false),
+    syscall(14, "read file", "read", false),
+    syscall(15, "write file", "write", false),
+    syscall(16, "close file", "close", false),
+    syscall(17, "exit2", "exit", false),
+];
+
+pub fn find_syscall(number: u32) -> Option<&'static Syscall> {
+    SYSCALLS.iter().find(|syscall| syscall.number == number)
+}
@@ -0,0 +1,29 @@
+//! Helpers for tests which need a compiled program.
+use mipsy_lib::{compile::get_kernel, Binary};
+use mipsy_parser::TaggedFile;
+
+use crate::ProgramOptions;
+
+/// Compiles the files with the kernel, the way the editor does, panicking if
+/// they don't compile.
+pub fn compile_files(files: &[(&str, &str)]) -> Binary {
+    let options = ProgramOptions::default();
+    let tagged_files = files
+        .iter()
+        .map(|&(tag, source)| TaggedFile::new(Some(tag), source))
+        .collect();
+
+    mipsy_lib::compile_with_kernel(
+        &mipsy_instructions::inst_set(),
+        tagged_files,
+        &mut get_kernel(),
+        &options.compiler_options(),
+        &options.config(),
+    )
+    .unwrap_or_else(|_| panic!("failed to compile:\n{:?}", files))
+}
+
+/// Compiles a single file tagged `test.s`.
+pub fn compile(source: &str) -> Binary {
+    compile_files(&[("test.s", source)])
+}
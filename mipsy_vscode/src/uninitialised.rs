@@ -0,0 +1,238 @@
+use std::collections::{BTreeMap, HashMap};
+
+use mipsy_lib::Binary;
+
+use crate::{
+    calling_convention::{find_functions, function_body},
+    cfg::successors,
+    decode::{register_bit, registers_in, text_instructions, Decoded, Flow, RegisterSet},
+    diagnostics::Warnings,
+    registers::{register_name, A0, A3, FP, RA, SP, V0, V1, ZERO},
+};
+
+/// Every register.
+const ALL: RegisterSet = !0;
+
+/// The registers which have a value when a function starts: its arguments,
+/// the stack pointer and return address, and `$zero`.
+fn abi_inputs() -> RegisterSet {
+    (A0..=A3)
+        .chain([SP, RA, ZERO])
+        .map(register_bit)
+        .fold(0, |set, bit| set | bit)
+}
+
+/// The registers an instruction reads, except for those it saves onto the
+/// stack (e.g. `sw $s0, 0($sp)` in a function's prologue), which is fine to
+/// do whether or not they've been set.
+fn reads(inst: &Decoded) -> RegisterSet {
+    let is_store = (0x28..=0x2E).contains(&inst.opcode);
+
+    if is_store && (inst.rs == SP || inst.rs == FP) {
+        inst.reads() & !register_bit(inst.rt)
+    } else {
+        inst.reads()
+    }
+}
+
+/// Works out which registers a call to each function could set, so that the
+/// caller doesn't get warned about reading them afterwards.
+struct Callees<'a> {
+    instructions: &'a HashMap<u32, Decoded>,
+    writes: HashMap<u32, RegisterSet>,
+}
+
+impl Callees<'_> {
+    fn writes(&mut self, flow: Flow) -> RegisterSet {
+        let target = match flow {
+            Flow::Call(target) => target,
+            // could be calling anything
+            _ => return ALL,
+        };
+
+        if let Some(&writes) = self.writes.get(&target) {
+            return writes;
+        }
+
+        let mut writes = register_bit(V0) | register_bit(V1);
+        for pc in function_body(self.instructions, target).into_keys() {
+            let inst = &self.instructions[&pc];
+            writes |= match inst.flow(pc) {
+                // not worth following calls any deeper
+                Flow::Call(_) | Flow::CallIndirect => ALL,
+                _ => inst.writes(),
+            };
+        }
+
+        self.writes.insert(target, writes);
+        writes
+    }
+}
+
+/// The registers which have definitely been written to before each
+/// instruction in the function starting at `entry`, whichever path is taken
+/// to get there.
+fn initialised(
+    instructions: &HashMap<u32, Decoded>,
+    callees: &mut Callees,
+    entry: u32,
+) -> BTreeMap<u32, RegisterSet> {
+    let body = function_body(instructions, entry);
+    let mut states: BTreeMap<u32, RegisterSet> = BTreeMap::new();
+    let mut worklist = vec![entry];
+    states.insert(entry, abi_inputs());
+
+    while let Some(pc) = worklist.pop() {
+        let inst = &instructions[&pc];
+        let flow = inst.flow(pc);
+
+        let mut after = states[&pc] | inst.writes();
+        if matches!(flow, Flow::Call(_) | Flow::CallIndirect) {
+            after |= callees.writes(flow);
+        }
+
+        for (successor, _) in successors(pc, inst, body[&pc]) {
+            if !body.contains_key(&successor) {
+                continue;
+            }
+
+            let joined = states.get(&successor).map_or(after, |&state| state & after);
+            if states.get(&successor) != Some(&joined) {
+                states.insert(successor, joined);
+                worklist.push(successor);
+            }
+        }
+    }
+
+    states
+}
+
+/// Warns about registers which might be read before anything has been
+/// written to them, along some path through a function. The functions are
+/// those found by the calling convention checks, along with `main`.
+pub fn check_uninitialised(binary: &Binary, warnings: &mut Warnings) {
+    let file_tag = warnings.file_tag().to_owned();
+    let instructions: HashMap<u32, Decoded> = text_instructions(binary).collect();
+
+    let mut functions: Vec<(String, u32)> = find_functions(binary, &instructions)
+        .into_iter()
+        .map(|function| (function.name, function.entry))
+        .collect();
+    if let Some(&main) = binary.labels.get("main") {
+        if instructions.contains_key(&main) && !functions.iter().any(|&(_, entry)| entry == main) {
+            functions.push(("main".to_string(), main));
+        }
+    }
+
+    let mut callees = Callees {
+        instructions: &instructions,
+        writes: HashMap::new(),
+    };
+
+    for (name, entry) in functions {
+        for (pc, state) in initialised(&instructions, &mut callees, entry) {
+            let line = match binary.line_numbers.get(&pc) {
+                Some((tag, line)) if **tag == *file_tag => *line,
+                _ => continue,
+            };
+
+            for reg in registers_in(reads(&instructions[&pc]) & !state) {
+                warnings.warn_register(
+                    line,
+                    reg,
+                    std::format!(
+                        "`{}` might be read before anything is written to it",
+                        register_name(reg)
+                    ),
+                    vec![
+                        std::format!(
+                            "there's a way to get here from the start of `{}` without setting `{}`",
+                            name,
+                            register_name(reg)
+                        ),
+                        "only `$a0`-`$a3`, `$sp` and `$ra` are set when a function starts".into(),
+                    ],
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::compile;
+
+    /// The line and message of each warning.
+    fn warned(source: &str) -> Vec<(u32, String)> {
+        let binary = compile(source);
+        let mut warnings = Warnings::new("test.s", source, 8);
+        check_uninitialised(&binary, &mut warnings);
+
+        warnings
+            .into_reports()
+            .into_iter()
+            .map(|warning| (warning.line, warning.message))
+            .collect()
+    }
+
+    #[test]
+    fn warns_about_a_register_only_set_on_one_path() {
+        let source = "\
+main:
+\tbeq\t$a0, $zero, skip
+\tli\t$t0, 1
+skip:
+\tmove\t$v0, $t0
+\tjr\t$ra
+";
+
+        assert_eq!(
+            warned(source),
+            [(
+                5,
+                "`$t0` might be read before anything is written to it".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn lets_a_prologue_save_registers_it_hasnt_set() {
+        let source = "\
+main:
+\taddi\t$sp, $sp, -8
+\tsw\t$s0, 0($sp)
+\tsw\t$ra, 4($sp)
+\tlw\t$s0, 0($sp)
+\tlw\t$ra, 4($sp)
+\taddi\t$sp, $sp, 8
+\tjr\t$ra
+";
+
+        assert_eq!(warned(source), []);
+
+        // but reading it any other way is still a problem
+        let source = source.replace("\tlw\t$s0, 0($sp)", "\tmove\t$t0, $s0");
+        let lines: Vec<u32> = warned(&source).into_iter().map(|(line, _)| line).collect();
+        assert_eq!(lines, [5]);
+    }
+
+    #[test]
+    fn knows_a_call_sets_the_return_value() {
+        let source = "\
+main:
+\taddi\t$sp, $sp, -4
+\tsw\t$ra, 0($sp)
+\tjal\tf
+\tmove\t$a0, $v0
+\tlw\t$ra, 0($sp)
+\taddi\t$sp, $sp, 4
+\tjr\t$ra
+f:
+\tli\t$v0, 1
+\tjr\t$ra
+";
+
+        assert_eq!(warned(source), []);
+    }
+}
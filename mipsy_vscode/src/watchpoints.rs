@@ -0,0 +1,529 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    condition::{Breakpoint, BreakpointHit, Condition, Location, SourceBreakpoint},
+    decode::Decoded,
+    registers::{register_index, register_name},
+};
+
+/// What a watchpoint watches.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WatchTarget {
+    /// a general purpose register, `$hi` or `$lo`
+    Register(Location),
+    /// `len` bytes of memory, starting at `start`
+    Memory { start: u32, len: u32 },
+}
+
+impl WatchTarget {
+    /// Works out what to watch from a name the editor gives: a register (as
+    /// named in the Variables view, e.g. `$t0` or `HI`), or an expression for
+    /// the address of some memory (e.g. `arr + 8`), which is worked out now.
+    pub fn from_name(
+        name: &str,
+        bytes: Option<u32>,
+        labels: &HashMap<String, u32>,
+        read: &impl Fn(Location) -> Option<i32>,
+    ) -> Result<Self, String> {
+        let name = name.trim();
+
+        match &*name.trim_start_matches('$').to_ascii_lowercase() {
+            "hi" => return Ok(WatchTarget::Register(Location::Hi)),
+            "lo" => return Ok(WatchTarget::Register(Location::Lo)),
+            "pc" => return Err("the program counter can't be watched".into()),
+            _ => {}
+        }
+
+        if name.starts_with('$') {
+            return register_index(name)
+                .map(|reg| WatchTarget::Register(Location::Register(reg)))
+                .ok_or_else(|| std::format!("`{}` isn't a register", name));
+        }
+
+        let start = Condition::parse(name, labels)?.evaluate(read)? as u32;
+        match bytes.unwrap_or(4) {
+            0 => Err("can't watch 0 bytes".into()),
+            len => Ok(WatchTarget::Memory { start, len }),
+        }
+    }
+
+    /// Reads back a `dataId` made by `data_id`.
+    pub fn from_data_id(data_id: &str) -> Option<Self> {
+        match data_id.split_once('/') {
+            Some((start, len)) => Some(WatchTarget::Memory {
+                start: u32::from_str_radix(start.strip_prefix("0x")?, 16).ok()?,
+                len: len.parse().ok()?,
+            }),
+            None => match data_id {
+                "$hi" => Some(WatchTarget::Register(Location::Hi)),
+                "$lo" => Some(WatchTarget::Register(Location::Lo)),
+                _ => register_index(data_id)
+                    .map(|reg| WatchTarget::Register(Location::Register(reg))),
+            },
+        }
+    }
+
+    /// How DAP refers to the watchpoint, e.g. `$t0` or `0x10010000/4`.
+    pub fn data_id(&self) -> String {
+        match *self {
+            WatchTarget::Register(Location::Register(reg)) => register_name(reg),
+            WatchTarget::Register(Location::Lo) => "$lo".into(),
+            WatchTarget::Register(_) => "$hi".into(),
+            WatchTarget::Memory { start, len } => std::format!("0x{:08X}/{}", start, len),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match *self {
+            WatchTarget::Register(_) => std::format!("`{}`", self.data_id()),
+            WatchTarget::Memory { start, len: 1 } => std::format!("the byte at 0x{:08X}", start),
+            WatchTarget::Memory { start, len: 2 } => std::format!("the half at 0x{:08X}", start),
+            WatchTarget::Memory { start, len: 4 } => std::format!("the word at 0x{:08X}", start),
+            WatchTarget::Memory { start, len } => {
+                std::format!("the {} bytes at 0x{:08X}", len, start)
+            }
+        }
+    }
+
+    /// The ways the watched location can be accessed, as DAP names them.
+    pub fn access_types(&self) -> Vec<String> {
+        match self {
+            // there's no telling when a register is read without changing
+            WatchTarget::Register(_) => vec!["write".into()],
+            WatchTarget::Memory { .. } => vec!["read".into(), "write".into(), "readWrite".into()],
+        }
+    }
+
+    pub fn values(&self, read: &impl Fn(Location) -> Option<i32>) -> Vec<Option<i32>> {
+        match *self {
+            WatchTarget::Register(location) => vec![read(location)],
+            WatchTarget::Memory { start, len } => (0..len)
+                .map(|i| read(Location::Byte(start.wrapping_add(i))))
+                .collect(),
+        }
+    }
+
+    pub fn format_values(&self, values: &[Option<i32>]) -> String {
+        let bytes: Option<Vec<i32>> = values.iter().copied().collect();
+
+        match (self, bytes) {
+            (WatchTarget::Register(_), Some(value)) => value[0].to_string(),
+            (WatchTarget::Register(_), None) => "uninitialised".into(),
+            (WatchTarget::Memory { len: 1 | 2 | 4, .. }, Some(bytes)) => {
+                // little endian, like mipsy
+                let bits = 8 * bytes.len() as u32;
+                let value = bytes
+                    .iter()
+                    .rev()
+                    .fold(0i64, |value, &byte| (value << 8) | i64::from(byte & 0xFF));
+                let value = if value & (1 << (bits - 1)) != 0 {
+                    value - (1 << bits)
+                } else {
+                    value
+                };
+
+                value.to_string()
+            }
+            _ if values.iter().all(Option::is_none) => "uninitialised".into(),
+            _ => values
+                .iter()
+                .map(|byte| match byte {
+                    Some(byte) => std::format!("{:02X}", byte & 0xFF),
+                    None => "??".into(),
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+
+    fn overlaps(&self, address: u32, size: u32) -> bool {
+        match *self {
+            WatchTarget::Register(_) => false,
+            WatchTarget::Memory { start, len } => {
+                u64::from(address) < u64::from(start) + u64::from(len)
+                    && u64::from(start) < u64::from(address) + u64::from(size)
+            }
+        }
+    }
+}
+
+/// A data breakpoint as the editor sets it (DAP's `DataBreakpoint`).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DataBreakpoint {
+    pub data_id: String,
+    #[serde(default)]
+    pub access_type: Option<String>,
+    #[serde(default)]
+    pub condition: Option<String>,
+    #[serde(default)]
+    pub hit_condition: Option<String>,
+}
+
+/// What can be watched for a name, for DAP's `dataBreakpointInfo`.
+#[derive(Serialize, Deserialize)]
+pub struct WatchpointInfo {
+    pub data_id: Option<String>,
+    pub description: String,
+    pub access_types: Vec<String>,
+}
+
+/// Whether a watchpoint could be set, and why not.
+#[derive(Serialize, Deserialize)]
+pub struct WatchpointStatus {
+    pub verified: bool,
+    pub message: Option<String>,
+}
+
+/// A watchpoint being triggered by the instruction at `pc`.
+pub struct WatchpointHit {
+    pub pc: u32,
+    pub description: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+struct Watchpoint {
+    target: WatchTarget,
+    access: Access,
+    /// the condition and hit count
+    breakpoint: Breakpoint,
+}
+
+/// What the watched locations held before an instruction ran.
+struct Before {
+    pc: u32,
+    values: Vec<Vec<Option<i32>>>,
+    /// the (address, size) of memory the instruction loads from
+    load: Option<(u32, u32)>,
+}
+
+/// The watchpoints of a running program, checked around each step.
+#[derive(Default)]
+pub struct Watchpoints {
+    watchpoints: Vec<Watchpoint>,
+    before: Option<Before>,
+}
+
+impl Watchpoints {
+    /// Replaces every watchpoint, returning whether each one could be set.
+    pub fn set(
+        &mut self,
+        data_breakpoints: &[DataBreakpoint],
+        labels: &HashMap<String, u32>,
+    ) -> Vec<WatchpointStatus> {
+        self.watchpoints.clear();
+        self.before = None;
+
+        data_breakpoints
+            .iter()
+            .map(|data_breakpoint| match self.add(data_breakpoint, labels) {
+                Ok(()) => WatchpointStatus {
+                    verified: true,
+                    message: None,
+                },
+                Err(message) => WatchpointStatus {
+                    verified: false,
+                    message: Some(message),
+                },
+            })
+            .collect()
+    }
+
+    fn add(
+        &mut self,
+        data_breakpoint: &DataBreakpoint,
+        labels: &HashMap<String, u32>,
+    ) -> Result<(), String> {
+        let target = WatchTarget::from_data_id(&data_breakpoint.data_id)
+            .ok_or_else(|| std::format!("`{}` can't be watched", data_breakpoint.data_id))?;
+
+        let access = match data_breakpoint.access_type.as_deref() {
+            None | Some("write") => Access::Write,
+            Some("read") => Access::Read,
+            Some("readWrite") => Access::ReadWrite,
+            Some(access) => return Err(std::format!("unknown access type `{}`", access)),
+        };
+        if access != Access::Write && matches!(target, WatchTarget::Register(_)) {
+            return Err("registers can only be watched for changes".into());
+        }
+
+        let breakpoint = Breakpoint::new(
+            &SourceBreakpoint {
+                line: 0,
+                condition: data_breakpoint.condition.clone(),
+                hit_condition: data_breakpoint.hit_condition.clone(),
+                log_message: None,
+            },
+            labels,
+        )?;
+
+        self.watchpoints.push(Watchpoint {
+            target,
+            access,
+            breakpoint,
+        });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watchpoints.is_empty()
+    }
+
+    /// Remembers what the watched locations hold before the instruction at
+    /// `pc` runs.
+    pub fn before_step(
+        &mut self,
+        pc: u32,
+        inst: Option<Decoded>,
+        read: &impl Fn(Location) -> Option<i32>,
+    ) {
+        if self.is_empty() {
+            return;
+        }
+
+        let load = inst.and_then(|inst| match inst.memory_access() {
+            Some((size, false)) => {
+                let base = read(Location::Register(inst.rs))?;
+                Some((base.wrapping_add(inst.imm.into()) as u32, size))
+            }
+            _ => None,
+        });
+
+        self.before = Some(Before {
+            pc,
+            values: self
+                .watchpoints
+                .iter()
+                .map(|watchpoint| watchpoint.target.values(read))
+                .collect(),
+            load,
+        });
+    }
+
+    /// Forgets what was remembered by `before_step`, e.g. when stepping
+    /// backwards.
+    pub fn forget_step(&mut self) {
+        self.before = None;
+    }
+
+    /// Checks whether the instruction which has just run triggered a
+    /// watchpoint, by changing what it watches or loading from it.
+    pub fn after_step(&mut self, read: &impl Fn(Location) -> Option<i32>) -> Option<WatchpointHit> {
+        let before = self.before.take()?;
+
+        for (watchpoint, old) in self.watchpoints.iter_mut().zip(before.values) {
+            let new = watchpoint.target.values(read);
+            let target = watchpoint.target;
+
+            let written = watchpoint.access != Access::Read && old != new;
+            let loaded = watchpoint.access != Access::Write
+                && before
+                    .load
+                    .is_some_and(|(address, size)| target.overlaps(address, size));
+            if !written && !loaded {
+                continue;
+            }
+
+            let error = match watchpoint.breakpoint.hit(read) {
+                BreakpointHit::Continue => continue,
+                BreakpointHit::Stop | BreakpointHit::Log(_) => None,
+                BreakpointHit::Error(error) => Some(error),
+            };
+
+            let mut description = if written {
+                std::format!(
+                    "{} changed from {} to {}",
+                    target.describe(),
+                    target.format_values(&old),
+                    target.format_values(&new),
+                )
+            } else {
+                std::format!(
+                    "{} was read (it's {})",
+                    target.describe(),
+                    target.format_values(&new),
+                )
+            };
+            if let Some(error) = error {
+                description += &std::format!(" ({})", error);
+            }
+
+            return Some(WatchpointHit {
+                pc: before.pc,
+                description,
+            });
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORD: u32 = 0x10000000;
+    /// `lw $t0, 0($t1)` and `sw $t0, 0($t1)`
+    const LW: u32 = 0x8D280000;
+    const SW: u32 = 0xAD280000;
+
+    /// A machine with `$t1` pointing at `WORD`, which holds `value`.
+    fn machine(value: i32) -> impl Fn(Location) -> Option<i32> {
+        move |location| match location {
+            Location::Register(9) => Some(WORD as i32),
+            Location::Byte(address) if (WORD..WORD + 4).contains(&address) => {
+                Some((value >> (8 * (address - WORD))) & 0xFF)
+            }
+            _ => None,
+        }
+    }
+
+    fn watch(access_type: &str) -> Watchpoints {
+        let mut watchpoints = Watchpoints::default();
+        let status = watchpoints.set(
+            &[DataBreakpoint {
+                data_id: std::format!("0x{:08X}/4", WORD),
+                access_type: Some(access_type.into()),
+                condition: None,
+                hit_condition: None,
+            }],
+            &HashMap::new(),
+        );
+        assert!(status[0].verified);
+
+        watchpoints
+    }
+
+    /// Runs an instruction which changes `WORD` from `old` to `new`.
+    fn step(watchpoints: &mut Watchpoints, word: u32, old: i32, new: i32) -> Option<String> {
+        watchpoints.before_step(0x00400000, Some(Decoded::new(word)), &machine(old));
+        watchpoints
+            .after_step(&machine(new))
+            .map(|hit| hit.description)
+    }
+
+    #[test]
+    fn reads_back_data_ids() {
+        let targets = [
+            WatchTarget::Register(Location::Register(8)),
+            WatchTarget::Register(Location::Hi),
+            WatchTarget::Register(Location::Lo),
+            WatchTarget::Memory {
+                start: 0x10010000,
+                len: 4,
+            },
+        ];
+
+        for target in targets {
+            assert_eq!(WatchTarget::from_data_id(&target.data_id()), Some(target));
+        }
+        assert_eq!(targets[0].data_id(), "$t0");
+        assert_eq!(targets[3].data_id(), "0x10010000/4");
+
+        assert_eq!(WatchTarget::from_data_id("$nope"), None);
+        assert_eq!(WatchTarget::from_data_id("10010000/4"), None);
+        assert_eq!(WatchTarget::from_data_id("0x10010000/x"), None);
+    }
+
+    #[test]
+    fn sign_extends_values() {
+        let memory = |len| WatchTarget::Memory { start: WORD, len };
+        let bytes = |bytes: &[i32]| bytes.iter().copied().map(Some).collect::<Vec<_>>();
+
+        assert_eq!(memory(1).format_values(&bytes(&[0xFF])), "-1");
+        assert_eq!(memory(2).format_values(&bytes(&[0xFE, 0xFF])), "-2");
+        assert_eq!(memory(2).format_values(&bytes(&[0xFF, 0x7F])), "32767");
+        assert_eq!(
+            memory(4).format_values(&bytes(&[0x78, 0x56, 0x34, 0x12])),
+            "305419896"
+        );
+        assert_eq!(
+            memory(4).format_values(&bytes(&[0x00, 0x00, 0x00, 0x80])),
+            i32::MIN.to_string()
+        );
+
+        // anything else is shown byte by byte
+        assert_eq!(
+            memory(3).format_values(&[Some(1), Some(0xAB), None]),
+            "01 AB ??"
+        );
+        assert_eq!(memory(2).format_values(&[None, None]), "uninitialised");
+        assert_eq!(
+            WatchTarget::Register(Location::Register(8)).format_values(&[Some(-5)]),
+            "-5"
+        );
+    }
+
+    #[test]
+    fn overlaps_at_the_edges() {
+        let target = WatchTarget::Memory {
+            start: 0x100,
+            len: 4,
+        };
+
+        assert!(!target.overlaps(0xFC, 4));
+        assert!(target.overlaps(0xFD, 4));
+        assert!(target.overlaps(0x103, 1));
+        assert!(!target.overlaps(0x104, 4));
+
+        let top = WatchTarget::Memory {
+            start: 0xFFFFFFFC,
+            len: 4,
+        };
+        assert!(top.overlaps(0xFFFFFFFF, 1));
+        assert!(!top.overlaps(0, 4));
+        assert!(!WatchTarget::Register(Location::Hi).overlaps(0x100, 4));
+    }
+
+    #[test]
+    fn stops_on_reads_or_writes_as_asked() {
+        let mut read = watch("read");
+        assert_eq!(step(&mut read, SW, 0, 7), None);
+        assert_eq!(
+            step(&mut read, LW, 7, 7).as_deref(),
+            Some("the word at 0x10000000 was read (it's 7)")
+        );
+
+        let mut write = watch("write");
+        assert_eq!(step(&mut write, LW, 7, 7), None);
+        assert_eq!(
+            step(&mut write, SW, 7, -1).as_deref(),
+            Some("the word at 0x10000000 changed from 7 to -1")
+        );
+        // storing the same value doesn't change anything
+        assert_eq!(step(&mut write, SW, 7, 7), None);
+
+        let mut read_write = watch("readWrite");
+        assert!(step(&mut read_write, LW, 7, 7).is_some());
+        assert!(step(&mut read_write, SW, 7, 8).is_some());
+    }
+
+    #[test]
+    fn only_watches_registers_for_writes() {
+        let mut watchpoints = Watchpoints::default();
+        let status = watchpoints.set(
+            &[DataBreakpoint {
+                data_id: "$t0".into(),
+                access_type: Some("read".into()),
+                condition: None,
+                hit_condition: None,
+            }],
+            &HashMap::new(),
+        );
+
+        assert!(!status[0].verified);
+        assert_eq!(
+            status[0].message.as_deref(),
+            Some("registers can only be watched for changes")
+        );
+        assert!(watchpoints.is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+use mipsy_lib::{compile::CompilerOptions, Binary, Safe};
+use mipsy_parser::TaggedFile;
+use mipsy_utils::MipsyConfig;
+use mipsy_vscode::{format_program, FormatOptions, RegisterStyle};
+
+fn compile(source: &str) -> Binary {
+    mipsy_lib::compile(
+        &mipsy_instructions::inst_set(),
+        vec![TaggedFile::new(Some("test.s"), source)],
+        &CompilerOptions::new(vec![]),
+        &MipsyConfig::default(),
+    )
+    .unwrap_or_else(|_| panic!("failed to compile:\n{}", source))
+}
+
+fn bytes(segment: &[Safe<u8>]) -> Vec<Option<u8>> {
+    segment
+        .iter()
+        .map(|byte| byte.as_option().copied())
+        .collect()
+}
+
+fn assert_round_trips(source: &str, options: &FormatOptions) -> String {
+    let formatted = format_program(source, "test.s", options, MipsyConfig::default().tab_size);
+    let (before, after) = (compile(source), compile(&formatted));
+
+    assert_eq!(bytes(&before.text), bytes(&after.text), "\n{}", formatted);
+    assert_eq!(bytes(&before.data), bytes(&after.data), "\n{}", formatted);
+    assert_eq!(bytes(&before.ktext), bytes(&after.ktext), "\n{}", formatted);
+    assert_eq!(bytes(&before.kdata), bytes(&after.kdata), "\n{}", formatted);
+    assert_eq!(before.labels, after.labels, "\n{}", formatted);
+    assert_eq!(before.line_numbers, after.line_numbers, "\n{}", formatted);
+
+    // formatting twice shouldn't change anything more
+    assert_eq!(
+        format_program(
+            &formatted,
+            "test.s",
+            options,
+            MipsyConfig::default().tab_size
+        ),
+        formatted
+    );
+
+    formatted
+}
+
+fn all_options() -> Vec<FormatOptions> {
+    let mut options = vec![];
+    for registers in [
+        RegisterStyle::Names,
+        RegisterStyle::Numbers,
+        RegisterStyle::Preserve,
+    ] {
+        for use_tabs in [true, false] {
+            options.push(FormatOptions {
+                registers,
+                use_tabs,
+            });
+        }
+    }
+    options
+}
+
+const HELLO: &str = "\
+# prints hello world
+main:
+  la $a0,msg # the string
+   li   $v0 , 4
+syscall
+
+    li $v0,0
+  jr $ra
+
+.data
+msg: .asciiz \"hello, world\\n\"   # with a comma in it
+";
+
+const LOOP: &str = "\
+N = 10
+SIZE=N*4
+
+    .text
+main: li $8, 0 # i
+loop:   bge $8,N,end
+        mul $9,$8,4
+        sw $8,array($9)
+        addi $8,$8,1
+        b loop
+end:    li $v0, 0
+        jr $31
+
+        .data
+array:  .space SIZE
+words: .word 1 , 2, 3
+        .word 4:3 # repeated
+";
+
+const FUNCTIONS: &str = "\
+#![tabsize(4)]
+main:
+	begin
+	push $ra
+	li $a0, 3
+	jal fact
+	move $a0,$v0
+	li $v0,1
+	syscall
+	pop $ra
+	end
+	jr $ra
+
+fact:	addiu $sp,$sp,-8
+	sw $ra , 4($sp)
+	sw $s0, 0( $sp )
+	move $s0, $a0
+	ble $a0,1,base # base case
+	addi $a0,$a0,-1
+	jal fact
+	mul $v0,$v0,$s0
+	j done
+base:	li $v0,1
+done:	lw $s0,0($sp)
+	lw $ra, 4($sp)
+	addiu $sp, $sp, 8
+	jr $ra
+";
+
+const CRLF_AND_FLOATS: &str =
+    "main:\r\n  li.s $f0, 1.5\r\n  mov.s $f2,$f0\r\n  li $v0,0\r\n  jr $ra\r\n";
+
+#[test]
+fn formatting_doesnt_change_the_program() {
+    for source in [HELLO, LOOP, FUNCTIONS, CRLF_AND_FLOATS] {
+        for options in all_options() {
+            assert_round_trips(source, &options);
+        }
+    }
+}
+
+#[test]
+fn lines_up_columns() {
+    let formatted = assert_round_trips(HELLO, &FormatOptions::default());
+
+    assert_eq!(
+        formatted,
+        "\
+# prints hello world
+main:
+\tla\t$a0, msg\t# the string
+\tli\t$v0, 4
+\tsyscall
+
+\tli\t$v0, 0
+\tjr\t$ra
+
+\t.data
+msg:\t.asciiz\t\"hello, world\\n\"\t# with a comma in it
+"
+    );
+}
+
+#[test]
+fn respells_registers() {
+    let numbers = FormatOptions {
+        registers: RegisterStyle::Numbers,
+        use_tabs: false,
+    };
+    let formatted = assert_round_trips(LOOP, &numbers);
+    assert!(
+        formatted.contains("sw      $8, array($9)"),
+        "\n{}",
+        formatted
+    );
+
+    let names = FormatOptions {
+        registers: RegisterStyle::Names,
+        use_tabs: false,
+    };
+    let formatted = assert_round_trips(LOOP, &names);
+    assert!(
+        formatted.contains("sw      $t0, array($t1)"),
+        "\n{}",
+        formatted
+    );
+    assert!(formatted.contains("jr      $ra"), "\n{}", formatted);
+}
+
+#[test]
+fn uses_the_files_tab_size() {
+    let spaces = FormatOptions {
+        registers: RegisterStyle::Preserve,
+        use_tabs: false,
+    };
+    let formatted = assert_round_trips(FUNCTIONS, &spaces);
+
+    assert!(
+        formatted.contains("\n        sw      $ra, 4($sp)\n"),
+        "\n{}",
+        formatted
+    );
+    assert!(
+        formatted.contains("\nbase:   li      $v0, 1\n"),
+        "\n{}",
+        formatted
+    );
+}
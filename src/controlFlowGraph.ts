@@ -0,0 +1,43 @@
+import * as vscode from 'vscode';
+import { languageClient } from './lspClient';
+
+const LABEL_REGEX = /[A-Za-z_][A-Za-z0-9_.]*/;
+
+export function setupControlFlowGraphCommand(context: vscode.ExtensionContext) {
+    context.subscriptions.push(vscode.commands.registerCommand('mips.showControlFlowGraph', async () => {
+        const editor = vscode.window.activeTextEditor;
+        if (!editor) {
+            return;
+        }
+
+        const document = editor.document;
+        const wordRange = document.getWordRangeAtPosition(editor.selection.active, LABEL_REGEX);
+
+        const functionLabel = wordRange ? document.getText(wordRange) : await vscode.window.showInputBox({
+            prompt: 'Which function should the control flow graph be shown for?',
+            value: 'main'
+        });
+        if (!functionLabel) {
+            return;
+        }
+
+        const client = languageClient();
+        if (!client) {
+            return;
+        }
+
+        // the language server knows the other files a multi-file program is made of
+        const result: { dot?: string, error?: string } = await client.sendRequest('mips/controlFlowGraph', {
+            uri: document.uri.toString(),
+            functionLabel,
+        });
+
+        if (result.error !== undefined || result.dot === undefined) {
+            vscode.window.showErrorMessage(`Couldn't make a control flow graph: ${result.error}`);
+            return;
+        }
+
+        const doc = await vscode.workspace.openTextDocument({ language: 'dot', content: result.dot });
+        await vscode.window.showTextDocument(doc, { preview: false, viewColumn: vscode.ViewColumn.Beside });
+    }));
+}
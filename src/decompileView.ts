@@ -1,5 +1,6 @@
 import * as vscode from 'vscode';
 import { decompile_source } from '../mipsy_vscode/pkg/mipsy_vscode';
+import { programOptions } from './programOptions';
 
 export const DECOMPILE_SCHEME = 'mips-decompile';
 
@@ -26,7 +27,10 @@ export class DecompileView implements vscode.TextDocumentContentProvider {
         const filename = uri.path.split('/', 2)[1]?.replace('Decompiled: ', '') || 'mips.s';
 
         // TODO: make this call on the language server side
-        const decompiled = decompile_source(sourceDocument.getText(), filename);
+        const decompiled = decompile_source(
+            sourceDocument.getText(), filename,
+            programOptions(vscode.workspace.getConfiguration('mips', sourceDocument.uri))
+        );
         return `Decompilation of ${filename}:\n${decompiled}`.trimEnd() + '\n';
     }
 }
@@ -1,8 +1,9 @@
 import * as vscode from 'vscode';
 
+import { setupControlFlowGraphCommand } from './controlFlowGraph';
 import { setupDecompilationButton } from './decompileView';
 import { setupIOView } from './ioViewProvider';
-import { setupDebugButton, setupSendInputButton } from './launchDebug';
+import { setupDebugButton, setupLastWriteCommand, setupSendInputButton, setupStepOutBackCommand } from './launchDebug';
 import { deactivateClient, startLSP } from './lspClient';
 import { setupMemoryButton } from './memoryViewer';
 
@@ -12,10 +13,13 @@ export function activate(context: vscode.ExtensionContext) {
 
     startLSP(context);
     setupDecompilationButton(context);
+    setupControlFlowGraphCommand(context);
     setupDebugButton(context);
     setupMemoryButton(context);
     setupIOView(context);
     setupSendInputButton(context);
+    setupLastWriteCommand(context);
+    setupStepOutBackCommand(context);
 }
 
 export function deactivate() {
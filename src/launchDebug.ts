@@ -1,4 +1,34 @@
 import * as vscode from 'vscode';
+import { programOptions } from './programOptions';
+import { multifileNames } from './multifile';
+
+// the other files of a multi-file program, taken from the editor so that unsaved changes are included
+async function readOtherFiles(file: vscode.Uri) {
+    const document = await vscode.workspace.openTextDocument(file);
+    const otherFiles: { filename: string, source: string, path: string }[] = [];
+
+    for (const filename of new Set(multifileNames(document.getText()))) {
+        const uri = vscode.Uri.joinPath(file, '..', filename);
+        if (uri.toString() === file.toString()) {
+            continue;
+        }
+
+        let otherDocument;
+        try {
+            otherDocument = await vscode.workspace.openTextDocument(uri);
+        } catch {
+            throw new Error(`Couldn't open \`${filename}\`, which is part of this multi-file program`);
+        }
+
+        otherFiles.push({
+            filename,
+            source: otherDocument.getText(),
+            path: uri.scheme === 'file' ? uri.fsPath : uri.toString()
+        });
+    }
+
+    return otherFiles;
+}
 
 export function setupDebugButton(context: vscode.ExtensionContext) {
     context.subscriptions.push(vscode.commands.registerCommand('mips.debug',  async (file?: vscode.Uri) => {
@@ -13,6 +43,14 @@ export function setupDebugButton(context: vscode.ExtensionContext) {
             return;
         }
 
+        let otherFiles;
+        try {
+            otherFiles = await readOtherFiles(file);
+        } catch (err) {
+            vscode.window.showErrorMessage(`${err instanceof Error ? err.message : err}`);
+            return;
+        }
+
         let doCustomSourceSending = {};
         if (file.scheme !== 'file') {
             doCustomSourceSending = {
@@ -29,7 +67,9 @@ export function setupDebugButton(context: vscode.ExtensionContext) {
                 program: file,
                 programUri: file.toString(),
                 console: 'integratedTerminal',
-                doCustomSourceSending
+                doCustomSourceSending,
+                otherFiles,
+                programOptions: programOptions(vscode.workspace.getConfiguration('mips', file))
             },
             undefined
         );
@@ -109,3 +149,23 @@ export function setupSendInputButton(context: vscode.ExtensionContext) {
         });
     }));
 }
+
+export function setupLastWriteCommand(context: vscode.ExtensionContext) {
+    // from the variables view, this is given the register that was right clicked
+    context.subscriptions.push(vscode.commands.registerCommand('mipsy.debug.goBackToLastWrite', async (variableContext?: { variable?: { name: string } }) => {
+        const name = variableContext?.variable?.name ?? await vscode.window.showInputBox({
+            title: 'Go back to the last write of...',
+            prompt: 'A register (like $t0), or the address of a word of memory (like arr + 8)',
+        });
+
+        if (name) {
+            vscode.debug.activeDebugSession?.customRequest('lastWrite', { name });
+        }
+    }));
+}
+
+export function setupStepOutBackCommand(context: vscode.ExtensionContext) {
+    context.subscriptions.push(vscode.commands.registerCommand('mipsy.debug.stepOutBack', () => {
+        vscode.debug.activeDebugSession?.customRequest('stepOutBack');
+    }));
+}
@@ -43,6 +43,11 @@ export function startLSP(context: vscode.ExtensionContext) {
     client.start();
 }
 
+/** The running language client, once `startLSP` has been called. */
+export function languageClient(): LanguageClient | undefined {
+    return client;
+}
+
 export async function deactivateClient(): Promise<void> {
     if (client) {
         return await client.stop();
@@ -7,6 +7,7 @@ import {
 } from '@vscode/debugprotocol';
 import { make_new_runtime, DebugRuntime } from '../mipsy_vscode/pkg/mipsy_vscode';
 import { ScanBuffer } from './scanBuffer';
+import { ProgramOptions } from './programOptions';
 import * as fs from 'node:fs/promises';
 
 // const rand = Math.floor(Math.random() * 9000) + 1000;
@@ -14,17 +15,75 @@ import * as fs from 'node:fs/promises';
 const THREAD_ID = 1;
 const STEPS_PER_INTERVAL = 300;
 
+// where an instruction came from, with a 1-indexed line
+interface SourcePosition {
+    file_tag: string,
+    line: number,
+}
+
+// another file of a multi-file program, as sent by the extension
+interface OtherFile {
+    filename: string,
+    source: string,
+    path: string,
+}
+
+// whether a breakpoint could be set, from `set_breakpoints`
+interface BreakpointStatus {
+    line: number,
+    verified: boolean,
+    message: string | null,
+}
+
+// a change to a register or memory and the instruction which made it, from
+// `take_watchpoint_hit` and `step_back_to_last_write`
+interface ChangeReport {
+    pc: number,
+    instruction: string,
+    file_tag: string | null,
+    line: number | null,
+    description: string,
+}
+
+// what can be watched for a name, from `watchpoint_info`
+interface WatchpointInfo {
+    data_id: string | null,
+    description: string,
+    access_types: DebugProtocol.DataBreakpointAccessType[],
+}
+
+// a function which hasn't returned yet, from `get_call_stack`
+interface StackFrame {
+    function: string,
+    pc: number,
+    file_tag: string | null,
+    line: number | null,
+    call_site: number | null,
+    sp: number | null,
+    fp: number | null,
+}
+
+function describeChange(change: ChangeReport, mainFileTag: string) {
+    const where = change.line === null ? '' : ` on line ${change.line}` + (
+        change.file_tag && change.file_tag !== mainFileTag ? ` of ${change.file_tag}` : ''
+    );
+    return `${change.description}, by \`${change.instruction}\`${where}`;
+}
+
 class MipsRuntime {
     private readonly runtime: DebugRuntime;
     private autoRunning: boolean;
     public inputNeeded: boolean;
     private resumeOnInput: boolean;
+    // a step over or out which is waiting for input to a read syscall, to carry on with once it's entered
+    private pendingStepping?: () => any;
     public runningReverse: boolean;
     private isAtExit: boolean = false;
+    private stoppedAtWatchpoint: boolean = false;
 
-    constructor(readonly source: string, readonly filename: string, readonly path: string, readonly session: MipsSession, private readonly scanBuffer: ScanBuffer) {
+    constructor(readonly source: string, readonly filename: string, readonly path: string, readonly session: MipsSession, private readonly scanBuffer: ScanBuffer, otherFiles: OtherFile[], options?: ProgramOptions) {
         this.runtime = make_new_runtime(
-            source, filename
+            source, filename, otherFiles.map(({ filename, source }) => ({ filename, source })), options
         );
         this.autoRunning = false;
         this.inputNeeded = false;
@@ -38,7 +97,8 @@ class MipsRuntime {
         for (let i = 0; i < STEPS_PER_INTERVAL && this.autoRunning; ++i) {
             if (!this.runningReverse) {
                 if (!this.step()) {
-                    this.setAutorun(false, 'breakpoint');
+                    this.setAutorun(false, this.stoppedAtWatchpoint ? 'data breakpoint' : 'breakpoint');
+                    this.stoppedAtWatchpoint = false;
                 }
             } else {
                 if (!this.stepBack()) {
@@ -78,6 +138,8 @@ class MipsRuntime {
     }
 
     step(): boolean {
+        this.pendingStepping = undefined;
+
         if (this.isAtExit) {
             this.session.sendStdoutLine('exiting...');
             this.session.sendEvent(new TerminatedEvent());
@@ -85,7 +147,12 @@ class MipsRuntime {
             return false;
         }
 
-        const result = this.runtime.step_debug();
+        return this.handleStepResult(this.runtime.step_debug());
+    }
+
+    // deals with whatever stopped the runtime (like a syscall), returning whether it can keep going
+    handleStepResult(result: any): boolean {
+        this.sendLogMessages();
 
         if (result === 'StepSuccess') {
              return true;
@@ -94,6 +161,7 @@ class MipsRuntime {
 
             if (syscallGuard === 'print') {
                 const printResult = this.runtime.do_print();
+                this.sendLogMessages();
 
                 const match = /^([^:]*): (.*)$/s.exec(printResult) || [];
                 const printType = match[1];
@@ -125,6 +193,13 @@ class MipsRuntime {
                 return false;
             } else if (syscallGuard === 'breakpoint') {
                 this.runtime.acknowledge_breakpoint();
+
+                const watchpoint: ChangeReport | null = this.runtime.take_watchpoint_hit();
+                if (watchpoint) {
+                    this.session.sendStdoutLine(`[watchpoint] ${describeChange(watchpoint, this.filename)}`);
+                    this.stoppedAtWatchpoint = true;
+                }
+
                 return !this.autoRunning; // stop the autorun, but don't stop single stepping
             } else if (syscallGuard.startsWith('read_')) {
                 if (!this.scanBuffer.isExhausted) {
@@ -201,17 +276,112 @@ class MipsRuntime {
     stepBack() {
         this.isAtExit = false;
         this.inputNeeded = false;
+        this.pendingStepping = undefined;
         return this.runtime.step_back(this.autoRunning && this.runningReverse);
     }
 
-    setBreakpoints(lines: number[]) {
-        return Array.from(this.runtime.set_breakpoints_from_lines(
-            new Uint32Array(lines)
-        ));
+    // runs a step over or out (which happens in the runtime), dealing with any syscalls along the way
+    private runStepping(stepOnce: () => any) {
+        this.pendingStepping = undefined;
+
+        if (this.isAtExit) {
+            this.step();
+            return;
+        }
+
+        while (true) {
+            const result = stepOnce();
+            const syscallGuard = result === 'AtSyscallGuard' ? this.runtime.get_syscall_type() : undefined;
+            const canKeepGoing = this.handleStepResult(result);
+
+            if (canKeepGoing && result === 'AtSyscallGuard' && syscallGuard !== 'breakpoint') {
+                continue;
+            }
+
+            if (!canKeepGoing && this.inputNeeded) {
+                // the runtime is still stepping, so `provideInput` carries on from here
+                this.pendingStepping = stepOnce;
+            } else {
+                this.runtime.cancel_stepping();
+            }
+            return;
+        }
+    }
+
+    stepOver() {
+        this.runStepping(() => this.runtime.step_over());
     }
 
-    getLineNum(): number | undefined {
-        return this.runtime.get_line_num();
+    stepOut() {
+        this.runStepping(() => this.runtime.step_out());
+    }
+
+    stepOverBack() {
+        this.isAtExit = false;
+        this.inputNeeded = false;
+        this.pendingStepping = undefined;
+        this.runtime.step_over_back();
+    }
+
+    stepOutBack() {
+        this.isAtExit = false;
+        this.inputNeeded = false;
+        this.pendingStepping = undefined;
+        this.runtime.step_out_back();
+    }
+
+    // messages from logpoints (and conditions which couldn't be checked)
+    sendLogMessages() {
+        if (this.runtime.has_log_messages()) {
+            for (const message of this.runtime.take_log_messages()) {
+                this.session.sendLogpointLine(message);
+            }
+        }
+    }
+
+    setBreakpoints(fileTag: string, breakpoints: DebugProtocol.SourceBreakpoint[]): BreakpointStatus[] {
+        return this.runtime.set_breakpoints(
+            fileTag, breakpoints.map(({ line, condition, hitCondition, logMessage }) => ({
+                line, condition, hitCondition, logMessage
+            }))
+        );
+    }
+
+    watchpointInfo(name: string, bytes?: number): WatchpointInfo {
+        return this.runtime.watchpoint_info(name, bytes);
+    }
+
+    setWatchpoints(watchpoints: DebugProtocol.DataBreakpoint[]): { verified: boolean, message: string | null }[] {
+        return this.runtime.set_watchpoints(
+            watchpoints.map(({ dataId, accessType, condition, hitCondition }) => ({
+                dataId, accessType, condition, hitCondition
+            }))
+        );
+    }
+
+    // goes back to just before `name` (a register or address) last changed
+    stepBackToLastWrite(name: string, bytes?: number) {
+        this.isAtExit = false;
+        this.inputNeeded = false;
+        this.pendingStepping = undefined;
+
+        let change: ChangeReport | null;
+        try {
+            change = this.runtime.step_back_to_last_write(name, bytes);
+        } catch (err) {
+            this.session.sendStderrLine(`${err}`);
+            return;
+        }
+
+        if (change) {
+            this.session.sendStdoutLine(`[last write] ${describeChange(change, this.filename)}`);
+        } else {
+            this.session.sendStdoutLine(`[last write] \`${name}\` hasn't changed since the program started`);
+        }
+    }
+
+    getLineNum(): SourcePosition | undefined {
+        return this.runtime.get_line_num() ?? undefined;
     }
 
     readRegisters() {
@@ -268,6 +438,10 @@ class MipsRuntime {
         return this.runtime.get_pc();
     }
 
+    getCallStack(): StackFrame[] {
+        return this.runtime.get_call_stack();
+    }
+
     readMemory() {
         return Array.from(this.runtime.read_memory());
     }
@@ -279,21 +453,28 @@ class MipsRuntime {
         }
 
         const result = this.runtime.provide_input(input);
+        this.sendLogMessages();
 
         if (result === 'ok') {
             this.inputNeeded = false;
+            this.sendToIOView(input.trimEnd() + '\n', 'in');
+
+            const pendingStepping = this.pendingStepping;
+            this.pendingStepping = undefined;
 
             if (this.resumeOnInput) {
                 this.setAutorun(true, '');
                 this.session.sendEvent(new ContinuedEvent(THREAD_ID));
+            } else if (pendingStepping) {
+                this.session.sendEvent(new ContinuedEvent(THREAD_ID));
+                this.runStepping(pendingStepping);
+                this.session.sendEvent(new StoppedEvent('step', THREAD_ID));
             } else {
                 // this.session.sendEvent(new InvalidatedEvent(undefined, THREAD_ID));
                 this.session.sendEvent(new ContinuedEvent(THREAD_ID));
                 this.session.sendEvent(new StoppedEvent('step', THREAD_ID));
             }
 
-            this.sendToIOView(input.trimEnd() + '\n', 'in');
-
             return `syscall ${sycallType}: ${input}`;
         } else if (result) {
             return result;
@@ -305,6 +486,13 @@ class MipsRuntime {
     }
 }
 
+function splitLines(source: string) {
+    const splitter = source.indexOf('\r\n') === -1 ? '\n' : '\r\n';
+    return source.split(splitter).map(
+        line => line.replaceAll('\r', '').replaceAll('\n', '')
+    );
+}
+
 function numTo32BitHex(value: number) {
     return '0x' + value.toString(16).padStart(8, '0').toUpperCase();
 }
@@ -315,7 +503,8 @@ class MipsSession extends DebugSession {
     private source: string = '';
     private sourceName: string = '<source code>';
     private sourceLines: string[] = [];
-    private initialBreakpoints: (() => void) | undefined;
+    private otherFiles: OtherFile[] = [];
+    private initialBreakpoints: (() => void)[] = [];
     private isVSCode: boolean = false;
     private scanBuffer: ScanBuffer = new ScanBuffer();
     private delayedGotSource: (() => void) | undefined;
@@ -336,6 +525,10 @@ class MipsSession extends DebugSession {
 
         // vscode shows function breakpoints as verified even if we say we don't support them - so just add a handler which sends an error
         response.body.supportsFunctionBreakpoints = true;
+        response.body.supportsConditionalBreakpoints = true;
+        response.body.supportsHitConditionalBreakpoints = true;
+        response.body.supportsLogPoints = true;
+        response.body.supportsDataBreakpoints = true;
 		// response.body.supportsSteppingGranularity = true;
 		// response.body.supportsInstructionBreakpoints = true;
 
@@ -362,6 +555,10 @@ class MipsSession extends DebugSession {
         this.sendEvent(new OutputEvent(`${str}\n`, 'stdout'));
     }
 
+    sendLogpointLine(str: string) {
+        this.sendEvent(new OutputEvent(`${str}\n`, 'console'));
+    }
+
     sendStderrLine(str: string) {
         this.sendEvent(new OutputEvent(`${str}\n`, 'stderr'));
     }
@@ -371,7 +568,16 @@ class MipsSession extends DebugSession {
         this.sendEvent(new OutputEvent(`${str}\n`, 'important'));
     }
 
-    getSource() {
+    getSource(fileTag?: string) {
+        const otherFile = this.otherFiles.find(file => file.filename === fileTag);
+        if (fileTag !== this.sourceName && otherFile) {
+            return {
+                name: otherFile.filename,
+                path: otherFile.path,
+                sourceReference: 0
+            };
+        }
+
         return {
             name: this.sourceName,
             path: this.sourceFilePath,
@@ -379,6 +585,20 @@ class MipsSession extends DebugSession {
         };
     }
 
+    // the file tag of a source the editor refers to, and its lines
+    fileOfPath(path: string | undefined): { fileTag: string, lines: string[] } | undefined {
+        if (path === undefined || path === this.sourceFilePath) {
+            return { fileTag: this.sourceName, lines: this.sourceLines };
+        }
+
+        const otherFile = this.otherFiles.find(file => file.path === path);
+        if (otherFile) {
+            return { fileTag: otherFile.filename, lines: splitLines(otherFile.source) };
+        }
+
+        return undefined;
+    }
+
     sendSource() {
         // no idea if this is necessary
         this.sendEvent(new LoadedSourceEvent(
@@ -394,23 +614,21 @@ class MipsSession extends DebugSession {
     }
 
     protected async launchRequest(response: DebugProtocol.LaunchResponse, args: any, request?: DebugProtocol.Request | undefined): Promise<void> {
+        this.otherFiles = args.otherFiles ?? [];
+
         const gotSource = () => {
-            const splitter = this.source.indexOf('\r\n') === -1 ? '\n' : '\r\n';
-            this.sourceLines = this.source.split(splitter).map(
-                line => line.replaceAll('\r', '').replaceAll('\n', '')
-            );
+            this.sourceLines = splitLines(this.source);
 
             try {
-                this.runtime = new MipsRuntime(this.source, this.sourceName, this.sourceFilePath, this, this.scanBuffer);
+                this.runtime = new MipsRuntime(this.source, this.sourceName, this.sourceFilePath, this, this.scanBuffer, this.otherFiles, args.programOptions);
             } catch (e) {
                 this.sendError('Error:\n' + e);
                 this.sendEvent(new TerminatedEvent());
                 return;
             }
 
-            if (this.initialBreakpoints) {
-                this.initialBreakpoints();
-            }
+            this.initialBreakpoints.forEach(handleBreakpoints => handleBreakpoints());
+            this.initialBreakpoints = [];
 
             this.sendResponse(response);
 
@@ -469,6 +687,32 @@ class MipsSession extends DebugSession {
         this.sendResponse(response);
     }
 
+    protected dataBreakpointInfoRequest(response: DebugProtocol.DataBreakpointInfoResponse, args: DebugProtocol.DataBreakpointInfoArguments, request?: DebugProtocol.Request): void {
+        // `bytes` is only in newer versions of the protocol
+        const bytes = (args as { bytes?: number }).bytes;
+        const info = this.runtime?.watchpointInfo(args.name, bytes);
+
+        response.body = {
+            dataId: info?.data_id ?? null,
+            description: info?.description ?? 'the program needs to be running to set a watchpoint',
+            accessTypes: info?.access_types,
+            canPersist: false,
+        };
+        this.sendResponse(response);
+    }
+
+    protected setDataBreakpointsRequest(response: DebugProtocol.SetDataBreakpointsResponse, args: DebugProtocol.SetDataBreakpointsArguments, request?: DebugProtocol.Request): void {
+        const statuses = this.runtime?.setWatchpoints(args.breakpoints);
+
+        response.body = {
+            breakpoints: args.breakpoints.map((_, index) => ({
+                verified: statuses?.[index].verified ?? false,
+                message: statuses?.[index].message ?? undefined,
+            }))
+        };
+        this.sendResponse(response);
+    }
+
     protected setInstructionBreakpointsRequest(response: DebugProtocol.SetInstructionBreakpointsResponse, args: DebugProtocol.SetInstructionBreakpointsArguments, request?: DebugProtocol.Request): void {
         this.sendResponse(response);
     }
@@ -493,21 +737,8 @@ class MipsSession extends DebugSession {
         this.runtime?.setAutorun(true, 'continue');
     }
 
-    performStepNext() {
-        if (this.runtime) {
-            const oldLine = this.runtime.getLineNum();
-            while (this.runtime.step()) {
-                const newLine = this.runtime.getLineNum();
-                // this.sendDebugLine(`old ${oldLine}, new ${newLine}, pc ${this.runtime.getPC()}`);
-                if (newLine !== oldLine && newLine !== undefined) {
-                    break;
-                }
-            }
-        }
-    }
-
     protected nextRequest(response: DebugProtocol.NextResponse, args: DebugProtocol.NextArguments, request?: DebugProtocol.Request | undefined): void {
-        this.performStepNext();
+        this.runtime?.stepOver();
         this.sendResponse(response);
         this.sendEvent(new StoppedEvent('step', THREAD_ID));
     }
@@ -528,29 +759,13 @@ class MipsSession extends DebugSession {
     }
 
     protected stepOutRequest(response: DebugProtocol.StepOutResponse, args: DebugProtocol.StepOutArguments, request?: DebugProtocol.Request | undefined): void {
-        if (this.runtime) {
-            const oldPc = this.runtime.getPC();
-            while (this.runtime.stepBack()) {
-                const newPc = this.runtime.getPC();
-                if (newPc !== oldPc && newPc !== undefined) {
-                    break;
-                }
-            }
-        }
+        this.runtime?.stepOut();
         this.sendResponse(response);
         this.sendEvent(new StoppedEvent('step', THREAD_ID));
     }
 
     protected stepBackRequest(response: DebugProtocol.StepBackResponse, args: DebugProtocol.StepBackArguments, request?: DebugProtocol.Request | undefined): void {
-        if (this.runtime) {
-            const oldLine = this.runtime.getLineNum();
-            while (this.runtime.stepBack()) {
-                const newLine = this.runtime.getLineNum();
-                if (newLine !== oldLine && newLine !== undefined) {
-                    break;
-                }
-            }
-        }
+        this.runtime?.stepOverBack();
         this.sendResponse(response);
         this.sendEvent(new StoppedEvent('step', THREAD_ID));
     }
@@ -589,16 +804,27 @@ class MipsSession extends DebugSession {
     }
 
     protected stackTraceRequest(response: DebugProtocol.StackTraceResponse, args: DebugProtocol.StackTraceArguments, request?: DebugProtocol.Request): void {
+        const frames = this.runtime?.getCallStack() ?? [];
+        const start = args.startFrame ?? 0;
+        const end = args.levels ? start + args.levels : frames.length;
+
         response.body = {
-            totalFrames: 1,
-            stackFrames: [{
-                id: 1,
-                name: '',
-                line: this.runtime?.getLineNum() || 0,
-                column: 1,
-                source: this.getSource(),
-                instructionPointerReference: numTo32BitHex(this.runtime?.getPC() || 0)
-            }]
+            totalFrames: frames.length,
+            stackFrames: frames.slice(start, end).map((frame, index) => {
+                const entry = frame.call_site === null ? '' : ` (called from ${numTo32BitHex(frame.call_site)}`
+                    + (frame.sp === null ? '' : `, $sp = ${numTo32BitHex(frame.sp >>> 0)}`)
+                    + (frame.fp === null ? '' : `, $fp = ${numTo32BitHex(frame.fp >>> 0)}`)
+                    + ')';
+
+                return {
+                    id: start + index + 1,
+                    name: frame.function + entry,
+                    line: frame.line || 0,
+                    column: 1,
+                    source: this.getSource(frame.file_tag ?? undefined),
+                    instructionPointerReference: numTo32BitHex(frame.pc),
+                };
+            }),
         };
         this.sendResponse(response);
     }
@@ -615,7 +841,7 @@ class MipsSession extends DebugSession {
                     endColumn: Number.MAX_SAFE_INTEGER,
                     line: part.line_num,
                     endLine: part.line_num,
-                    location: this.getSource(),
+                    location: this.getSource(part.file_tag),
                     instructionBytes: part.instruction_bytes,
                     symbol: part.symbols
                 };
@@ -649,11 +875,18 @@ class MipsSession extends DebugSession {
             breakpoint => breakpoint.line
         );
 
-        let linesWithActualBreakpoints: number[] = [];
-
         const handleBreakpoints = () => {
             if (this.runtime) {
-                const sourceLines = this.sourceLines;
+                const file = this.fileOfPath(args.source.path);
+                if (!file) {
+                    response.body = {
+                        breakpoints: breakpoints.map(() => ({ verified: false }))
+                    };
+                    this.sendResponse(response);
+                    return;
+                }
+
+                const sourceLines = file.lines;
 
                 breakpointLines = breakpointLines.map(line => {
                     while (true) {
@@ -673,18 +906,22 @@ class MipsSession extends DebugSession {
                     return line;
                 });
 
-                linesWithActualBreakpoints = this.runtime.setBreakpoints(breakpointLines);
+                const statuses = this.runtime.setBreakpoints(
+                    file.fileTag,
+                    breakpoints.map((breakpoint, index) => ({ ...breakpoint, line: breakpointLines[index] }))
+                );
                 response.body = {
-                    breakpoints: breakpoints.map((breakpoint, index) => ({
-                        verified: linesWithActualBreakpoints.includes(breakpointLines[index]),
-                        line: breakpointLines[index],
-                        endLine: breakpointLines[index],
+                    breakpoints: statuses.map(status => ({
+                        verified: status.verified,
+                        line: status.line,
+                        endLine: status.line,
+                        message: status.message ?? undefined,
                     }))
                 };
 
                 this.sendResponse(response);
             } else {
-                this.initialBreakpoints = handleBreakpoints;
+                this.initialBreakpoints.push(handleBreakpoints);
             }
         };
 
@@ -787,6 +1024,22 @@ class MipsSession extends DebugSession {
             }
 
             return;
+        } else if (command === 'lastWrite') {
+            this.sendResponse(response);
+
+            if (this.runtime) {
+                this.runtime.setAutorun(false, 'step');
+                this.runtime.stepBackToLastWrite(args.name, args.bytes);
+                this.sendEvent(new StoppedEvent('step', THREAD_ID));
+            }
+        } else if (command === 'stepOutBack') {
+            this.sendResponse(response);
+
+            if (this.runtime) {
+                this.runtime.setAutorun(false, 'step');
+                this.runtime.stepOutBack();
+                this.sendEvent(new StoppedEvent('step', THREAD_ID));
+            }
         } else if (command === 'mipsySource') {
             this.source = args.source;
             const gotSource = this.delayedGotSource;
@@ -0,0 +1,9 @@
+// a `# @[multifile(a.s, b.s)]` comment, naming the other files of a program
+export const MULTIFILE_REGEX = /#[^\n@]*@[ \t]*\[[ \t]*multifile[ \t]*\(([^)\n]*)\)[ \t]*\]/g;
+
+// the filenames from every multifile comment in a source file
+export function multifileNames(source: string): string[] {
+    return Array.from(source.matchAll(MULTIFILE_REGEX)).map(match => {
+        return match[1].split(',').map(s => s.trim()).filter(s => s !== '');
+    }).flat(1);
+}
@@ -0,0 +1,28 @@
+// how mipsy_vscode compiles (and runs) programs, like the options to the mipsy command line
+export interface ProgramOptions {
+    args: string[],
+    tab_size: number,
+    spim: boolean,
+}
+
+// the `mips.*` settings these come from
+export interface ProgramSettings {
+    programArguments: string[],
+    tabSize: number,
+    spim: boolean,
+}
+
+export const defaultProgramSettings: ProgramSettings = {
+    programArguments: [],
+    tabSize: 8,
+    spim: false,
+};
+
+export function programOptions(settings?: Partial<ProgramSettings>): ProgramOptions {
+    return {
+        args: settings?.programArguments ?? defaultProgramSettings.programArguments,
+        // mipsy_vscode wants a whole number, where 0 means the default
+        tab_size: Math.max(0, Math.trunc(settings?.tabSize ?? defaultProgramSettings.tabSize)),
+        spim: settings?.spim ?? defaultProgramSettings.spim,
+    };
+}
@@ -3,6 +3,7 @@ import {
     TextDocuments,
     Diagnostic,
     DiagnosticSeverity,
+    DiagnosticTag,
     ProposedFeatures,
     InitializeParams,
     DidChangeConfigurationNotification,
@@ -15,18 +16,48 @@ import {
     SymbolKind,
     DocumentSymbol,
     SymbolInformation,
-    MarkupKind
+    MarkupKind,
+    SignatureHelp,
+    ResponseError,
+    ErrorCodes,
+    TextEdit,
+    WorkspaceEdit,
+    InlayHint,
+    InlayHintKind,
+    CodeLens,
+    CodeLensRefreshRequest,
+    CallHierarchyItem,
+    CallHierarchyIncomingCall,
+    CallHierarchyOutgoingCall
 } from 'vscode-languageserver/node';
 
-import { test_compile } from '../mipsy_vscode/pkg/mipsy_vscode';
+import {
+    test_compile,
+    check_calling_convention,
+    get_symbols,
+    find_references,
+    rename_symbol,
+    semantic_tokens,
+    semantic_tokens_legend,
+    hover_instruction,
+    get_signature_help,
+    get_completions,
+    format_source,
+    get_inlay_hints,
+    get_assembled_lines,
+    get_outline,
+    get_call_graph,
+    get_control_flow_graph
+} from '../mipsy_vscode/pkg/mipsy_vscode';
 
 import {
-    Position,
     TextDocument,
 } from 'vscode-languageserver-textdocument';
 
 import { suggestions as staticSuggestions, hover_docs as staticHovers }  from './lsp_data.json';
 
+import { ProgramOptions, ProgramSettings, defaultProgramSettings, programOptions } from './programOptions';
+import { MULTIFILE_REGEX, multifileNames } from './multifile';
 import { fileURLToPath } from 'node:url';
 import { readFileSync } from 'node:fs';
 
@@ -36,11 +67,30 @@ const connection = createConnection(ProposedFeatures.all);
 const documents: TextDocuments<TextDocument> = new TextDocuments(TextDocument);
 const splitSources: {[uri: string]: string[]} = {};
 
+type MipsySymbolKind = 'text_label' | 'data_label' | 'constant';
+
+interface MipsySymbol {
+    name: string,
+    kind: MipsySymbolKind,
+    file_tag: string,
+    line: number,
+    col: number,
+    col_end: number,
+    address?: number,
+    value?: number,
+    docs: string,
+}
+
 interface Definition {
     type: 'label' | 'constant',
+    kind: MipsySymbolKind,
     line: number,
+    character: number,
+    endCharacter: number,
     identifier: string,
     sourceUri: string,
+    address?: number,
+    value?: number,
     docs: string,
 }
 
@@ -48,11 +98,15 @@ const cachedDefinitions: {[uri: string]: Definition[] | undefined} = {};
 
 
 let hasConfigurationCapability = false;
+let hasCodeLensRefreshCapability = false;
+
+const semanticTokensLegend: { token_types: string[], token_modifiers: string[] } = semantic_tokens_legend();
 
 connection.onInitialize((params: InitializeParams) => {
     const capabilities = params.capabilities;
 
     hasConfigurationCapability = !!capabilities?.workspace?.configuration;
+    hasCodeLensRefreshCapability = !!capabilities?.workspace?.codeLens?.refreshSupport;
     // console.log(capabilities.textDocument?.documentSymbol?.hierarchicalDocumentSymbolSupport);
     // capabilities.textDocument?.completion?.completionItem.
 
@@ -67,8 +121,26 @@ connection.onInitialize((params: InitializeParams) => {
                 // resolveProvider: true
             },
             definitionProvider: true,
+            signatureHelpProvider: {
+                triggerCharacters: [' ', ','],
+                retriggerCharacters: ['(']
+            },
+            referencesProvider: true,
+            renameProvider: true,
+            semanticTokensProvider: {
+                legend: {
+                    tokenTypes: semanticTokensLegend.token_types,
+                    tokenModifiers: semanticTokensLegend.token_modifiers
+                },
+                full: true
+            },
             documentSymbolProvider: true,
-            hoverProvider: true
+            hoverProvider: true,
+            documentFormattingProvider: true,
+            inlayHintProvider: true,
+            codeLensProvider: {},
+            foldingRangeProvider: true,
+            callHierarchyProvider: true
         }
     };
 
@@ -81,21 +153,34 @@ connection.onInitialized(() => {
     }
 });
 
-interface MipsSettings {
+interface MipsSettings extends ProgramSettings {
     maxDiagonstics: number;
+    formatRegisters: 'names' | 'numbers' | 'preserve';
+    showAssembledCode: boolean;
 }
 
 const defaultSettings: MipsSettings = {
-    maxDiagonstics: 3
+    ...defaultProgramSettings,
+    maxDiagonstics: 3,
+    formatRegisters: 'names',
+    showAssembledCode: false
 };
 const documentSettings: Map<string, Thenable<MipsSettings>> = new Map();
 
+// the options each document was last compiled with, so that requests which
+// can't wait for the settings still compile it the same way
+const documentProgramOptions: Map<string, ProgramOptions> = new Map();
+
 connection.onDidChangeConfiguration(change => {
     if (hasConfigurationCapability) {
         documentSettings.clear();
     }
 
     documents.all().forEach(validateTextDocument);
+
+    if (hasCodeLensRefreshCapability) {
+        connection.sendRequest(CodeLensRefreshRequest.type);
+    }
 });
 
 function getDocumentSettings(resource: string): Thenable<MipsSettings> {
@@ -127,8 +212,6 @@ function setFilenameOfUri(uri: string, newName: string) {
     return components.join('/');
 }
 
-const MULTIFILE_REGEX = /#[^\n@]*@[ \t]*\[[ \t]*multifile[ \t]*\(([^)\n]*)\)[ \t]*\]/g;
-
 function getMultifileSources(uri: string): string | { filename: string, source: string, uri: string }[] {
     const rootDocument = documents.get(uri);
     if (!rootDocument) {
@@ -142,9 +225,7 @@ function getMultifileSources(uri: string): string | { filename: string, source:
         uri
     }];
 
-    const allExtraFiles = Array.from(rootSource.matchAll(MULTIFILE_REGEX)).map(match => {
-        return match[1].split(',').map(s => s.trim()).filter(s => s !== '');
-    }).flat(1);
+    const allExtraFiles = multifileNames(rootSource);
 
     const unavailableFiles: string[] = [];
     const allUris: string[] = [];
@@ -209,6 +290,7 @@ documents.onDidClose(e => {
     delete splitSources[e.document.uri];
     delete cachedDefinitions[e.document.uri];
     delete multiFileDependencies[e.document.uri];
+    documentProgramOptions.delete(e.document.uri);
 
     connection.sendDiagnostics({
         uri: e.document.uri,
@@ -271,15 +353,20 @@ async function validateTextDocument(textDocument: TextDocument): Promise<void> {
     }
 
     const settings = await getDocumentSettings(textDocument.uri);
-    const recheckAmt = settings?.maxDiagonstics || 3;
+    const maxDiagnostics = settings?.maxDiagonstics ?? defaultSettings.maxDiagonstics;
+    const options = programOptions(settings);
+    documentProgramOptions.set(textDocument.uri, options);
 
     const source = textDocument.getText();
 
     const diagnostics: Diagnostic[] = [];
 
-    const response = test_compile(source, getFilenameFromUri(textDocument.uri), multiFiles, recheckAmt, false);
+    const filename = getFilenameFromUri(textDocument.uri);
+    const response = test_compile(source, filename, multiFiles, maxDiagnostics, false, options);
+    const conventionWarnings = check_calling_convention(source, filename, multiFiles, false, options);
+    const errors = [...response.errors, ...conventionWarnings.errors].slice(0, maxDiagnostics);
 
-    let tabsSize = 8;
+    let tabsSize = options.tab_size;
     const tabSizeAttributeMatch = /#!\[[ \t]*tabsize[ \t]*\([ \t]*(\d{1,2})[ \t]*\)[ \t]*\]/.exec(source);
     if (tabSizeAttributeMatch && tabSizeAttributeMatch[1]) {
         tabsSize = parseInt(tabSizeAttributeMatch[1]) || tabsSize;
@@ -326,7 +413,7 @@ async function validateTextDocument(textDocument: TextDocument): Promise<void> {
 
     }
 
-    for (let err of response.errors) {
+    for (let err of errors) {
         // just stick non localised errors at the start
         let lineNum = err.localised ? err.line - 1 : 0;
 
@@ -350,7 +437,10 @@ async function validateTextDocument(textDocument: TextDocument): Promise<void> {
         }
 
         const diagnostic: Diagnostic = {
-            severity: err.is_warning ? DiagnosticSeverity.Warning : DiagnosticSeverity.Error,
+            severity: err.is_hint ? DiagnosticSeverity.Hint
+                : err.is_warning ? DiagnosticSeverity.Warning
+                : DiagnosticSeverity.Error,
+            tags: err.is_hint ? [DiagnosticTag.Unnecessary] : undefined,
             range: {
                 start: {
                     line: lineNum,
@@ -376,232 +466,123 @@ connection.onDidChangeWatchedFiles(_change => {
     connection.console.log('Received a file change event');
 });
 
-function getDefinitions(uri: string): Definition[] {
-    const cachedValue = cachedDefinitions[uri];
-    if (cachedValue) {
-        return cachedValue;
-    }
-
-    const lines = splitSources[uri] || [];
-    const definitions: Definition[] = [];
-
-    const allSources = [{
-        uri: uri, lines
-    }];
+// the sources of the program a document is part of, for the analyses in mipsy_vscode
+function getProgramSources(uri: string) {
+    const source = documents.get(uri)?.getText() ?? (splitSources[uri] || []).join('\n');
+    const filename = getFilenameFromUri(uri);
 
     const multiFile = getMultifileSources(uri);
-    if (typeof multiFile !== 'string') {
-        allSources.push(...multiFile.map(file => ({
-            uri: file.uri,
-            lines: splitSourceIntoLines(file.source)
-        })));
-    }
-
-    allSources.forEach(({uri, lines}) => {
-        let docsLines: string[] = [];
+    const otherFiles = typeof multiFile === 'string' ? [] : multiFile;
 
-        lines.forEach((line, lineNum) => {
-            let lineIter = line;
-            const docs = docsLines.join('\n').trim();
+    const fileUris: {[filename: string]: string} = { [filename]: uri };
+    otherFiles.forEach(file => {
+        fileUris[file.filename] = file.uri;
+    });
 
-            let definitionsOnThisLine: Definition[] = [];
+    const options = documentProgramOptions.get(uri) ?? programOptions();
 
-            function addDefinition(definition: Definition) {
-                definitionsOnThisLine.push(definition);
-                definitions.push(definition);
-            }
+    return { source, filename, otherFiles, fileUris, options };
+}
 
-            while (true) {
-                const match = /^[ \t]*([a-zA-Z_0-9.]+)[ \t]*:(.*)$/.exec(lineIter);
-                if (match === null) {
-                    break;
-                }
-                addDefinition({
-                    identifier: match[1],
-                    line: lineNum,
-                    type: 'label',
-                    sourceUri: uri,
-                    docs
-                });
-                lineIter = match[2];
-            }
+function getDefinitions(uri: string): Definition[] {
+    const cachedValue = cachedDefinitions[uri];
+    if (cachedValue) {
+        return cachedValue;
+    }
 
-            while (true) {
-                const match = /^[ \t]*([a-zA-Z_0-9.]+)[ \t]*=(.*)$/.exec(lineIter);
-                if (match === null) {
-                    break;
-                }
-                addDefinition({
-                    identifier: match[1],
-                    line: lineNum,
-                    type: 'constant',
-                    sourceUri: uri,
-                    docs
-                });
-                lineIter = match[2];
-            }
+    const { source, filename, otherFiles, fileUris, options } = getProgramSources(uri);
 
-            if (lineIter.trim() && definitionsOnThisLine.length > 0) {
-                definitionsOnThisLine.forEach(definition => {
-                    definition.docs = line.trim(); // lineIter.trim();
-                });
-            }
+    const table: { symbols: MipsySymbol[] } = get_symbols(source, filename, otherFiles, options);
 
-            const commentMatch = /^[ \t]*#[ \t]?(.*)$/.exec(line);
-            if (line.trim() === '') {
-                docsLines.push('');
-            } else if (commentMatch !== null) {
-                docsLines.push(commentMatch[1].trimEnd());
-            } else {
-                docsLines = [];
-            }
-        });
-    });
+    const definitions: Definition[] = table.symbols.map(symbol => ({
+        identifier: symbol.name,
+        type: symbol.kind === 'constant' ? 'constant' : 'label',
+        kind: symbol.kind,
+        line: symbol.line,
+        character: symbol.col,
+        endCharacter: symbol.col_end,
+        sourceUri: fileUris[symbol.file_tag] ?? uri,
+        address: symbol.address,
+        value: symbol.value,
+        docs: symbol.docs,
+    }));
 
     return (cachedDefinitions[uri] = definitions);
 }
 
+interface MipsyCompletion {
+    label: string,
+    kind: 'instruction' | 'pseudo_instruction' | 'directive' | 'register' | 'fpu_register' | 'label' | 'constant' | 'syscall',
+    detail?: string,
+    docs: string,
+    sort_text: string,
+    insert_text: string,
+    replace: [number, number],
+}
+
+const completionKinds: {[kind in MipsyCompletion['kind']]: CompletionItemKind} = {
+    instruction: CompletionItemKind.Function,
+    pseudo_instruction: CompletionItemKind.Function,
+    directive: CompletionItemKind.Keyword,
+    register: CompletionItemKind.Variable,
+    fpu_register: CompletionItemKind.Variable,
+    label: CompletionItemKind.Reference,
+    constant: CompletionItemKind.Constant,
+    syscall: CompletionItemKind.Event,
+};
+
 connection.onCompletion((textDocumentPosition: TextDocumentPositionParams): CompletionItem[] => {
     const lineNum = textDocumentPosition.position.line;
     const colNum = textDocumentPosition.position.character;
     const uri = textDocumentPosition.textDocument.uri;
-    const line = (splitSources[uri] || [])[lineNum] || '';
-    const definitions = getDefinitions(uri);
-
-    const before = line.slice(0, colNum);
-    const after = line.slice(colNum);
+    const { source, filename, otherFiles, options } = getProgramSources(uri);
 
-    if (before.indexOf('#') !== -1) {
-        // probably in comment - although the # might be in a string/character literal
-        return [];
-    }
-
-    const beforeWord = (/\$?[a-zA-Z.0-9_]*$/.exec(before) || [''])[0] || '';
-    const beforeWithLabelsRemoved = before.replace(/[A-Za-z_][A-Za-z_0-9.]*[ \t]*:/g, '');
-
-    const isStartOfLine = /^[ \t]*$/.test(
-        beforeWithLabelsRemoved.slice(0, beforeWithLabelsRemoved.length - beforeWord.length)
+    const response: { completions: MipsyCompletion[] } = get_completions(
+        source, filename, otherFiles, filename, lineNum, colNum, options
     );
-    const isImmeadiateV0 = /^[ \t]*li[ \t]+\$v0[ \t]*,[ \t]*[0-9]{0,2}$/.test(beforeWithLabelsRemoved);
-
-    const allSuggestions = Array.from(staticSuggestions);
 
-    definitions.forEach(definition => {
-        allSuggestions.push({
-            label: definition.identifier,
-            type: definition.type,
-            docs: definition.docs
-        });
-    });
-
-    const result: CompletionItem[] = [];
-
-    allSuggestions.forEach(suggestion => {
-        if (!isStartOfLine && suggestion.type === 'instruction') {
-            return;
-        }
-
-        if (!isStartOfLine && suggestion.type === 'directive') {
-            return;
-        }
-
-        if (suggestion.type === 'syscall_num' && !isImmeadiateV0) {
-            return;
-        }
-
-        let sortLevel = 'c';
-
-        if (suggestion.type === 'register') {
-            sortLevel = 'd';
-        }
-
-        if (suggestion.type === 'register' && beforeWord.startsWith('$') && !isStartOfLine) {
-            sortLevel = 'a';
-        }
-
-        if (suggestion.type === 'directive' && beforeWord.startsWith('.') && isStartOfLine) {
-            sortLevel = 'a';
-        }
-
-        if (suggestion.type === 'directive' && !isStartOfLine) {
-            sortLevel = 'e';
+    // instruction descriptions still come from the generated static data
+    const instructionDocs: {[label: string]: string | undefined} = {};
+    staticSuggestions.forEach(suggestion => {
+        if (suggestion.type === 'instruction') {
+            instructionDocs[suggestion.label] = suggestion.docs;
         }
+    });
 
-        if (suggestion.type === 'syscall_num') {
-            sortLevel = `a${suggestion.syscall_common ? 'a' : 'b'}$`;
+    return response.completions.map(completion => {
+        let docs = completion.docs;
+        if (completion.kind === 'instruction' || completion.kind === 'pseudo_instruction') {
+            docs = instructionDocs[completion.label] || '';
         }
 
         const item: CompletionItem = {
-            label: suggestion.label,
-            kind: CompletionItemKind.Function,
-            filterText: suggestion.label,
-            sortText: `${sortLevel}${suggestion.sort_data || ''}${suggestion.label}`,
+            label: completion.label,
+            kind: completionKinds[completion.kind],
+            filterText: completion.label,
+            sortText: completion.sort_text,
+            textEdit: {
+                newText: completion.insert_text,
+                range: {
+                    start: { line: lineNum, character: completion.replace[0] },
+                    end: { line: lineNum, character: completion.replace[1] },
+                },
+            },
             data: {}
         };
 
-        if (suggestion.type === 'instruction') {
-            item.kind = CompletionItemKind.Function;
-        } else if (suggestion.type === 'constant') {
-            item.kind = CompletionItemKind.Constant;
-        } else if (suggestion.type === 'label') {
-            item.kind = CompletionItemKind.Reference;
-        } else if (suggestion.type === 'directive') {
-            item.kind = CompletionItemKind.Keyword;
-        } else if (suggestion.type === 'register') {
-            item.kind = CompletionItemKind.Variable;
-        } else if (suggestion.type === 'syscall_num') {
-            item.kind = CompletionItemKind.Event;
-        } else {
-            console.log(`Don't know what kind for ${suggestion.type}`);
-        }
-
-        if (suggestion.docs && suggestion.docs !== 'todo') {
+        if (docs && docs !== 'todo') {
             item.labelDetails = {
-                description: suggestion.docs
+                description: completion.detail ?? docs
             };
-            if (suggestion.type === 'syscall_num') {
-                item.documentation = `syscall: ${suggestion.docs}`;
-            } else {
-                item.documentation = suggestion.docs;
-            }
-        }
-
-        let matchingLength = 0;
-        while (matchingLength < Math.min(beforeWord.length, suggestion.label.length)) {
-            if (beforeWord[matchingLength] === suggestion.label[matchingLength]) {
-                matchingLength++;
-            } else {
-                break;
-            }
-        }
-
-        const appendTab = isStartOfLine && suggestion.autoIndent;
-
-        if (!/[a-zA-Z]/.test(suggestion.label[0])) {
-            const editPosition: Position = {
-                line: lineNum,
-                character: colNum
-            };
-            item.textEdit = {
-                newText: suggestion.label.slice(matchingLength) + (appendTab ? '\t' : ''),
-                range: {
-                    start: {
-                        line: lineNum,
-                        character: colNum - (beforeWord.length - matchingLength)
-                    },
-                    end: editPosition
-                },
+            item.documentation = docs;
+        } else if (completion.detail) {
+            item.labelDetails = {
+                description: completion.detail
             };
-        } else {
-            item.insertText = suggestion.label + (appendTab ? '\t' : '');
         }
 
-        result.push(item);
+        return item;
     });
-
-    // return [{label:"Text",kind:CompletionItemKind.Text},{label:"Method",kind:CompletionItemKind.Method},{label:"Function",kind:CompletionItemKind.Function},{label:"Constructor",kind:CompletionItemKind.Constructor},{label:"Field",kind:CompletionItemKind.Field},{label:"Variable",kind:CompletionItemKind.Variable},{label:"Class",kind:CompletionItemKind.Class},{label:"Interface",kind:CompletionItemKind.Interface},{label:"Module",kind:CompletionItemKind.Module},{label:"Property",kind:CompletionItemKind.Property},{label:"Unit",kind:CompletionItemKind.Unit},{label:"Value",kind:CompletionItemKind.Value},{label:"Enum",kind:CompletionItemKind.Enum},{label:"Keyword",kind:CompletionItemKind.Keyword},{label:"Snippet",kind:CompletionItemKind.Snippet},{label:"Color",kind:CompletionItemKind.Color},{label:"File",kind:CompletionItemKind.File},{label:"Reference",kind:CompletionItemKind.Reference},{label:"Folder",kind:CompletionItemKind.Folder},{label:"EnumMember",kind:CompletionItemKind.EnumMember},{label:"Constant",kind:CompletionItemKind.Constant},{label:"Struct",kind:CompletionItemKind.Struct},{label:"Event",kind:CompletionItemKind.Event},{label:"Operator",kind:CompletionItemKind.Operator},{label:"TypeParameter",kind:CompletionItemKind.TypeParameter}];
-
-    return result;
 });
 
 function getWordAtPosition(params: TextDocumentPositionParams) {
@@ -634,11 +615,11 @@ connection.onDefinition(params => {
                 range: {
                     start: {
                         line,
-                        character: 0
+                        character: definition.character
                     },
                     end: {
                         line,
-                        character: Number.MAX_SAFE_INTEGER
+                        character: definition.endCharacter
                     }
                 },
                 uri: definition.sourceUri
@@ -649,87 +630,426 @@ connection.onDefinition(params => {
     return result;
 });
 
-const MAX_INT32 = 2147483647;
+interface MipsySignatureHelp {
+    mnemonic: string,
+    forms: { label: string, operands: [number, number][], is_pseudo: boolean }[],
+    active_form: number,
+    active_operand: number,
+}
 
-connection.onDocumentSymbol(params => {
-    // TODO: support SymbolInformation when the lsp client doesn't understand DocumentSymbol
+connection.onSignatureHelp((params): SignatureHelp | null => {
+    const uri = params.textDocument.uri;
+    const line = (splitSources[uri] || [])[params.position.line] || '';
+    const options = documentProgramOptions.get(uri) ?? programOptions();
+    const help: MipsySignatureHelp | undefined = get_signature_help(line, params.position.character, options);
 
-    const symbols: DocumentSymbol[] = [];
+    if (!help) {
+        return null;
+    }
 
-    const definitions = getDefinitions(params.textDocument.uri);
+    const hovers: {[name: string]: { docs: string } | undefined} = staticHovers;
+    const docs = hovers[help.mnemonic]?.docs;
+
+    return {
+        signatures: help.forms.map(form => ({
+            label: form.label,
+            documentation: form.is_pseudo ? [docs, '(pseudo-instruction)'].filter(Boolean).join(' ') : docs,
+            parameters: form.operands.map(range => ({ label: range })),
+        })),
+        activeSignature: help.active_form,
+        activeParameter: help.active_operand,
+    };
+});
 
-    const stack: DocumentSymbol[] = [];
+interface MipsyReference {
+    file_tag: string,
+    line: number,
+    col: number,
+    col_end: number,
+    is_definition: boolean,
+}
 
-    definitions.forEach(definition => {
-        if (definition.sourceUri !== params.textDocument.uri) {
-            return;
-        }
+function referenceRange(reference: MipsyReference) {
+    return {
+        start: { line: reference.line, character: reference.col },
+        end: { line: reference.line, character: reference.col_end },
+    };
+}
+
+connection.onReferences(params => {
+    const uri = params.textDocument.uri;
+    const { source, filename, otherFiles, fileUris, options } = getProgramSources(uri);
 
-        const newSymbol = {
-            kind: definition.type === 'label' ? SymbolKind.Class : SymbolKind.Constant,
-            name: definition.identifier,
+    const result: { references: MipsyReference[] } = find_references(
+        source, filename, otherFiles, filename, params.position.line, params.position.character, options
+    );
+
+    return result.references
+        .filter(reference => params.context.includeDeclaration || !reference.is_definition)
+        .map(reference => Location.create(fileUris[reference.file_tag] ?? uri, referenceRange(reference)));
+});
+
+connection.onRenameRequest(params => {
+    const uri = params.textDocument.uri;
+    const { source, filename, otherFiles, fileUris, options } = getProgramSources(uri);
+
+    const result: { edits: MipsyReference[], error?: string } = rename_symbol(
+        source, filename, otherFiles, filename, params.position.line, params.position.character, params.newName,
+        options
+    );
+
+    if (result.error) {
+        return new ResponseError(ErrorCodes.InvalidRequest, result.error);
+    }
+
+    const changes: {[uri: string]: TextEdit[]} = {};
+    result.edits.forEach(edit => {
+        const editUri = fileUris[edit.file_tag] ?? uri;
+        (changes[editUri] ??= []).push(TextEdit.replace(referenceRange(edit), params.newName));
+    });
+
+    const workspaceEdit: WorkspaceEdit = { changes };
+    return workspaceEdit;
+});
+
+connection.languages.semanticTokens.on(params => {
+    const { source, filename, otherFiles, options } = getProgramSources(params.textDocument.uri);
+
+    return {
+        data: Array.from(semantic_tokens(source, filename, otherFiles, options))
+    };
+});
+
+interface MipsyInlayHint {
+    line: number,
+    col: number,
+    label: string,
+    kind: 'address' | 'value' | 'branch_offset',
+    tooltip: string,
+}
+
+connection.languages.inlayHint.on((params): InlayHint[] => {
+    const { source, filename, otherFiles, options } = getProgramSources(params.textDocument.uri);
+    const { start, end } = params.range;
+
+    const result: { hints: MipsyInlayHint[] } = get_inlay_hints(source, filename, otherFiles, filename, options);
+
+    return result.hints
+        .filter(hint => start.line <= hint.line && hint.line <= end.line)
+        .map(hint => ({
+            position: { line: hint.line, character: hint.col },
+            label: hint.label,
+            kind: hint.kind === 'value' ? InlayHintKind.Parameter : InlayHintKind.Type,
+            tooltip: hint.tooltip,
+            paddingLeft: true,
+        }));
+});
+
+interface MipsyAssembledLine {
+    line: number,
+    segment: 'text' | 'data' | 'ktext' | 'kdata',
+    address: number,
+    size: number,
+    words: number[],
+    bytes: (number | null)[],
+}
+
+connection.onCodeLens(async (params): Promise<CodeLens[]> => {
+    const settings = await getDocumentSettings(params.textDocument.uri);
+    if (!(settings?.showAssembledCode ?? defaultSettings.showAssembledCode)) {
+        return [];
+    }
+
+    const { source, filename, otherFiles, options } = getProgramSources(params.textDocument.uri);
+    const result: { lines: MipsyAssembledLine[] } = get_assembled_lines(source, filename, otherFiles, filename, options);
+
+    return result.lines.map(line => {
+        const contents = line.words.length
+            ? line.words.map(hex)
+            : line.bytes.map(byte => byte === null ? '??' : byte.toString(16).toUpperCase().padStart(2, '0'));
+        const truncated = line.bytes.length && line.bytes.length < line.size ? ' \u2026' : '';
+
+        return {
             range: {
-                start: { character: 0, line: definition.line },
-                end: { character: MAX_INT32, line: definition.line },
+                start: { line: line.line, character: 0 },
+                end: { line: line.line, character: 0 },
             },
-            selectionRange: {
-                start: { character: 0, line: definition.line },
-                end: { character: MAX_INT32, line: definition.line },
+            command: {
+                title: `${hex(line.address)}: ${contents.join(' ')}${truncated}`,
+                command: '',
             },
-            children: definition.type === 'label' ? [] : undefined, // kinda hacky
         };
+    });
+});
 
-        let wasPushedToStack = false;
+const MAX_INT32 = 2147483647;
 
-        while (stack.length) {
-            if (definition.type !== 'label') {
-                break;
-            }
+interface MipsyOutlineNode {
+    name: string,
+    kind: 'segment' | 'function' | 'label' | 'data_label' | 'constant',
+    line: number,
+    end_line: number,
+    col: number,
+    col_end: number,
+    children: MipsyOutlineNode[],
+}
 
-            if (definition.identifier.startsWith(stack[stack.length - 1].name + '__')) {
-                stack[stack.length - 1].children?.push(newSymbol);
-                wasPushedToStack = true;
-                break;
-            }
+interface MipsyOutline {
+    nodes: MipsyOutlineNode[],
+    folding_ranges: [number, number][],
+}
 
-            stack.pop();
-        }
+function getOutline(uri: string): MipsyOutline {
+    const { source, filename, otherFiles, options } = getProgramSources(uri);
+    return get_outline(source, filename, otherFiles, filename, options);
+}
 
-        if (!wasPushedToStack) {
-            symbols.push(newSymbol);
-        }
+const outlineSymbolKinds = {
+    segment: SymbolKind.Namespace,
+    function: SymbolKind.Function,
+    label: SymbolKind.Key,
+    data_label: SymbolKind.Variable,
+    constant: SymbolKind.Constant,
+};
 
-        if (definition.type === 'label') {
-            stack.push(newSymbol);
-        }
-    });
+function outlineToDocumentSymbol(node: MipsyOutlineNode): DocumentSymbol {
+    return {
+        kind: outlineSymbolKinds[node.kind],
+        name: node.name,
+        range: {
+            start: { character: 0, line: node.line },
+            end: { character: MAX_INT32, line: node.end_line },
+        },
+        selectionRange: {
+            start: { character: node.col, line: node.line },
+            end: { character: node.col_end, line: node.line },
+        },
+        children: node.children.map(outlineToDocumentSymbol),
+    };
+}
 
-    symbols.reverse();
+connection.onDocumentSymbol(params => {
+    // TODO: support SymbolInformation when the lsp client doesn't understand DocumentSymbol
 
-    function expandRanges(symbols: DocumentSymbol[], lastLine: number) {
-        symbols.forEach(symbol => {
-            if (symbol.children !== undefined) {
-                symbol.range.end.line = Math.max(symbol.selectionRange.end.line, lastLine);
+    return getOutline(params.textDocument.uri).nodes.map(outlineToDocumentSymbol);
+});
 
-                symbol.children.reverse();
-                expandRanges(symbol.children, lastLine);
-                symbol.children.reverse();
+connection.onFoldingRange(params => {
+    return getOutline(params.textDocument.uri).folding_ranges.map(([startLine, endLine]) => ({
+        startLine,
+        endLine,
+    }));
+});
 
-                lastLine = symbol.range.start.line - 1;
-            }
-        });
+interface MipsyCallGraphFunction {
+    name: string,
+    address: number,
+    file_tag?: string,
+    line: number,
+    col: number,
+    col_end: number,
+    is_recursive: boolean,
+    is_called: boolean,
+}
+
+interface MipsyCall {
+    caller: string,
+    callee?: string,
+    address: number,
+    file_tag?: string,
+    line: number,
+    col: number,
+    col_end: number,
+}
+
+function getCallGraph(uri: string) {
+    const { source, filename, otherFiles, fileUris, options } = getProgramSources(uri);
+    const graph: { functions: MipsyCallGraphFunction[], calls: MipsyCall[] } = get_call_graph(source, filename, otherFiles, options);
+
+    const uriOf = (fileTag?: string) => fileTag === undefined ? uri : (fileUris[fileTag] ?? uri);
+    return { ...graph, uriOf };
+}
+
+function callRange(location: { line: number, col: number, col_end: number }) {
+    return {
+        start: { line: location.line, character: location.col },
+        end: { line: location.line, character: location.col_end },
+    };
+}
+
+/** What the call graph knows about a function, kept on its call hierarchy item. */
+interface CallHierarchyData {
+    address: number,
+    isRecursive: boolean,
+    isCalled: boolean,
+}
+
+function callHierarchyItem(func: MipsyCallGraphFunction, uri: string): CallHierarchyItem {
+    const notes = [hex(func.address)];
+    if (func.is_recursive) {
+        notes.push('recursive');
+    }
+    if (!func.is_called) {
+        notes.push('never called');
+    }
+
+    const data: CallHierarchyData = {
+        address: func.address,
+        isRecursive: func.is_recursive,
+        isCalled: func.is_called,
+    };
+
+    return {
+        name: func.name,
+        kind: SymbolKind.Function,
+        detail: notes.join(', '),
+        uri,
+        range: callRange(func),
+        selectionRange: callRange(func),
+        data,
+    };
+}
+
+function unknownCallee(call: MipsyCall, uri: string): CallHierarchyItem {
+    return {
+        name: '(unknown)',
+        kind: SymbolKind.Null,
+        detail: 'called through a register, so it could be any function',
+        uri,
+        range: callRange(call),
+        selectionRange: callRange(call),
+    };
+}
+
+const inRange = (line: number, character: number, location: { line: number, col: number, col_end: number }) =>
+    location.line === line && location.col <= character && character <= location.col_end;
+
+connection.languages.callHierarchy.onPrepare((params): CallHierarchyItem[] | null => {
+    const uri = params.textDocument.uri;
+    const { line, character } = params.position;
+    const { functions, calls, uriOf } = getCallGraph(uri);
+
+    const definition = functions.find(func => uriOf(func.file_tag) === uri && inRange(line, character, func));
+    const call = calls.find(call => uriOf(call.file_tag) === uri && inRange(line, character, call));
+    const func = definition ?? functions.find(func => call?.callee !== undefined && func.name === call.callee);
+
+    if (func) {
+        return [callHierarchyItem(func, uriOf(func.file_tag))];
     }
+    if (call) {
+        return [unknownCallee(call, uriOf(call.file_tag))];
+    }
+    return null;
+});
+
+connection.languages.callHierarchy.onIncomingCalls((params): CallHierarchyIncomingCall[] => {
+    const { functions, calls, uriOf } = getCallGraph(params.item.uri);
 
-    expandRanges(symbols, splitSources[params.textDocument.uri].length);
+    return functions.flatMap(caller => {
+        const fromRanges = calls
+            .filter(call => call.caller === caller.name && call.callee === params.item.name)
+            .map(callRange);
 
-    symbols.reverse();
+        return fromRanges.length ? [{ from: callHierarchyItem(caller, uriOf(caller.file_tag)), fromRanges }] : [];
+    });
+});
+
+connection.languages.callHierarchy.onOutgoingCalls((params): CallHierarchyOutgoingCall[] => {
+    const { functions, calls, uriOf } = getCallGraph(params.item.uri);
+    const outgoing = calls.filter(call => call.caller === params.item.name);
+
+    const known = functions.flatMap(callee => {
+        const fromRanges = outgoing.filter(call => call.callee === callee.name).map(callRange);
+        return fromRanges.length ? [{ to: callHierarchyItem(callee, uriOf(callee.file_tag)), fromRanges }] : [];
+    });
+    const unknown = outgoing
+        .filter(call => call.callee === undefined)
+        .map(call => ({ to: unknownCallee(call, uriOf(call.file_tag)), fromRanges: [callRange(call)] }));
 
-    return symbols;
+    return [...known, ...unknown];
 });
 
+// sent by the client for the `mips.showControlFlowGraph` command
+connection.onRequest('mips/controlFlowGraph', (params: { uri: string, functionLabel: string }) => {
+    const { source, filename, otherFiles, options } = getProgramSources(params.uri);
+    const result: { dot?: string, error?: string } = get_control_flow_graph(
+        source, filename, otherFiles, params.functionLabel, options
+    );
+
+    return { dot: result.dot, error: result.error };
+});
+
+interface InstructionHover {
+    mnemonic: string,
+    is_pseudo: boolean,
+    description?: string,
+    forms: { label: string }[],
+    assembled: {
+        address: number,
+        word: number,
+        assembly: string,
+        format: string,
+        fields: { name: string, value: number, bits: number, meaning?: string }[],
+    }[],
+}
+
+function hex(value: number) {
+    return '0x' + (value >>> 0).toString(16).toUpperCase().padStart(8, '0');
+}
+
+function instructionHoverMarkdown(hover: InstructionHover, docs: string | undefined) {
+    const parts: string[] = [];
+
+    const description = hover.description ?? docs;
+    if (description) {
+        parts.push(description);
+    }
+
+    if (!hover.assembled.length) {
+        // the program doesn't compile, so all we can show is how it's written
+        if (hover.is_pseudo) {
+            parts.push(`\`${hover.mnemonic}\` is a pseudo-instruction.`);
+        }
+        if (hover.forms.length) {
+            parts.push('```mips\n' + hover.forms.map(form => form.label).join('\n') + '\n```');
+        }
+    } else if (hover.is_pseudo) {
+        parts.push(`\`${hover.mnemonic}\` is a pseudo-instruction, which expands to:`);
+        parts.push('```mips\n' + hover.assembled.map(inst => inst.assembly).join('\n') + '\n```');
+    } else {
+        hover.assembled.forEach(inst => {
+            const fields = inst.fields;
+            parts.push(`${inst.format}-format instruction, which assembles to \`${hex(inst.word)}\``);
+            parts.push([
+                '| ' + fields.map(field => field.name).join(' | ') + ' |',
+                '|' + fields.map(() => ':-:|').join(''),
+                '| ' + fields.map(field => '`' + field.value.toString(2).padStart(field.bits, '0') + '`').join(' | ') + ' |',
+                '| ' + fields.map(field => field.meaning ?? `${field.value}`).join(' | ') + ' |',
+            ].join('\n'));
+        });
+    }
+
+    return parts.join('\n\n');
+}
+
 connection.onHover(params => {
     const word = getWordAtPosition(params);
 
+    const { source, filename, otherFiles, options } = getProgramSources(params.textDocument.uri);
+    const instructionHover: InstructionHover | undefined = hover_instruction(
+        source, filename, otherFiles, filename, params.position.line, params.position.character, options
+    );
+
+    if (instructionHover) {
+        const staticDocs: {[name: string]: { docs: string } | undefined} = staticHovers;
+        return {
+            contents: {
+                kind: MarkupKind.Markdown,
+                value: instructionHoverMarkdown(instructionHover, staticDocs[instructionHover.mnemonic]?.docs)
+            }
+        };
+    }
+
     const hovers: {[name: string]: { docs: string }} = staticHovers;
 
     getDefinitions(params.textDocument.uri).forEach(definition => {
@@ -754,6 +1074,29 @@ connection.onHover(params => {
     return null;
 });
 
+connection.onDocumentFormatting(async (params): Promise<TextEdit[]> => {
+    const document = documents.get(params.textDocument.uri);
+    if (!document) {
+        return [];
+    }
+
+    const settings = await getDocumentSettings(params.textDocument.uri);
+    const source = document.getText();
+    const { filename, options } = getProgramSources(params.textDocument.uri);
+    const formatted: string = format_source(source, filename, {
+        registers: settings?.formatRegisters ?? defaultSettings.formatRegisters,
+        use_tabs: !params.options.insertSpaces,
+    }, options);
+
+    if (formatted === source) {
+        return [];
+    }
+
+    return [TextEdit.replace({
+        start: { line: 0, character: 0 },
+        end: document.positionAt(source.length),
+    }, formatted)];
+});
 
 documents.listen(connection);
 